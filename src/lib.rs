@@ -4,6 +4,40 @@ pub fn add(left: u64, right: u64) -> u64 {
 
 pub mod mcp;
 
+pub use mcp::schema;
+pub use mcp::server::error::{self, ApiError, Result};
+pub use mcp::server::{
+    supported_methods, supported_notifications, Server, ServerBuilder, ServerHandle,
+};
+
+/// Everything needed to build and run a server
+///
+/// ```
+/// use rust_mcp::prelude::*;
+///
+/// let server: ServerHandle = std::sync::Arc::new(ServerBuilder::new("calculator", "0.1.0").build());
+/// let tool: schema::Tool = serde_json::from_value(serde_json::json!({
+///     "name": "add",
+///     "inputSchema": { "type": "object", "required": [] }
+/// }))
+/// .unwrap();
+///
+/// server
+///     .register_tool(tool, |_| async { Ok(schema::CallToolResult::text("3")) })
+///     .unwrap();
+/// assert_eq!(server.info().name, "calculator");
+/// ```
+pub mod prelude {
+    // Result is left out so it doesn't shadow std's in glob imports
+    pub use crate::mcp::schema;
+    pub use crate::mcp::server::error::ApiError;
+    pub use crate::mcp::server::{
+        NotificationDropPolicy, OutboundProgress, ProgressReporter, PromptTemplate, RequestContext,
+        ResourceChunk, Server, ServerBuilder, ServerHandle, ToolArgument, ToolOverflowPolicy,
+    };
+    pub use crate::tool;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,9 +1,12 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     collections::HashMap,
     fmt::{self, Display},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -14,12 +17,62 @@ pub enum JSONRPCMessage {
     Response(JSONRPCResponse),
 }
 
+impl JSONRPCMessage {
+    /// The `jsonrpc` field, whichever kind of message this is
+    pub fn json_rpc(&self) -> &str {
+        match self {
+            Self::Request(req) => &req.json_rpc,
+            Self::Notification(notification) => &notification.json_rpc,
+            Self::Response(JSONRPCResponse::Result(res)) => &res.json_rpc,
+            Self::Response(JSONRPCResponse::Error(err)) => &err.json_rpc,
+        }
+    }
+
+    /// Sets the `jsonrpc` field to [`JSONRPC_VERSION`]
+    pub fn set_json_rpc_version(&mut self) {
+        let json_rpc = match self {
+            Self::Request(req) => &mut req.json_rpc,
+            Self::Notification(notification) => &mut notification.json_rpc,
+            Self::Response(JSONRPCResponse::Result(res)) => &mut res.json_rpc,
+            Self::Response(JSONRPCResponse::Error(err)) => &mut err.json_rpc,
+        };
+        JSONRPC_VERSION.clone_into(json_rpc);
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum ProtocolVersion {
     #[serde(rename = "2024-11-05")]
     #[default]
     Mcp2024_11_05,
+    #[serde(rename = "2025-03-26")]
+    Mcp2025_03_26,
+    /// Version this crate does not know, kept so initialize can still negotiate
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+const SUPPORTED_PROTOCOL_VERSIONS: &[ProtocolVersion] = &[
+    ProtocolVersion::Mcp2024_11_05,
+    ProtocolVersion::Mcp2025_03_26,
+];
+
+impl ProtocolVersion {
+    /// Versions the server can speak, oldest first
+    pub fn supported_versions() -> &'static [ProtocolVersion] {
+        SUPPORTED_PROTOCOL_VERSIONS
+    }
+
+    /// Version to answer an initialize with. The client's version is kept when supported,
+    /// otherwise the server proposes its latest and the client decides whether to continue
+    pub fn negotiate(requested: &ProtocolVersion) -> ProtocolVersion {
+        if Self::supported_versions().contains(requested) {
+            requested.to_owned()
+        } else {
+            LATEST_PROTOCOL_VERSION.to_owned()
+        }
+    }
 }
 
 // impl Display for ProtocolVersion {
@@ -34,29 +87,39 @@ pub enum ProtocolVersion {
 //     }
 // }
 
-pub const LATEST_PROTOCOL_VERSION: &ProtocolVersion = &ProtocolVersion::Mcp2024_11_05;
+pub const LATEST_PROTOCOL_VERSION: &ProtocolVersion = &ProtocolVersion::Mcp2025_03_26;
 
 pub const JSONRPC_VERSION: &str = "2.0";
 
 // TODO see where to implement _meta for request and result types
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// Strict clients treat `"_meta": {}` differently from an absent key, so an empty map is omitted
+fn is_empty_meta(meta: &Option<HashMap<String, Value>>) -> bool {
+    meta.as_ref().is_none_or(HashMap::is_empty)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(untagged)]
 pub enum ProgressToken {
     String(String),
     Number(i64),
+    /// Numbers past `i64::MAX`, see [`RequestId::Unsigned`]
+    Unsigned(u64),
 }
 
 pub type Cursor = String;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestBaseMeta {
     #[serde(skip_serializing_if = "Option::is_none")]
-    progress_token: Option<ProgressToken>,
+    pub progress_token: Option<ProgressToken>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestBaseParams {
     #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
@@ -66,10 +129,47 @@ pub struct RequestBaseParams {
     pub extra: HashMap<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+impl RequestBaseParams {
+    /// Base params asking the receiver to report progress with `token`
+    pub fn with_progress_token(token: ProgressToken) -> Self {
+        Self {
+            meta: Some(RequestBaseMeta {
+                progress_token: Some(token),
+                extra: HashMap::new(),
+            }),
+            extra: HashMap::new(),
+        }
+    }
+
+    pub fn progress_token(&self) -> Option<&ProgressToken> {
+        self.meta.as_ref()?.progress_token.as_ref()
+    }
+
+    /// Looks up a non standard `_meta` entry
+    pub fn meta_field(&self, key: &str) -> Option<&Value> {
+        self.meta.as_ref()?.extra.get(key)
+    }
+
+    /// How long the client is willing to wait, from `_meta.timeoutMs` or an absolute
+    /// `_meta.deadline` in milliseconds since the unix epoch. A passed deadline gives zero
+    pub fn timeout_hint(&self) -> Option<Duration> {
+        if let Some(timeout) = self.meta_field("timeoutMs").and_then(Value::as_u64) {
+            return Some(Duration::from_millis(timeout));
+        }
+
+        let deadline = UNIX_EPOCH + Duration::from_millis(self.meta_field("deadline")?.as_u64()?);
+        Some(
+            deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct NotificationBaseParams {
-    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "_meta", skip_serializing_if = "is_empty_meta")]
     pub meta: Option<HashMap<String, Value>>,
 
     #[serde(flatten)]
@@ -79,7 +179,7 @@ pub struct NotificationBaseParams {
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ResultBase {
-    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "_meta", skip_serializing_if = "is_empty_meta")]
     pub meta: Option<HashMap<String, Value>>,
 
     #[serde(flatten)]
@@ -96,17 +196,48 @@ pub struct Result {
     pub defined_fields: ResultEnum,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// Id of a request, echoed back exactly as the client sent it: strings stay strings and
+/// numbers stay numbers. Integers from `i64::MIN` to `u64::MAX` are supported, other numbers,
+/// e.g. fractional ones, are rejected as invalid requests
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(untagged)]
 pub enum RequestId {
     String(String),
     Number(i64),
+    /// Numbers past `i64::MAX`, smaller ones are always [`RequestId::Number`]
+    Unsigned(u64),
+    /// `null`, the id of errors for messages whose id could not be read, e.g. unparseable ones.
+    /// Only valid in responses, requests with it are rejected as invalid
+    Null,
+}
+
+impl RequestId {
+    /// Canonical string form for correlating requests. String ids are quoted so that
+    /// `Number(5)` and `String("5")` never collide
+    pub fn key(&self) -> String {
+        match self {
+            Self::String(val) => format!("{val:?}"),
+            other => other.to_string(),
+        }
+    }
+}
+
+// Bare value for logs and messages, `Number(5)` and `String("5")` both display as `5`
+impl Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(val) => write!(f, "{val}"),
+            Self::Number(num) => write!(f, "{num}"),
+            Self::Unsigned(num) => write!(f, "{num}"),
+            Self::Null => write!(f, "null"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCRequest {
-    #[serde(flatten)]
+    #[serde(flatten, deserialize_with = "deserialize_request_params")]
     pub params: RequestParams,
     #[serde(rename = "jsonrpc")]
     pub json_rpc: String,
@@ -116,12 +247,46 @@ pub struct JSONRPCRequest {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCNotification {
-    #[serde(flatten)]
+    #[serde(flatten, deserialize_with = "deserialize_notification_params")]
     pub params: NotificationParams,
     #[serde(rename = "jsonrpc")]
     pub json_rpc: String,
 }
 
+// Spec methods without required params (e.g. ping) may omit the key or send null, as the
+// examples in the spec do. Custom methods keep their params as sent
+fn deserialize_request_params<'de, D>(
+    deserializer: D,
+) -> std::result::Result<RequestParams, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut map = serde_json::Map::deserialize(deserializer)?;
+    let is_spec_method = map
+        .get("method")
+        .and_then(Value::as_str)
+        .is_some_and(|method| REQUEST_METHODS.contains(&method));
+    if is_spec_method && map.get("params").is_none_or(Value::is_null) {
+        map.insert("params".into(), Value::Object(serde_json::Map::new()));
+    }
+
+    RequestParams::deserialize(Value::Object(map)).map_err(serde::de::Error::custom)
+}
+
+// Notifications without params (e.g. notifications/initialized) may omit the key entirely
+fn deserialize_notification_params<'de, D>(
+    deserializer: D,
+) -> std::result::Result<NotificationParams, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut map = serde_json::Map::deserialize(deserializer)?;
+    map.entry("params")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+
+    NotificationParams::deserialize(Value::Object(map)).map_err(serde::de::Error::custom)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCResult {
@@ -136,6 +301,8 @@ pub const INVALID_REQUEST: i64 = -32600;
 pub const METHOD_NOT_FOUND: i64 = -32601;
 pub const INVALID_PARAMS: i64 = -32602;
 pub const INTERNAL_ERROR: i64 = -32603;
+/// Implementation defined server error, the request can be retried later
+pub const SERVER_BUSY: i64 = -32000;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -165,6 +332,38 @@ impl Default for ErrorParams {
     }
 }
 
+/// Structured `data` for common errors, so clients get the same shape for the same problem
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ErrorData {
+    /// `{ "path": "options.size", "reason": "..." }`
+    Validation { path: String, reason: String },
+    /// `{ "supported": ["2024-11-05", ...] }`
+    UnsupportedVersion { supported: Vec<ProtocolVersion> },
+}
+
+impl ErrorData {
+    /// A value failed validation. `field` is the path to it, e.g. `options.size`
+    pub fn validation(field: &str, reason: &str) -> Self {
+        Self::Validation {
+            path: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// The requested protocol version is not one of `supported`
+    pub fn unsupported_version(supported: Vec<ProtocolVersion>) -> Self {
+        Self::UnsupportedVersion { supported }
+    }
+}
+
+impl From<ErrorData> for Value {
+    fn from(data: ErrorData) -> Self {
+        // Only strings and versions, which always serialize
+        serde_json::to_value(data).unwrap_or_default()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum JSONRPCResponse {
@@ -216,6 +415,44 @@ pub struct ClientCapabilities {
     pub roots: Option<RootCapabilities>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sampling: Option<HashMap<String, Value>>,
+
+    /// Keys outside the spec, kept so [`ClientCapabilities::validate`] can see them
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl ClientCapabilities {
+    /// Checks the capabilities are consistent, describing the first contradiction found.
+    /// Experimental capabilities may share a name with standard ones as long as they agree
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        // The flag belongs to `roots`, on its own it declares list changes for roots the
+        // client never advertised
+        if self.extra.contains_key("listChanged") {
+            return Err("listChanged is declared without roots".to_string());
+        }
+
+        for (name, value) in self.experimental.iter().flatten() {
+            if !value.is_object() {
+                return Err(format!("experimental capability {name} must be an object"));
+            }
+        }
+
+        let experimental_roots = self
+            .experimental
+            .as_ref()
+            .and_then(|experimental| experimental.get("roots"))
+            .and_then(|roots| roots.get("listChanged"));
+        if let Some(list_changed) = experimental_roots {
+            let declared = self.roots.as_ref().and_then(|roots| roots.list_changed);
+            if list_changed.as_bool() != Some(declared.unwrap_or_default()) {
+                return Err(
+                    "experimental roots.listChanged contradicts roots.listChanged".to_string(),
+                );
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -225,7 +462,7 @@ pub struct RootCapabilities {
     pub list_changed: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerCapabilities {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -240,6 +477,50 @@ pub struct ServerCapabilities {
     pub tools: Option<HashMap<String, Value>>,
 }
 
+impl ServerCapabilities {
+    /// Adds the capabilities of `other`, e.g. from another feature module. Flags set by either
+    /// side are kept, so `listChanged` is true if either has it. For other conflicting values
+    /// the existing one wins
+    pub fn merge(&mut self, other: &ServerCapabilities) {
+        for (capability, other) in [
+            (&mut self.experimental, &other.experimental),
+            (&mut self.logging, &other.logging),
+            (&mut self.prompts, &other.prompts),
+            (&mut self.resources, &other.resources),
+            (&mut self.tools, &other.tools),
+        ] {
+            if let Some(other) = other {
+                merge_capability(capability.get_or_insert_with(HashMap::new), other);
+            }
+        }
+    }
+}
+
+fn merge_capability(capability: &mut HashMap<String, Value>, other: &HashMap<String, Value>) {
+    for (key, other) in other {
+        match capability.get_mut(key) {
+            Some(value) => merge_value(value, other),
+            None => _ = capability.insert(key.clone(), other.clone()),
+        }
+    }
+}
+
+fn merge_value(value: &mut Value, other: &Value) {
+    match (value, other) {
+        (Value::Bool(value), Value::Bool(other)) => *value |= other,
+        (Value::Object(value), Value::Object(other)) => {
+            for (key, other) in other {
+                match value.get_mut(key) {
+                    Some(value) => merge_value(value, other),
+                    None => _ = value.insert(key.clone(), other.clone()),
+                }
+            }
+        }
+        (value @ Value::Null, other) => *value = other.clone(),
+        _ => (),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptCapabilities {
@@ -332,12 +613,15 @@ pub struct ListResourcesTemplateResult {
     #[serde(flatten)]
     // Composition with flattening to emulate schema inheritance
     pub paginated_base: PaginatedResult,
-    pub resources_templates: Vec<ResourceTemplate>,
+    pub resource_templates: Vec<ResourceTemplate>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ReadResourceRequestParams {
+    #[serde(flatten)]
+    pub request_base: RequestBaseParams,
+
     pub uri: String,
 }
 
@@ -347,6 +631,29 @@ pub struct ReadResourceResult {
     pub contents: Vec<ContentsResource>,
 }
 
+impl ReadResourceResult {
+    /// Result with a single text content
+    pub fn text(uri: &str, mime_type: Option<&str>, text: &str) -> Self {
+        Self {
+            contents: vec![ContentsResource::Text(TextResourceContents {
+                resource_contents_base: ResourceContents::new(uri, mime_type),
+                text: text.to_string(),
+            })],
+        }
+    }
+
+    /// Result with a single binary content, base64 encoded when serialized. Passing a `Vec`
+    /// keeps it without copying
+    pub fn blob(uri: &str, mime_type: Option<&str>, bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            contents: vec![ContentsResource::Blob(BlobResourceContents {
+                resource_contents_base: ResourceContents::new(uri, mime_type),
+                blob: Base64Bytes(bytes.into()),
+            })],
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase", untagged)]
 pub enum ContentsResource {
@@ -354,7 +661,73 @@ pub enum ContentsResource {
     Blob(BlobResourceContents),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// How [`ContentsResource::from_bytes_as`] wraps raw bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResourceEncoding {
+    /// Text for valid UTF-8 with a text-like or missing mime type, blob otherwise
+    #[default]
+    Auto,
+    /// Text whenever the bytes are valid UTF-8
+    Text,
+    Blob,
+}
+
+impl ContentsResource {
+    /// Wraps bytes as text or base64 blob depending on their mime type and whether they are
+    /// valid UTF-8
+    pub fn from_bytes(uri: &str, mime_type: Option<&str>, bytes: &[u8]) -> Self {
+        Self::from_bytes_as(uri, mime_type, bytes, ResourceEncoding::Auto)
+    }
+
+    /// Same as [`ContentsResource::from_bytes`] with an explicit encoding. Bytes that are not
+    /// valid UTF-8 are always sent as a blob
+    pub fn from_bytes_as(
+        uri: &str,
+        mime_type: Option<&str>,
+        bytes: &[u8],
+        encoding: ResourceEncoding,
+    ) -> Self {
+        let as_text = match encoding {
+            ResourceEncoding::Auto => mime_type.is_none_or(is_text_mime),
+            ResourceEncoding::Text => true,
+            ResourceEncoding::Blob => false,
+        };
+
+        match std::str::from_utf8(bytes) {
+            Ok(text) if as_text => ContentsResource::Text(TextResourceContents {
+                resource_contents_base: ResourceContents::new(uri, mime_type),
+                text: text.to_string(),
+            }),
+            _ => ContentsResource::Blob(BlobResourceContents {
+                resource_contents_base: ResourceContents::new(uri, mime_type),
+                blob: Base64Bytes(bytes.to_vec()),
+            }),
+        }
+    }
+}
+
+fn is_text_mime(mime_type: &str) -> bool {
+    let essence = mime_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/yaml"
+                | "application/toml"
+        )
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceListChangedNotificationParams {
     #[serde(flatten)]
@@ -397,40 +770,94 @@ pub struct Resource {
 #[serde(rename_all = "camelCase")]
 pub struct ResourceTemplate {
     #[serde(flatten)]
-    annotated_base: AnnotatedBase,
+    pub annotated_base: AnnotatedBase,
 
-    uri_template: String,
-    name: String,
+    pub uri_template: String,
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    mime_type: Option<String>,
+    pub mime_type: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceContents {
-    uri: String,
+    pub uri: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    mime_type: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+impl ResourceContents {
+    pub fn new(uri: &str, mime_type: Option<&str>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            mime_type: mime_type.map(String::from),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct TextResourceContents {
     #[serde(flatten)]
-    resource_contents_base: ResourceContents,
+    pub resource_contents_base: ResourceContents,
 
-    text: String,
+    pub text: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct BlobResourceContents {
     #[serde(flatten)]
-    resource_contents_base: ResourceContents,
+    pub resource_contents_base: ResourceContents,
 
-    blob: String,
+    pub blob: Base64Bytes,
+}
+
+/// Binary data that is base64 on the wire. Only the raw bytes are kept, they are encoded
+/// straight into the output when serialized
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Base64Bytes(pub Vec<u8>);
+
+impl Base64Bytes {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<Vec<u8>> for Base64Bytes {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl Serialize for Base64Bytes {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&base64::display::Base64Display::new(
+            &self.0,
+            &BASE64_STANDARD,
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for Base64Bytes {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64_STANDARD
+            .decode(encoded)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 // Prompts
@@ -446,45 +873,48 @@ pub struct ListPromptsRequestParams {
 #[serde(rename_all = "camelCase")]
 pub struct ListPromptsResult {
     #[serde(flatten)]
-    paginated_base: PaginatedResult,
+    pub paginated_base: PaginatedResult,
 
-    prompts: Vec<Prompt>,
+    pub prompts: Vec<Prompt>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct GetPromptRequestParams {
-    name: String,
+    #[serde(flatten)]
+    pub request_base: RequestBaseParams,
+
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    arguments: Option<HashMap<String, String>>,
+    pub arguments: Option<HashMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct GetPromptResult {
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    messages: Vec<PromptMessage>,
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Prompt {
-    name: String,
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    arguments: Option<Vec<PromptArgument>>,
+    pub arguments: Option<Vec<PromptArgument>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptArgument {
-    name: String,
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    required: Option<bool>,
+    pub required: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -494,11 +924,87 @@ pub enum Role {
     Assistant,
 }
 
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+// Same form as on the wire
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = UnknownRole;
+
+    /// Parses `user` or `assistant`, ignoring case
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("user") {
+            Ok(Role::User)
+        } else if s.eq_ignore_ascii_case("assistant") {
+            Ok(Role::Assistant)
+        } else {
+            Err(UnknownRole(s.to_string()))
+        }
+    }
+}
+
+/// Role string that is neither `user` nor `assistant`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRole(pub String);
+
+impl Display for UnknownRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown role: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRole {}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptMessage {
-    role: Role,
-    content: PromptMessageContent,
+    pub role: Role,
+    pub content: PromptMessageContent,
+}
+
+impl PromptMessage {
+    pub fn text(role: Role, text: &str) -> Self {
+        Self {
+            role,
+            content: PromptMessageContent::Text(TextContent::new(text)),
+        }
+    }
+
+    /// Message with an image, base64 encoded
+    pub fn image(role: Role, bytes: &[u8], mime_type: &str) -> Self {
+        Self {
+            role,
+            content: PromptMessageContent::Image(ImageContent::new(bytes, mime_type)),
+        }
+    }
+
+    /// Message with an audio clip, base64 encoded
+    pub fn audio(role: Role, bytes: &[u8], mime_type: &str) -> Self {
+        Self {
+            role,
+            content: PromptMessageContent::Audio(AudioContent::new(bytes, mime_type)),
+        }
+    }
+
+    /// Message embedding a resource, e.g. one of the contents of a [`ReadResourceResult`]
+    pub fn resource(role: Role, resource: impl Into<EmbeddedResourceEnum>) -> Self {
+        Self {
+            role,
+            content: PromptMessageContent::Embedded(EmbeddedResource::new(resource)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -506,6 +1012,8 @@ pub struct PromptMessage {
 pub enum PromptMessageContent {
     Text(TextContent),
     Image(ImageContent),
+    /// Only defined since protocol 2025-03-26, older clients may not understand it
+    Audio(AudioContent),
     #[serde(rename = "resource")]
     Embedded(EmbeddedResource),
 }
@@ -519,6 +1027,15 @@ pub struct EmbeddedResource {
     resource: EmbeddedResourceEnum,
 }
 
+impl EmbeddedResource {
+    pub fn new(resource: impl Into<EmbeddedResourceEnum>) -> Self {
+        Self {
+            annotated_base: AnnotatedBase { annotations: None },
+            resource: resource.into(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase", untagged)]
 pub enum EmbeddedResourceEnum {
@@ -526,7 +1043,26 @@ pub enum EmbeddedResourceEnum {
     Blob(BlobResourceContents),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+// Both enums wrap the same contents types, so a resource read can be embedded directly
+impl From<ContentsResource> for EmbeddedResourceEnum {
+    fn from(value: ContentsResource) -> Self {
+        match value {
+            ContentsResource::Text(x) => EmbeddedResourceEnum::Text(x),
+            ContentsResource::Blob(x) => EmbeddedResourceEnum::Blob(x),
+        }
+    }
+}
+
+impl From<EmbeddedResourceEnum> for ContentsResource {
+    fn from(value: EmbeddedResourceEnum) -> Self {
+        match value {
+            EmbeddedResourceEnum::Text(x) => ContentsResource::Text(x),
+            EmbeddedResourceEnum::Blob(x) => ContentsResource::Blob(x),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptListChangedNotificationParams {
     #[serde(flatten)]
@@ -546,16 +1082,107 @@ pub struct ListToolsRequestParams {
 #[serde(rename_all = "camelCase")]
 pub struct ListToolsResult {
     #[serde(flatten)]
-    paginated_base: PaginatedResult,
-    tools: Vec<Tool>,
+    pub paginated_base: PaginatedResult,
+    pub tools: Vec<Tool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CallToolResult {
-    content: Vec<CallToolContent>,
+    pub content: Vec<CallToolContent>,
+    // The spec treats a missing `isError` as false, but some clients don't, so the
+    // constructors always set it
     #[serde(skip_serializing_if = "Option::is_none")]
-    is_error: Option<bool>,
+    pub is_error: Option<bool>,
+    /// Result as JSON, matching the tool's `outputSchema` when it declares one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+}
+
+impl CallToolResult {
+    /// Successful result, serialized with `isError: false`
+    pub fn success(content: Vec<CallToolContent>) -> Self {
+        Self {
+            content,
+            is_error: Some(false),
+            structured_content: None,
+        }
+    }
+
+    /// Failed tool call. The error is reported to the model in `content` rather than as a
+    /// protocol error
+    pub fn error(content: Vec<CallToolContent>) -> Self {
+        Self {
+            content,
+            is_error: Some(true),
+            structured_content: None,
+        }
+    }
+
+    /// Successful result with a single text content
+    pub fn text(text: &str) -> Self {
+        Self::success(vec![CallToolContent::Text(TextContent::new(text))])
+    }
+
+    /// Builder for a result mixing content types, e.g. a summary and the resource it describes
+    pub fn builder() -> CallToolResultBuilder {
+        CallToolResultBuilder::default()
+    }
+}
+
+/// Appends content blocks to a [`CallToolResult`], kept in the order they were added
+#[derive(Debug, Clone, Default)]
+pub struct CallToolResultBuilder {
+    content: Vec<CallToolContent>,
+    is_error: bool,
+    structured_content: Option<Value>,
+}
+
+impl CallToolResultBuilder {
+    pub fn text(self, text: &str) -> Self {
+        self.content(CallToolContent::Text(TextContent::new(text)))
+    }
+
+    /// Image from raw bytes, base64 encoded
+    pub fn image(self, bytes: &[u8], mime_type: &str) -> Self {
+        self.content(CallToolContent::Image(ImageContent::new(bytes, mime_type)))
+    }
+
+    /// Audio from raw bytes, base64 encoded
+    pub fn audio(self, bytes: &[u8], mime_type: &str) -> Self {
+        self.content(CallToolContent::Audio(AudioContent::new(bytes, mime_type)))
+    }
+
+    pub fn embedded_resource(self, resource: impl Into<EmbeddedResourceEnum>) -> Self {
+        self.content(CallToolContent::Embedded(EmbeddedResource::new(resource)))
+    }
+
+    pub fn content(mut self, content: CallToolContent) -> Self {
+        self.content.push(content);
+        self
+    }
+
+    /// Flags the result as a failed tool call
+    pub fn error(mut self) -> Self {
+        self.is_error = true;
+        self
+    }
+
+    /// Sets `structuredContent`, for tools with an output schema
+    pub fn structured(mut self, content: Value) -> Self {
+        self.structured_content = Some(content);
+        self
+    }
+
+    pub fn build(self) -> CallToolResult {
+        let mut result = if self.is_error {
+            CallToolResult::error(self.content)
+        } else {
+            CallToolResult::success(self.content)
+        };
+        result.structured_content = self.structured_content;
+        result
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -563,6 +1190,8 @@ pub struct CallToolResult {
 pub enum CallToolContent {
     Text(TextContent),
     Image(ImageContent),
+    /// Only defined since protocol 2025-03-26, older clients may not understand it
+    Audio(AudioContent),
     #[serde(rename = "resource")]
     Embedded(EmbeddedResource),
 }
@@ -570,12 +1199,15 @@ pub enum CallToolContent {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CallToolRequestParams {
-    name: String,
+    #[serde(flatten)]
+    pub request_base: RequestBaseParams,
+
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    arguments: Option<HashMap<String, Value>>,
+    pub arguments: Option<HashMap<String, Value>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolListChangedNotificationParams {
     #[serde(flatten)]
@@ -585,18 +1217,22 @@ pub struct ToolListChangedNotificationParams {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Tool {
-    name: String,
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    input_schema: ToolInputSchemaType,
+    pub description: Option<String>,
+    pub input_schema: ToolInputSchemaType,
+    /// Shape of the `structuredContent` the tool returns. Not part of 2024-11-05, so only
+    /// serialized when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<ToolInputSchemaType>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolInputSchema {
     #[serde(skip_serializing_if = "Option::is_none")]
-    properties: Option<HashMap<String, Value>>,
-    required: Vec<String>,
+    pub properties: Option<HashMap<String, Value>>,
+    pub required: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -610,20 +1246,24 @@ pub enum ToolInputSchemaType {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct SetLevelRequestParams {
-    level: LoggingLevel,
+    pub level: LoggingLevel,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct LoggingMessageNotificationParams {
-    level: LoggingLevel,
+    #[serde(rename = "_meta", default, skip_serializing_if = "is_empty_meta")]
+    pub meta: Option<HashMap<String, Value>>,
+    pub level: LoggingLevel,
     #[serde(skip_serializing_if = "Option::is_none")]
-    logger: Option<String>,
-    data: Value, // TODO maybe Option<Value>
+    pub logger: Option<String>,
+    pub data: Value, // TODO maybe Option<Value>
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "camelCase", untagged)]
+/// Ordered by severity as in the spec, `Debug` lowest and `Emergency` highest. The derived
+/// ordering relies on the variants being declared in that order
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
 pub enum LoggingLevel {
     Debug,
     Info,
@@ -638,29 +1278,32 @@ pub enum LoggingLevel {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateMessageRequestParams {
-    messages: Vec<SamplingMessage>,
+    #[serde(flatten)]
+    pub request_base: RequestBaseParams,
+
+    pub messages: Vec<SamplingMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    model_preferences: Option<ModelPreferences>,
+    pub model_preferences: Option<ModelPreferences>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    system_prompt: Option<String>,
+    pub system_prompt: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<OrderedFloat<f32>>, // TODO maybe validate between 0 and 1
+    pub temperature: Option<OrderedFloat<f32>>, // TODO maybe validate between 0 and 1
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u64>,
+    pub max_tokens: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    stop_sequences: Option<Vec<String>>,
+    pub stop_sequences: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    metadata: Option<HashMap<String, Value>>,
+    pub metadata: Option<HashMap<String, Value>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateMessageResult {
     #[serde(flatten)]
-    sampling_message: SamplingMessage,
-    model: String,
+    pub sampling_message: SamplingMessage,
+    pub model: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    stop_reason: Option<StopReason>,
+    pub stop_reason: Option<StopReason>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -675,8 +1318,8 @@ pub enum StopReason {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct SamplingMessage {
-    role: Role,
-    content: SamplingMessageContent,
+    pub role: Role,
+    pub content: SamplingMessageContent,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -684,24 +1327,56 @@ pub struct SamplingMessage {
 pub enum SamplingMessageContent {
     Text(TextContent),
     Image(ImageContent),
+    /// Only defined since protocol 2025-03-26, older clients may not understand it
+    Audio(AudioContent),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct AnnotatedBase {
     #[serde(skip_serializing_if = "Option::is_none")]
-    annotations: Option<Annotations>,
+    pub annotations: Option<Annotations>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Annotations {
+    /// Who the content is meant for
     #[serde(skip_serializing_if = "Option::is_none")]
-    audience: Option<Vec<Role>>,
+    pub audience: Option<Vec<Role>>,
+    /// Importance from 0, optional, to 1, required
     #[serde(skip_serializing_if = "Option::is_none")]
-    priority: Option<i64>,
+    pub priority: Option<OrderedFloat<f32>>,
+}
+
+/// Adds `audience` and `priority` builder methods to content types with annotations
+macro_rules! impl_annotated {
+    ($($ty:ty),+) => {
+        $(
+            impl $ty {
+                pub fn audience(mut self, audience: &[Role]) -> Self {
+                    self.annotated_base
+                        .annotations
+                        .get_or_insert_with(Annotations::default)
+                        .audience = Some(audience.to_vec());
+                    self
+                }
+
+                /// Clamped to the 0 to 1 range
+                pub fn priority(mut self, priority: f32) -> Self {
+                    self.annotated_base
+                        .annotations
+                        .get_or_insert_with(Annotations::default)
+                        .priority = Some(OrderedFloat(priority.clamp(0.0, 1.0)));
+                    self
+                }
+            }
+        )+
+    };
 }
 
+impl_annotated!(TextContent, ImageContent, AudioContent, EmbeddedResource);
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct TextContent {
@@ -710,6 +1385,15 @@ pub struct TextContent {
     text: String,
 }
 
+impl TextContent {
+    pub fn new(text: &str) -> Self {
+        Self {
+            annotated_base: AnnotatedBase { annotations: None },
+            text: text.to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ImageContent {
@@ -719,6 +1403,38 @@ pub struct ImageContent {
     mime_type: String,
 }
 
+impl ImageContent {
+    /// Image content from raw bytes, base64 encoded
+    pub fn new(bytes: &[u8], mime_type: &str) -> Self {
+        Self {
+            annotated_base: AnnotatedBase { annotations: None },
+            data: BASE64_STANDARD.encode(bytes),
+            mime_type: mime_type.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioContent {
+    #[serde(flatten)]
+    annotated_base: AnnotatedBase,
+    data: String,
+    /// An `audio/*` type
+    mime_type: String,
+}
+
+impl AudioContent {
+    /// Audio content from raw bytes, base64 encoded
+    pub fn new(bytes: &[u8], mime_type: &str) -> Self {
+        Self {
+            annotated_base: AnnotatedBase { annotations: None },
+            data: BASE64_STANDARD.encode(bytes),
+            mime_type: mime_type.to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ModelPreferences {
@@ -741,11 +1457,14 @@ pub struct ModelHint {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CompleteRequestParams {
-    r#ref: CompleteRequestRef,
-    argument: CompleteRequestArgument,
+    #[serde(flatten)]
+    pub request_base: RequestBaseParams,
+
+    pub r#ref: CompleteRequestRef,
+    pub argument: CompleteRequestArgument,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub enum CompleteRequestRef {
     #[serde(rename = "ref/resource")]
@@ -757,18 +1476,18 @@ pub enum CompleteRequestRef {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CompleteRequestArgument {
-    name: String,
-    value: String,
+    pub name: String,
+    pub value: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CompleteResult {
-    values: Vec<String>,
+    pub values: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    total: Option<i64>,
+    pub total: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    has_more: Option<bool>,
+    pub has_more: Option<bool>,
 }
 
 // Roots
@@ -777,21 +1496,21 @@ pub struct CompleteResult {
 #[serde(rename_all = "camelCase")]
 pub struct ListRootsRequestParams {
     #[serde(flatten)]
-    request_base: RequestBaseParams,
+    pub request_base: RequestBaseParams,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ListRootResult {
-    roots: Vec<Root>,
+    pub roots: Vec<Root>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Root {
-    uri: String,
+    pub uri: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<String>,
+    pub name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -801,41 +1520,112 @@ pub struct RootsListChangedNotificationParams {
     notification_base: NotificationBaseParams,
 }
 
+/// Defines [`RequestParams`] and the method name lists from one table, so the wire names,
+/// [`RequestParams::method`] and [`REQUEST_METHODS`] can't drift apart
+macro_rules! request_params {
+    (
+        client { $($client:ident($client_params:ty) => $client_method:literal,)+ }
+        server { $($server:ident($server_params:ty) => $server_method:literal,)+ }
+    ) => {
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+        #[serde(tag = "method", content = "params")]
+        pub enum RequestParams {
+            $(
+                #[serde(rename = $client_method)]
+                $client($client_params),
+            )+
+            $(
+                #[serde(rename = $server_method)]
+                $server($server_params),
+            )+
+            /// Any method not defined by the spec, e.g. experimental extensions
+            #[serde(untagged)]
+            Custom(CustomRequestParams),
+        }
+
+        impl RequestParams {
+            /// Method name as sent on the wire
+            pub fn method(&self) -> &str {
+                match self {
+                    $(RequestParams::$client(_) => $client_method,)+
+                    $(RequestParams::$server(_) => $server_method,)+
+                    RequestParams::Custom(params) => &params.method,
+                }
+            }
+
+            /// Whether the spec only defines this request from server to client
+            pub fn is_server_request(&self) -> bool {
+                matches!(self, $(RequestParams::$server(_))|+)
+            }
+        }
+
+        /// Request methods defined by the spec. A request for one of these that parsed as
+        /// [`RequestParams::Custom`] had invalid params
+        pub const REQUEST_METHODS: &[&str] = &[$($client_method,)+ $($server_method,)+];
+
+        /// Request methods the spec defines from client to server
+        pub const CLIENT_REQUEST_METHODS: &[&str] = &[$($client_method,)+];
+    };
+}
+
+request_params! {
+    client {
+        Initialize(InitializeRequestParams) => "initialize",
+        Ping(PingRequestParams) => "ping",
+        ListResources(ListResourcesRequestParams) => "resources/list",
+        ListResourceTemplate(ListResourceTemplatesRequestParams) => "resources/templates/list",
+        ReadResource(ReadResourceRequestParams) => "resources/read",
+        Subscribe(SubscribeRequestParams) => "resources/subscribe",
+        Unsubscribe(UnsubscribeRequestParams) => "resources/unsubscribe",
+        ListPrompts(ListPromptsRequestParams) => "prompts/list",
+        GetPrompt(GetPromptRequestParams) => "prompts/get",
+        ListTools(ListToolsRequestParams) => "tools/list",
+        CallTool(CallToolRequestParams) => "tools/call",
+        SetLevel(SetLevelRequestParams) => "logging/setLevel",
+        CompleteRequest(CompleteRequestParams) => "completion/complete",
+    }
+    server {
+        CreateMessage(CreateMessageRequestParams) => "sampling/createMessage",
+        ListRoots(ListRootsRequestParams) => "roots/list",
+    }
+}
+
+impl RequestParams {
+    /// Base params of the spec requests that have them, carrying `_meta`
+    pub fn request_base(&self) -> Option<&RequestBaseParams> {
+        match self {
+            RequestParams::Ping(params) => Some(&params.request_base),
+            RequestParams::ListResources(params) => Some(&params.paginated_base.request_base),
+            RequestParams::ListResourceTemplate(params) => {
+                Some(&params.paginated_base.request_base)
+            }
+            RequestParams::ReadResource(params) => Some(&params.request_base),
+            RequestParams::ListPrompts(params) => Some(&params.paginated_base.request_base),
+            RequestParams::GetPrompt(params) => Some(&params.request_base),
+            RequestParams::ListTools(params) => Some(&params.paginated_base.request_base),
+            RequestParams::CallTool(params) => Some(&params.request_base),
+            RequestParams::CompleteRequest(params) => Some(&params.request_base),
+            RequestParams::CreateMessage(params) => Some(&params.request_base),
+            RequestParams::ListRoots(params) => Some(&params.request_base),
+            RequestParams::Initialize(_)
+            | RequestParams::Subscribe(_)
+            | RequestParams::Unsubscribe(_)
+            | RequestParams::SetLevel(_)
+            | RequestParams::Custom(_) => None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "camelCase", tag = "method", content = "params")]
-pub enum RequestParams {
-    Initialize(InitializeRequestParams),
-    Ping(PingRequestParams),
-    Paginated(Option<PaginatedRequestParams>),
-    #[serde(rename = "resources/list")]
-    ListResources(ListResourcesRequestParams),
-    #[serde(rename = "resources/templates/list")]
-    ListResourceTemplate(ListResourceTemplatesRequestParams),
-    #[serde(rename = "resources/read")]
-    ReadResource(ReadResourceRequestParams),
-    #[serde(rename = "resources/subscribe")]
-    Subscribe(SubscribeRequestParams),
-    Unsubscribe(UnsubscribeRequestParams),
-    #[serde(rename = "prompts/list")]
-    ListPrompts(ListPromptsRequestParams),
-    #[serde(rename = "prompts/get")]
-    GetPrompt(GetPromptRequestParams),
-    #[serde(rename = "tools/list")]
-    ListTools(ListToolsRequestParams),
-    #[serde(rename = "tools/call")]
-    CallTool(CallToolRequestParams),
-    #[serde(rename = "logging/setLevel")]
-    SetLevel(SetLevelRequestParams),
-    #[serde(rename = "sampling/createMessage")]
-    CreateMessage(CreateMessageRequestParams),
-    #[serde(rename = "completion/complete")]
-    CompleteRequest(CompleteRequestParams),
-    #[serde(rename = "roots/list")]
-    ListRoots(ListRootsRequestParams),
+#[serde(rename_all = "camelCase")]
+pub struct CustomRequestParams {
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "camelCase", tag = "method")]
+#[serde(rename_all = "camelCase", tag = "method", content = "params")]
 pub enum NotificationParams {
     #[serde(rename = "notifications/cancelled")]
     Cancelled(CancelledNotificationParams),
@@ -855,13 +1645,24 @@ pub enum NotificationParams {
     LoggingMessage(LoggingMessageNotificationParams),
     #[serde(rename = "notifications/roots/list_changed")]
     RootsListChanged(RootsListChangedNotificationParams),
+    /// Any notification not defined by the spec
+    #[serde(untagged)]
+    Custom(CustomNotificationParams),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomNotificationParams {
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase", untagged)]
 pub enum ResultEnum {
     Empty(EmptyResult),
-    Initialize(InitializeResult),
+    Initialize(Box<InitializeResult>),
     Paginated(PaginatedResult),
     ListResources(ListResourcesResult),
     ListResourcesTemplate(ListResourcesTemplateResult),
@@ -873,6 +1674,10 @@ pub enum ResultEnum {
     CreateMessage(CreateMessageResult),
     Complete(CompleteResult),
     ListRoot(ListRootResult),
+    /// Result of a custom method. Only ever sent, so a malformed known result is never
+    /// mistaken for one
+    #[serde(skip_deserializing)]
+    Custom(serde_json::Map<String, Value>),
 }
 
 // Client Messages
@@ -918,7 +1723,7 @@ impl From<RequestParams> for Option<ClientRequestParams> {
     }
 }
 
-// TODO impl From trait
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ClientNotificationParams {
     Cancelled(CancelledNotificationParams),
     Progress(ProgressNotificationParams),
@@ -926,6 +1731,19 @@ pub enum ClientNotificationParams {
     RootsListChanged(RootsListChangedNotificationParams),
 }
 
+impl From<ClientNotificationParams> for NotificationParams {
+    fn from(value: ClientNotificationParams) -> Self {
+        match value {
+            ClientNotificationParams::Cancelled(x) => NotificationParams::Cancelled(x),
+            ClientNotificationParams::Progress(x) => NotificationParams::Progress(x),
+            ClientNotificationParams::Initialized(x) => NotificationParams::Initialized(x),
+            ClientNotificationParams::RootsListChanged(x) => {
+                NotificationParams::RootsListChanged(x)
+            }
+        }
+    }
+}
+
 impl From<NotificationParams> for Option<ClientNotificationParams> {
     fn from(value: NotificationParams) -> Self {
         match value {
@@ -940,13 +1758,23 @@ impl From<NotificationParams> for Option<ClientNotificationParams> {
     }
 }
 
-// TODO impl From trait
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ClientResult {
     Empty(EmptyResult),
     CreateMessage(CreateMessageResult),
     ListRoot(ListRootResult),
 }
 
+impl From<ClientResult> for ResultEnum {
+    fn from(value: ClientResult) -> Self {
+        match value {
+            ClientResult::Empty(x) => ResultEnum::Empty(x),
+            ClientResult::CreateMessage(x) => ResultEnum::CreateMessage(x),
+            ClientResult::ListRoot(x) => ResultEnum::ListRoot(x),
+        }
+    }
+}
+
 impl From<ResultEnum> for Option<ClientResult> {
     fn from(value: ResultEnum) -> Self {
         match value {
@@ -960,6 +1788,7 @@ impl From<ResultEnum> for Option<ClientResult> {
 
 // Server
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ServerRequestParams {
     Ping(PingRequestParams),
     CreateMessage(CreateMessageRequestParams),
@@ -977,6 +1806,16 @@ impl From<RequestParams> for Option<ServerRequestParams> {
     }
 }
 
+impl From<ServerRequestParams> for RequestParams {
+    fn from(value: ServerRequestParams) -> Self {
+        match value {
+            ServerRequestParams::Ping(x) => RequestParams::Ping(x),
+            ServerRequestParams::CreateMessage(x) => RequestParams::CreateMessage(x),
+            ServerRequestParams::ListRoots(x) => RequestParams::ListRoots(x),
+        }
+    }
+}
+
 pub enum ServerNotificationParams {
     Cancelled(CancelledNotificationParams),
     Progress(ProgressNotificationParams),
@@ -1012,9 +1851,10 @@ impl From<NotificationParams> for Option<ServerNotificationParams> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ServerResult {
     Empty(EmptyResult),
-    Initialize(InitializeResult),
+    Initialize(Box<InitializeResult>),
     Complete(CompleteResult),
     GetPrompt(GetPromptResult),
     ListPrompts(ListPromptsResult),
@@ -1025,6 +1865,23 @@ pub enum ServerResult {
     ListTools(ListToolsResult),
 }
 
+impl From<ServerResult> for ResultEnum {
+    fn from(value: ServerResult) -> Self {
+        match value {
+            ServerResult::Empty(x) => ResultEnum::Empty(x),
+            ServerResult::Initialize(x) => ResultEnum::Initialize(x),
+            ServerResult::Complete(x) => ResultEnum::Complete(x),
+            ServerResult::GetPrompt(x) => ResultEnum::GetPrompt(x),
+            ServerResult::ListPrompts(x) => ResultEnum::ListPrompts(x),
+            ServerResult::ListResources(x) => ResultEnum::ListResources(x),
+            ServerResult::ListResourcesTemplate(x) => ResultEnum::ListResourcesTemplate(x),
+            ServerResult::ReadResource(x) => ResultEnum::ReadResource(x),
+            ServerResult::CallTool(x) => ResultEnum::CallTool(x),
+            ServerResult::ListTools(x) => ResultEnum::ListTools(x),
+        }
+    }
+}
+
 impl From<ResultEnum> for Option<ServerResult> {
     fn from(value: ResultEnum) -> Self {
         match value {
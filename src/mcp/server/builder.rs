@@ -0,0 +1,367 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use super::hooks::Hooks;
+use tower::{Layer, Service};
+
+use super::complexity::ComplexityLimit;
+use super::error::ApiError;
+use super::idempotency::IdempotencyConfig;
+use super::resource_cache::ResourceCache;
+use super::service::ServiceLayer;
+use super::sweep::SweepConfig;
+use super::{
+    McpService, NotificationDropPolicy, Server, ToolOverflowPolicy, DEFAULT_KEEP_ALIVE_INTERVAL,
+    DEFAULT_QUEUE_DEPTH,
+};
+use crate::mcp::schema::{self, JSONRPCMessage};
+
+/// Configures a [`Server`] before it starts serving
+#[derive(Debug)]
+pub struct ServerBuilder {
+    info: schema::Implementation,
+    instructions: Option<String>,
+    generated_instructions: bool,
+    port: usize,
+    endpoint: String,
+    path_prefix: String,
+    capabilities: schema::ServerCapabilities,
+    capabilities_route: bool,
+    require_initialize: bool,
+    request_timeout: Option<Duration>,
+    shutdown_timeout: Option<Duration>,
+    keep_alive_interval: Duration,
+    idempotency: Option<IdempotencyConfig>,
+    resource_cache: Option<usize>,
+    hooks: Hooks,
+    pretty_json: bool,
+    history_capacity: Option<usize>,
+    max_message_size: Option<usize>,
+    complexity_limit: Option<ComplexityLimit>,
+    enabled_methods: Option<HashSet<String>>,
+    queue_depth: usize,
+    notification_drop_policy: NotificationDropPolicy,
+    progress_interval: Option<Duration>,
+    resource_subscriptions: bool,
+    session_resume_timeout: Option<Duration>,
+    sweep: SweepConfig,
+    tool_concurrency_limit: Option<usize>,
+    tool_overflow_policy: ToolOverflowPolicy,
+    service_layer: Option<ServiceLayer>,
+}
+
+impl ServerBuilder {
+    pub fn new(name: &str, version: &str) -> Self {
+        Self::from_info(schema::Implementation {
+            name: String::from(name),
+            version: String::from(version),
+        })
+    }
+
+    /// Builder for a server identifying itself to clients with `info`
+    pub fn from_info(info: schema::Implementation) -> Self {
+        Self {
+            info,
+            instructions: None,
+            generated_instructions: false,
+            port: 3001,
+            endpoint: String::from("messages"),
+            path_prefix: String::new(),
+            capabilities: schema::ServerCapabilities::default(),
+            capabilities_route: false,
+            require_initialize: true,
+            request_timeout: None,
+            shutdown_timeout: None,
+            keep_alive_interval: DEFAULT_KEEP_ALIVE_INTERVAL,
+            idempotency: None,
+            resource_cache: None,
+            hooks: Hooks::default(),
+            pretty_json: false,
+            history_capacity: None,
+            max_message_size: None,
+            complexity_limit: None,
+            enabled_methods: None,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            notification_drop_policy: NotificationDropPolicy::default(),
+            progress_interval: None,
+            resource_subscriptions: false,
+            session_resume_timeout: None,
+            sweep: SweepConfig::default(),
+            tool_concurrency_limit: None,
+            tool_overflow_policy: ToolOverflowPolicy::default(),
+            service_layer: None,
+        }
+    }
+
+    pub fn port(mut self, port: usize) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Endpoint advertised to SSE clients for posting messages
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = String::from(endpoint);
+        self
+    }
+
+    /// Mounts every route under `prefix`, e.g. `/mcp` serves `/mcp/sse` and `/mcp/messages`.
+    /// The advertised endpoint is placed under the same prefix
+    pub fn path_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        self.path_prefix = match prefix {
+            "" => String::new(),
+            prefix if prefix.starts_with('/') => String::from(prefix),
+            prefix => format!("/{prefix}"),
+        };
+        self
+    }
+
+    /// Capabilities advertised on initialize. Features with registered handlers are added on
+    /// top of these
+    pub fn capabilities(mut self, capabilities: schema::ServerCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Usage hints for the model sent to clients on initialize
+    pub fn instructions(mut self, instructions: &str) -> Self {
+        self.instructions = Some(String::from(instructions));
+        self
+    }
+
+    /// Without explicit [`ServerBuilder::instructions`], sends instructions listing the tools
+    /// and prompts registered at initialize time, with their descriptions. Off by default
+    pub fn generated_instructions(mut self, enabled: bool) -> Self {
+        self.generated_instructions = enabled;
+        self
+    }
+
+    /// Exposes `GET /capabilities` with the advertised capabilities and registered names.
+    /// Off by default as it leaks what the server exposes without a handshake
+    pub fn capabilities_route(mut self, enabled: bool) -> Self {
+        self.capabilities_route = enabled;
+        self
+    }
+
+    /// Whether sessions must complete the initialize handshake before other requests. Turning
+    /// it off lets trusted peers, e.g. scripts over stdio, call methods right away. On by default
+    pub fn require_initialize(mut self, required: bool) -> Self {
+        self.require_initialize = required;
+        self
+    }
+
+    /// Only serves the request `methods` listed, e.g. leaving out `tools/call` for a read-only
+    /// deployment. Others get `METHOD_NOT_FOUND` even when a handler is registered.
+    /// `initialize` and `ping` are always served. All methods are enabled by default
+    pub fn enabled_methods(mut self, methods: &[&str]) -> Self {
+        self.enabled_methods = Some(methods.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    /// Default time limit for handlers. Tools registered with their own timeout override it
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// How long [`Server::serve_with_shutdown`] waits for open connections once signalled.
+    /// Sessions still open after it are terminated. Waits indefinitely by default
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Idle time after which an SSE stream gets a keep-alive comment. Any message sent restarts
+    /// the interval, so busy streams carry no keep-alives. Defaults to 1 second
+    pub fn keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = interval;
+        self
+    }
+
+    /// Deduplicates `tools/call` requests carrying an `idempotencyKey` in `_meta`. A repeated
+    /// key within a session returns the cached result instead of running the tool again.
+    /// Each session keeps at most `capacity` results for up to `ttl`
+    pub fn idempotency_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.idempotency = Some(IdempotencyConfig { capacity, ttl });
+        self
+    }
+
+    /// Caches the contents returned by `resources/read` handlers, keeping the `capacity` most
+    /// recently read uris. A cached uri is read again after
+    /// [`Server::notify_resource_updated`](super::Server::notify_resource_updated) for it.
+    /// Streamed resources are never cached
+    pub fn resource_cache(mut self, capacity: usize) -> Self {
+        self.resource_cache = Some(capacity);
+        self
+    }
+
+    /// Pretty prints outbound messages, easier to read in network logs when debugging.
+    /// Messages are compact by default
+    pub fn pretty_json(mut self, enabled: bool) -> Self {
+        self.pretty_json = enabled;
+        self
+    }
+
+    /// Keeps the last `capacity` messages of each session, in and out, readable with
+    /// [`Server::session_history`]. Off by default
+    pub fn session_history(mut self, capacity: usize) -> Self {
+        self.history_capacity = Some(capacity);
+        self
+    }
+
+    /// Largest serialized message in bytes sent to clients. Larger responses become an error
+    /// response and other messages an error log. Unlimited by default
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = Some(bytes);
+        self
+    }
+
+    /// Rejects requests whose params nest deeper than `max_depth` or hold more than
+    /// `max_values` JSON values with `INVALID_PARAMS`. Off by default
+    pub fn params_complexity_limit(mut self, max_depth: usize, max_values: usize) -> Self {
+        self.complexity_limit = Some(ComplexityLimit {
+            max_depth,
+            max_values,
+        });
+        self
+    }
+
+    /// Messages queued per client waiting for its transport. Defaults to 32
+    pub fn queue_depth(mut self, depth: usize) -> Self {
+        self.queue_depth = depth;
+        self
+    }
+
+    /// What to do with notifications, including broadcasts, for a client whose queue is full.
+    /// Blocks by default, so one slow client can stall a broadcast to everyone
+    pub fn notification_drop_policy(mut self, policy: NotificationDropPolicy) -> Self {
+        self.notification_drop_policy = policy;
+        self
+    }
+
+    /// Sends at most one progress notification per `interval` for each request, the rest
+    /// are dropped. Reports reaching the total always go out. Every report is sent by default
+    pub fn progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = Some(interval);
+        self
+    }
+
+    /// Accepts `resources/subscribe` and `resources/unsubscribe`, advertising `subscribe` on
+    /// the resources capability. Off by default, clients get `METHOD_NOT_FOUND` for them
+    pub fn resource_subscriptions(mut self, enabled: bool) -> Self {
+        self.resource_subscriptions = enabled;
+        self
+    }
+
+    /// Keeps sessions open for `timeout` after their SSE stream drops. Clients reconnecting
+    /// with `GET /sse?sessionId=..` in time get the same session back, with its subscriptions
+    /// and the messages queued meanwhile, up to the queue depth. Off by default
+    pub fn session_resumption(mut self, timeout: Duration) -> Self {
+        self.session_resume_timeout = Some(timeout);
+        self
+    }
+
+    /// Closes sessions that haven't completed initialization `timeout` after connecting.
+    /// The disconnect hook runs for them like for any other close. Off by default
+    pub fn init_timeout(mut self, timeout: Duration) -> Self {
+        self.sweep.init_timeout = Some(timeout);
+        self
+    }
+
+    /// Closes sessions that sent no message for `timeout`. Off by default
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.sweep.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Runs at most `limit` tool calls at once across all sessions. Further calls wait for a
+    /// running one to finish or are rejected, as `policy` says. Tools can get their own lower
+    /// limit with [`Server::set_tool_concurrency_limit`]. Unlimited by default, a limit of 0
+    /// counts as 1 so calls can still run
+    pub fn tool_concurrency_limit(mut self, limit: usize, policy: ToolOverflowPolicy) -> Self {
+        self.tool_concurrency_limit = Some(limit.max(1));
+        self.tool_overflow_policy = policy;
+        self
+    }
+
+    /// Wraps message handling of every session in a `tower` layer, e.g. for metrics or auth
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<McpService> + Send + Sync + 'static,
+        L::Service: Service<JSONRPCMessage, Response = Option<JSONRPCMessage>, Error = ApiError>
+            + Clone
+            + Send
+            + 'static,
+        <L::Service as Service<JSONRPCMessage>>::Future: Send + 'static,
+    {
+        self.service_layer = Some(ServiceLayer::new(layer));
+        self
+    }
+
+    /// Runs `hook` with the session id whenever a session is closed or terminated
+    pub fn on_disconnect<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.hooks.on_disconnect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Runs `hook` with the session id and params of client notifications the server doesn't
+    /// handle itself. Without it they are logged and ignored
+    pub fn on_notification<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &schema::NotificationParams) + Send + Sync + 'static,
+    {
+        self.hooks.on_notification = Some(Arc::new(hook));
+        self
+    }
+
+    /// Generates the ids of new SSE sessions, e.g. to prefix them with a node id. Ids must be
+    /// unique across live sessions. Random v4 UUIDs by default
+    pub fn session_id_generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.hooks.session_id = Some(Arc::new(generator));
+        self
+    }
+
+    pub fn build(self) -> Server {
+        let mut server = Server::new(&self.info.name, &self.info.version, self.port);
+        server.instructions = self.instructions;
+        server.generated_instructions = self.generated_instructions;
+        server.endpoint = self.endpoint;
+        server.path_prefix = self.path_prefix;
+        server.capabilities = RwLock::new(self.capabilities);
+        server.capabilities_route = self.capabilities_route;
+        server.require_initialize = self.require_initialize;
+        server.request_timeout = self.request_timeout;
+        server.shutdown_timeout = self.shutdown_timeout;
+        server.keep_alive_interval = self.keep_alive_interval;
+        server.idempotency = self.idempotency;
+        server.resource_cache = self
+            .resource_cache
+            .map(|capacity| Mutex::new(ResourceCache::new(capacity)));
+        server.hooks = self.hooks;
+        server.pretty_json = self.pretty_json;
+        server.history_capacity = self.history_capacity;
+        server.max_message_size = self.max_message_size;
+        server.complexity_limit = self.complexity_limit;
+        server.enabled_methods = self.enabled_methods;
+        server.queue_depth = self.queue_depth;
+        server.notification_drop_policy = self.notification_drop_policy;
+        server.progress_interval = self.progress_interval;
+        server.resource_subscriptions = self.resource_subscriptions;
+        server.session_resume_timeout = self.session_resume_timeout;
+        server.sweep = self.sweep;
+        server.tool_permits = self
+            .tool_concurrency_limit
+            .map(|limit| Arc::new(Semaphore::new(limit)));
+        server.tool_overflow_policy = self.tool_overflow_policy;
+        server.service_layer = self.service_layer;
+        server
+    }
+}
@@ -0,0 +1,35 @@
+use serde_json::Value;
+
+/// Bounds on the shape of request params, checked before they reach a handler
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ComplexityLimit {
+    pub(crate) max_depth: usize,
+    pub(crate) max_values: usize,
+}
+
+impl ComplexityLimit {
+    /// Describes why `params` is too complex, if it is
+    pub(crate) fn check(&self, params: &Value) -> Option<String> {
+        let mut values = 0;
+        // Walk iteratively so a deep value can't overflow the stack here either
+        let mut pending = vec![(params, 1)];
+
+        while let Some((value, depth)) = pending.pop() {
+            values += 1;
+            if values > self.max_values {
+                return Some(format!("Params exceed {} values", self.max_values));
+            }
+            if depth > self.max_depth {
+                return Some(format!("Params exceed nesting depth of {}", self.max_depth));
+            }
+
+            match value {
+                Value::Array(items) => pending.extend(items.iter().map(|x| (x, depth + 1))),
+                Value::Object(fields) => pending.extend(fields.values().map(|x| (x, depth + 1))),
+                _ => (),
+            }
+        }
+
+        None
+    }
+}
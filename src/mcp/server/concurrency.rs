@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::error::{ApiError, Result};
+use super::Server;
+
+/// What happens to a `tools/call` arriving while its concurrency limit is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolOverflowPolicy {
+    /// Wait for a running call to finish
+    #[default]
+    Wait,
+    /// Answer right away with a `SERVER_BUSY` error
+    Reject,
+}
+
+/// Permit to run a tool, `None` when the policy rejects the call
+pub(crate) async fn acquire(
+    permits: &Arc<Semaphore>,
+    policy: ToolOverflowPolicy,
+) -> Option<OwnedSemaphorePermit> {
+    match policy {
+        // The semaphores are never closed
+        ToolOverflowPolicy::Wait => permits.clone().acquire_owned().await.ok(),
+        ToolOverflowPolicy::Reject => permits.clone().try_acquire_owned().ok(),
+    }
+}
+
+impl Server {
+    /// Caps the calls of tool `name` running at once, on top of the server wide limit. Calls
+    /// over it follow the server's [`ToolOverflowPolicy`]. Fails if the tool isn't registered or
+    /// `limit` is 0
+    pub fn set_tool_concurrency_limit(&self, name: &str, limit: usize) -> Result<()> {
+        if limit == 0 {
+            return Err(ApiError::InvalidParams(format!(
+                "Concurrency limit of {name} must be at least 1"
+            )));
+        }
+        let mut registry = self.registry.write().map_err(|_| ApiError::PoisonedLock)?;
+        let registered = registry
+            .tools
+            .get_mut(name)
+            .ok_or_else(|| ApiError::InvalidTool(format!("{name} is not registered")))?;
+
+        registered.permits = Some(Arc::new(Semaphore::new(limit)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::history::HistoryDirection;
+use super::queue::NotificationDropPolicy;
+use super::{ClientConn, Message, SessionId};
+
+/// State of the request a handler is serving, built when the request arrives
+#[derive(Clone)]
+pub struct RequestContext {
+    session_id: SessionId,
+    request_id: schema::RequestId,
+    protocol_version: schema::ProtocolVersion,
+    progress: ProgressReporter,
+    cancellation: CancellationToken,
+    headers: HeaderMap,
+    received_at: Instant,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        session_id: &SessionId,
+        request_id: &schema::RequestId,
+        protocol_version: schema::ProtocolVersion,
+        progress: ProgressReporter,
+        cancellation: CancellationToken,
+        headers: HeaderMap,
+    ) -> Self {
+        Self {
+            session_id: session_id.to_owned(),
+            request_id: request_id.to_owned(),
+            protocol_version,
+            progress,
+            cancellation,
+            headers,
+            received_at: Instant::now(),
+        }
+    }
+
+    pub fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+
+    pub fn request_id(&self) -> &schema::RequestId {
+        &self.request_id
+    }
+
+    /// Protocol version negotiated on initialize, the default before it completes
+    pub fn protocol_version(&self) -> &schema::ProtocolVersion {
+        &self.protocol_version
+    }
+
+    pub fn progress(&self) -> &ProgressReporter {
+        &self.progress
+    }
+
+    /// Cancelled when the client sends `notifications/cancelled` for the request. The
+    /// response is dropped either way, so long running handlers can stop early
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Headers of the HTTP post that carried the request, limited to [`CONTEXT_HEADERS`].
+    /// Empty on transports without headers, like stdio
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// When the server started handling the request
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+
+    /// Sends an intermediate result as a `notifications/message` from the
+    /// [`PARTIAL_RESULT_LOGGER`] logger, with `{ "requestId": .., "partial": data }` as data.
+    ///
+    /// Partials share the session queue with the final response, so they reach the client in
+    /// the order they were sent and before the response. Unlike logs they ignore the level set
+    /// by the client, but they can be dropped when the queue is full under a dropping
+    /// [`NotificationDropPolicy`]
+    pub async fn send_partial(&self, data: Value) -> Result<()> {
+        let message = schema::JSONRPCMessage::Notification(schema::JSONRPCNotification {
+            json_rpc: schema::JSONRPC_VERSION.into(),
+            params: schema::NotificationParams::LoggingMessage(
+                schema::LoggingMessageNotificationParams {
+                    meta: None,
+                    level: schema::LoggingLevel::Info,
+                    logger: Some(PARTIAL_RESULT_LOGGER.to_string()),
+                    data: json!({ "requestId": self.request_id, "partial": data }),
+                },
+            ),
+        });
+
+        self.progress.send(message).await
+    }
+}
+
+/// Logger name of the notifications sent by [`RequestContext::send_partial`]
+pub const PARTIAL_RESULT_LOGGER: &str = "partial_result";
+
+/// Headers copied from a posted message into its [`RequestContext`]
+pub const CONTEXT_HEADERS: &[&str] =
+    &["authorization", "user-agent", "x-request-id", "traceparent"];
+
+/// The [`CONTEXT_HEADERS`] out of `headers`
+pub(crate) fn context_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut kept = HeaderMap::new();
+    for name in CONTEXT_HEADERS {
+        for value in headers.get_all(*name) {
+            kept.append(*name, value.clone());
+        }
+    }
+
+    kept
+}
+
+impl std::fmt::Debug for RequestContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestContext")
+            .field("session_id", &self.session_id)
+            .field("request_id", &self.request_id)
+            .field("protocol_version", &self.protocol_version)
+            .field("progress_token", &self.progress.token)
+            .field("cancelled", &self.is_cancelled())
+            // Values are left out, they may hold credentials
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("received_at", &self.received_at)
+            .finish()
+    }
+}
+
+/// Sends `notifications/progress` for a request, if the client asked for them with a
+/// `progressToken`
+#[derive(Clone)]
+pub struct ProgressReporter {
+    token: Option<schema::ProgressToken>,
+    session_id: SessionId,
+    conn: Arc<Mutex<ClientConn>>,
+    drop_policy: NotificationDropPolicy,
+    interval: Option<Duration>,
+    // Shared by clones so a handler reporting from several tasks is throttled as a whole
+    last_sent: Arc<Mutex<Option<Instant>>>,
+}
+
+impl ProgressReporter {
+    pub(crate) fn new(
+        token: Option<schema::ProgressToken>,
+        session_id: &SessionId,
+        conn: Arc<Mutex<ClientConn>>,
+        drop_policy: NotificationDropPolicy,
+        interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            token,
+            session_id: session_id.to_owned(),
+            conn,
+            drop_policy,
+            interval,
+            last_sent: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Token the client attached to the request
+    pub fn token(&self) -> Option<&schema::ProgressToken> {
+        self.token.as_ref()
+    }
+
+    /// Reports `progress` out of `total`, doing nothing when the client gave no token. With
+    /// a progress interval on the server, reports coming faster than it are skipped
+    pub async fn report(&self, progress: i64, total: Option<i64>) -> Result<()> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let done = total.is_some_and(|total| progress >= total);
+        if !done && !self.should_send()? {
+            return Ok(());
+        }
+
+        let message = schema::JSONRPCMessage::Notification(schema::JSONRPCNotification {
+            json_rpc: schema::JSONRPC_VERSION.into(),
+            params: schema::NotificationParams::Progress(schema::ProgressNotificationParams {
+                progress_token: token.to_owned(),
+                progress,
+                total,
+            }),
+        });
+
+        self.send(message).await
+    }
+
+    /// Queues a notification on the request's session, recording it in the history
+    async fn send(&self, message: schema::JSONRPCMessage) -> Result<()> {
+        let tx = {
+            let mut client_conn = self.conn.lock().map_err(|_| ApiError::PoisonedLock)?;
+            if let Some(history) = client_conn.history.as_mut() {
+                history.push(HistoryDirection::Outbound, &message);
+            }
+            client_conn.send.clone()
+        };
+
+        let message = Message {
+            session_id: self.session_id.to_owned(),
+            sse_message: message,
+        };
+        if let Ok(Some(dropped)) = tx.send_notification(message, self.drop_policy).await {
+            tracing::warn!(
+                session_id = self.session_id,
+                notification = ?dropped.sse_message,
+                "client queue full, dropping notification"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether the interval since the last sent report has passed, marking this one sent
+    fn should_send(&self) -> Result<bool> {
+        let Some(interval) = self.interval else {
+            return Ok(true);
+        };
+
+        let mut last_sent = self.last_sent.lock().map_err(|_| ApiError::PoisonedLock)?;
+        let now = Instant::now();
+        if last_sent.is_some_and(|last| now - last < interval) {
+            return Ok(false);
+        }
+        *last_sent = Some(now);
+
+        Ok(true)
+    }
+}
+
+/// `_meta.progressToken` of any request, whichever params type it has
+pub(crate) fn progress_token(params: &schema::RequestParams) -> Option<schema::ProgressToken> {
+    if let schema::RequestParams::Custom(custom) = params {
+        let token = custom.params.pointer("/_meta/progressToken")?;
+        return schema::ProgressToken::deserialize(token).ok();
+    }
+
+    params.request_base()?.progress_token().cloned()
+}
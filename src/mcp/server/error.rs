@@ -1,6 +1,8 @@
 use axum_derive_error::ErrorResponse;
 use thiserror::Error;
 
+use crate::mcp::schema;
+
 pub type Result<T> = std::result::Result<T, ApiError>;
 
 #[derive(Error, ErrorResponse)]
@@ -10,6 +12,61 @@ pub enum ApiError {
     PoisonedLock,
     #[error("Io Error")]
     IoError(#[from] std::io::Error),
+    // The io error is the source, so it is printed as the cause rather than in the message
+    #[error("Failed to listen on {addr}")]
+    BindError {
+        addr: String,
+        source: std::io::Error,
+    },
     #[error("Missing Client Error")]
     MissingClient,
+    #[error("Handler Error: {0}")]
+    HandlerError(String),
+    #[error("Invalid Params: {0}")]
+    InvalidParams(String),
+    #[error("Invalid Tool: {0}")]
+    InvalidTool(String),
+    #[error("Invalid argument at {path}: {message}")]
+    InvalidArguments { path: String, message: String },
+    #[error("Client Error {code}: {message}")]
+    ClientError { code: i64, message: String },
+    #[error("Invalid Response: {0}")]
+    InvalidResponse(String),
+    // The panic message is only logged, it may hold details the client shouldn't see
+    #[error("Handler panicked")]
+    HandlerPanicked,
+    #[error("Timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("Session closed")]
+    SessionClosed,
+    #[error("Request cancelled")]
+    Cancelled,
+    #[error("No in-flight request with id {0}")]
+    UnknownRequest(schema::RequestId),
+    #[error("Session id {0} is already in use")]
+    SessionIdInUse(String),
+}
+
+impl ApiError {
+    /// JSON-RPC error code a request failing with this error is answered with
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::InvalidParams(_) | Self::InvalidArguments { .. } | Self::UnknownRequest(_) => {
+                schema::INVALID_PARAMS
+            }
+            Self::MissingClient => schema::INVALID_REQUEST,
+            Self::PoisonedLock
+            | Self::IoError(_)
+            | Self::BindError { .. }
+            | Self::HandlerError(_)
+            | Self::InvalidTool(_)
+            | Self::ClientError { .. }
+            | Self::InvalidResponse(_)
+            | Self::HandlerPanicked
+            | Self::Timeout(_)
+            | Self::SessionClosed
+            | Self::Cancelled
+            | Self::SessionIdInUse(_) => schema::INTERNAL_ERROR,
+        }
+    }
 }
@@ -0,0 +1,23 @@
+//! What the dispatch actually handles, as opposed to everything the schema defines. Every
+//! client request has a handler, notifications are listed here. Keep them in sync with
+//! `handle_notification` when handlers land
+
+use crate::mcp::schema;
+
+/// Client notifications the server acts on. Others only reach the notification hook
+const SUPPORTED_NOTIFICATIONS: &[&str] = &[
+    "notifications/initialized",
+    "notifications/cancelled",
+    "notifications/progress",
+];
+
+/// Spec request methods this crate answers. Requests the spec only defines from server to
+/// client, e.g. `sampling/createMessage`, are not in it
+pub fn supported_methods() -> &'static [&'static str] {
+    schema::CLIENT_REQUEST_METHODS
+}
+
+/// Spec notifications from the client this crate handles
+pub fn supported_notifications() -> &'static [&'static str] {
+    SUPPORTED_NOTIFICATIONS
+}
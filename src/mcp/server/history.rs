@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::{Server, SessionId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryDirection {
+    /// Received from the client
+    Inbound,
+    /// Queued for delivery to the client
+    Outbound,
+}
+
+/// A message exchanged with a session, kept for debugging
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub direction: HistoryDirection,
+    pub timestamp: SystemTime,
+    pub message: schema::JSONRPCMessage,
+}
+
+/// Ring buffer of the last `capacity` messages of a session
+#[derive(Debug)]
+pub(crate) struct SessionHistory {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl SessionHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn push(&mut self, direction: HistoryDirection, message: &schema::JSONRPCMessage) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(HistoryEntry {
+            direction,
+            timestamp: SystemTime::now(),
+            message: message.to_owned(),
+        });
+    }
+}
+
+impl Server {
+    /// Last messages exchanged with the session, oldest first. Empty unless history was
+    /// enabled with [`super::ServerBuilder::session_history`]
+    pub fn session_history(&self, session_id: &SessionId) -> Result<Vec<HistoryEntry>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(client_conn
+            .history
+            .as_ref()
+            .map(|history| history.entries.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// Records a message received from the client if the session keeps a history
+    pub(crate) fn record_inbound(
+        &self,
+        session_id: &SessionId,
+        message: &schema::JSONRPCMessage,
+    ) -> Result<()> {
+        if self.history_capacity.is_none() {
+            return Ok(());
+        }
+
+        let Some(lock) = self.clients.get(session_id) else {
+            return Ok(());
+        };
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        if let Some(history) = client_conn.history.as_mut() {
+            history.push(HistoryDirection::Inbound, message);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use crate::mcp::schema::NotificationParams;
+
+/// Called with the session id after a session is removed from the server
+pub(crate) type DisconnectHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Produces the id of each new SSE session
+pub(crate) type SessionIdGenerator = Arc<dyn Fn() -> String + Send + Sync>;
+
+/// Called with the session id and params of client notifications the server doesn't handle
+pub(crate) type NotificationHook = Arc<dyn Fn(&str, &NotificationParams) + Send + Sync>;
+
+/// Lifecycle callbacks set through the [`super::ServerBuilder`]
+#[derive(Default, Clone)]
+pub(crate) struct Hooks {
+    pub(crate) on_disconnect: Option<DisconnectHook>,
+    pub(crate) session_id: Option<SessionIdGenerator>,
+    pub(crate) on_notification: Option<NotificationHook>,
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_disconnect", &self.on_disconnect.is_some())
+            .field("session_id", &self.session_id.is_some())
+            .field("on_notification", &self.on_notification.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,128 @@
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::{Server, SessionId};
+
+/// `_meta` key clients set on `tools/call` to make retries safe
+pub const IDEMPOTENCY_KEY: &str = "idempotencyKey";
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IdempotencyConfig {
+    pub(crate) capacity: usize,
+    pub(crate) ttl: Duration,
+}
+
+/// Bounded per-session cache of tool results keyed by tool name and idempotency key.
+/// Entries expire after the configured ttl and the oldest entry is evicted when full
+#[derive(Debug)]
+pub(crate) struct IdempotencyCache {
+    config: IdempotencyConfig,
+    entries: HashMap<(String, String), (Instant, schema::CallToolResult)>,
+    order: VecDeque<(String, String)>,
+}
+
+impl IdempotencyCache {
+    pub(crate) fn new(config: IdempotencyConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn get(&mut self, tool: &str, key: &str) -> Option<schema::CallToolResult> {
+        let entry_key = (tool.to_string(), key.to_string());
+        let (inserted_at, result) = self.entries.get(&entry_key)?;
+
+        if inserted_at.elapsed() > self.config.ttl {
+            self.entries.remove(&entry_key);
+            self.order.retain(|x| x != &entry_key);
+            return None;
+        }
+
+        Some(result.to_owned())
+    }
+
+    pub(crate) fn insert(&mut self, tool: &str, key: &str, result: schema::CallToolResult) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let entry_key = (tool.to_string(), key.to_string());
+        if self
+            .entries
+            .insert(entry_key.clone(), (Instant::now(), result))
+            .is_none()
+        {
+            self.order.push_back(entry_key);
+        }
+
+        while self.order.len() > self.config.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl Server {
+    /// Idempotency key of a tool call, if the server deduplicates calls
+    pub(crate) fn idempotency_key(&self, params: &schema::CallToolRequestParams) -> Option<String> {
+        self.idempotency?;
+
+        params
+            .request_base
+            .meta_field(IDEMPOTENCY_KEY)
+            .and_then(Value::as_str)
+            .map(String::from)
+    }
+
+    pub(crate) fn cached_tool_result(
+        &self,
+        session_id: &SessionId,
+        tool: &str,
+        key: &str,
+    ) -> Result<Option<schema::CallToolResult>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(client_conn
+            .idempotency_cache
+            .as_mut()
+            .and_then(|cache| cache.get(tool, key)))
+    }
+
+    pub(crate) fn cache_tool_result(
+        &self,
+        session_id: &SessionId,
+        tool: &str,
+        key: &str,
+        result: schema::CallToolResult,
+    ) -> Result<()> {
+        let Some(config) = self.idempotency else {
+            return Ok(());
+        };
+
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        client_conn
+            .idempotency_cache
+            .get_or_insert_with(|| IdempotencyCache::new(config))
+            .insert(tool, key, result);
+
+        Ok(())
+    }
+}
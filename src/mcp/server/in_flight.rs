@@ -0,0 +1,164 @@
+use tokio_util::sync::CancellationToken;
+
+use crate::mcp::schema::{self, JSONRPCMessage};
+
+use super::error::{ApiError, Result};
+use super::utils::{create_error_response, create_result_response};
+use super::{Server, SessionId};
+
+impl Server {
+    /// Completes an in-flight request of the session out of band, e.g. from a worker the
+    /// handler handed the request to. The handler's own response is dropped if it finishes later
+    pub async fn respond(
+        &self,
+        session_id: &SessionId,
+        id: &schema::RequestId,
+        result: schema::ServerResult,
+    ) -> Result<()> {
+        if !self.finish_request(session_id, id)? {
+            return Err(ApiError::UnknownRequest(id.to_owned()));
+        }
+
+        self.send_message(session_id, create_result_response(id, result.into()))
+            .await
+    }
+
+    /// Ids of the requests from the client that have not been answered or cancelled yet
+    pub fn in_flight_requests(&self, session_id: &SessionId) -> Result<Vec<schema::RequestId>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        Ok(client_conn.in_flight.keys().cloned().collect())
+    }
+
+    /// Tracks a request answered without a handler, so its reply goes through
+    /// [`Server::send_response`] like any other. Returns false, leaving the first one tracked,
+    /// if a request with the same id is still in flight
+    pub(crate) fn track_request(
+        &self,
+        session_id: &SessionId,
+        id: &schema::RequestId,
+    ) -> Result<bool> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        if client_conn.in_flight.contains_key(id) {
+            return Ok(false);
+        }
+        client_conn
+            .in_flight
+            .insert(id.to_owned(), CancellationToken::new());
+
+        Ok(true)
+    }
+
+    /// Sends the response to an in-flight request. Responses to requests that were already
+    /// answered are dropped
+    pub(crate) async fn send_response(
+        &self,
+        session_id: &SessionId,
+        response: JSONRPCMessage,
+    ) -> Result<()> {
+        if !self.finish_response(session_id, &response)? {
+            return Ok(());
+        }
+
+        self.send_message(session_id, response).await
+    }
+
+    /// Stops tracking the request the response answers. Returns false if it was answered
+    /// already, in which case the response must be dropped
+    pub(crate) fn finish_response(
+        &self,
+        session_id: &SessionId,
+        response: &JSONRPCMessage,
+    ) -> Result<bool> {
+        // Null ids are only on errors for requests that could not be read or reused an id,
+        // which are never tracked
+        if let Some(id) = response_id(response).filter(|id| **id != schema::RequestId::Null) {
+            let _span = tracing::debug_span!("inbound_request", session_id, %id).entered();
+            if !self.finish_request(session_id, id)? {
+                tracing::debug!("request already answered, dropping response");
+                return Ok(false);
+            }
+            tracing::debug!("sending response");
+        }
+
+        Ok(true)
+    }
+
+    /// Guard that stops tracking the request when dropped before being marked answered
+    pub(crate) fn tracked_request<'a>(
+        &'a self,
+        session_id: &'a SessionId,
+        id: &'a schema::RequestId,
+    ) -> TrackedRequest<'a> {
+        TrackedRequest {
+            server: self,
+            session_id,
+            id,
+            answered: false,
+        }
+    }
+
+    /// Stops tracking the request, returning whether it was still in flight
+    fn finish_request(&self, session_id: &SessionId, id: &schema::RequestId) -> Result<bool> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(client_conn.in_flight.remove(id).is_some())
+    }
+}
+
+/// `INVALID_REQUEST` for a request reusing the id of one still in flight. The reply has a null
+/// id, with the request's one it would be taken for the answer to the first request
+pub(crate) fn duplicate_request_response(id: &schema::RequestId) -> JSONRPCMessage {
+    tracing::warn!(%id, "request id reused while in flight");
+    create_error_response(
+        &schema::RequestId::Null,
+        schema::INVALID_REQUEST,
+        &format!("Request id {id} is already in flight"),
+    )
+}
+
+fn response_id(message: &JSONRPCMessage) -> Option<&schema::RequestId> {
+    match message {
+        JSONRPCMessage::Response(schema::JSONRPCResponse::Result(result)) => Some(&result.id),
+        JSONRPCMessage::Response(schema::JSONRPCResponse::Error(error)) => Some(&error.id),
+        _ => None,
+    }
+}
+
+/// See [`Server::tracked_request`]
+pub(crate) struct TrackedRequest<'a> {
+    server: &'a Server,
+    session_id: &'a SessionId,
+    id: &'a schema::RequestId,
+    answered: bool,
+}
+
+impl TrackedRequest<'_> {
+    /// The response will go through [`Server::send_response`], which stops tracking it
+    pub(crate) fn answered(mut self) {
+        self.answered = true;
+    }
+}
+
+impl Drop for TrackedRequest<'_> {
+    fn drop(&mut self) {
+        if !self.answered {
+            // The session may be gone already, in which case there is nothing to clean up
+            _ = self.server.finish_request(self.session_id, self.id);
+        }
+    }
+}
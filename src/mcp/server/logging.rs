@@ -0,0 +1,154 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::{Instrument, Level};
+
+use crate::mcp::schema::{self, JSONRPCMessage};
+
+use super::error::{ApiError, Result};
+use super::{Server, SessionId};
+
+impl From<&schema::LoggingLevel> for Level {
+    fn from(level: &schema::LoggingLevel) -> Self {
+        match level {
+            schema::LoggingLevel::Debug => Level::DEBUG,
+            schema::LoggingLevel::Info | schema::LoggingLevel::Notice => Level::INFO,
+            schema::LoggingLevel::Warning => Level::WARN,
+            schema::LoggingLevel::Error
+            | schema::LoggingLevel::Critical
+            | schema::LoggingLevel::Alert
+            | schema::LoggingLevel::Emergency => Level::ERROR,
+        }
+    }
+}
+
+impl Server {
+    /// Sends a `notifications/message` to the client. The `logger` name is set on the
+    /// notification and the session id in its `_meta.sessionId`. Messages from loggers muted
+    /// by the session, or below the level it set with `logging/setLevel`, are dropped.
+    ///
+    /// Returns whether the message was delivered
+    pub async fn send_log(
+        &self,
+        session_id: &SessionId,
+        level: schema::LoggingLevel,
+        logger: Option<&str>,
+        data: Value,
+    ) -> Result<bool> {
+        let span = tracing::debug_span!("send_log", session_id);
+        self.deliver_log(session_id, level, logger, data)
+            .instrument(span)
+            .await
+    }
+
+    async fn deliver_log(
+        &self,
+        session_id: &SessionId,
+        level: schema::LoggingLevel,
+        logger: Option<&str>,
+        data: Value,
+    ) -> Result<bool> {
+        if self.is_log_filtered(session_id, level, logger)? {
+            tracing::debug!(?level, logger, "dropping filtered log");
+            return Ok(false);
+        }
+        tracing::debug!(?level, logger, "sending log");
+
+        let meta = HashMap::from([("sessionId".to_string(), Value::from(session_id.as_str()))]);
+        let notification = schema::JSONRPCNotification {
+            json_rpc: schema::JSONRPC_VERSION.into(),
+            params: schema::NotificationParams::LoggingMessage(
+                schema::LoggingMessageNotificationParams {
+                    meta: Some(meta),
+                    level,
+                    logger: logger.map(String::from),
+                    data,
+                },
+            ),
+        };
+
+        self.send_message(session_id, JSONRPCMessage::Notification(notification))
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Same as [`Server::send_log`] but also mirrors the message to the server's own
+    /// `tracing` output at the mapped level
+    pub async fn send_log_traced(
+        &self,
+        session_id: &SessionId,
+        level: schema::LoggingLevel,
+        logger: Option<&str>,
+        data: Value,
+    ) -> Result<bool> {
+        let logger_name = logger.unwrap_or_default();
+        match Level::from(&level) {
+            Level::ERROR => tracing::error!(session_id, logger = logger_name, %data),
+            Level::WARN => tracing::warn!(session_id, logger = logger_name, %data),
+            Level::INFO => tracing::info!(session_id, logger = logger_name, %data),
+            _ => tracing::debug!(session_id, logger = logger_name, %data),
+        }
+
+        self.send_log(session_id, level, logger, data).await
+    }
+
+    /// Stops delivering log messages from `logger` to the client
+    pub fn mute_logger(&self, session_id: &SessionId, logger: &str) -> Result<()> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        client_conn.muted_loggers.insert(logger.to_string());
+
+        Ok(())
+    }
+
+    /// Resumes delivering log messages from `logger` to the client
+    pub fn unmute_logger(&self, session_id: &SessionId, logger: &str) -> Result<()> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        client_conn.muted_loggers.remove(logger);
+
+        Ok(())
+    }
+
+    /// Sets the minimum level of log messages delivered to the client
+    pub(crate) fn set_log_level(
+        &self,
+        session_id: &SessionId,
+        level: schema::LoggingLevel,
+    ) -> Result<()> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        client_conn.log_level = Some(level);
+
+        Ok(())
+    }
+
+    fn is_log_filtered(
+        &self,
+        session_id: &SessionId,
+        level: schema::LoggingLevel,
+        logger: Option<&str>,
+    ) -> Result<bool> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(client_conn.log_level.is_some_and(|min| level < min)
+            || logger.is_some_and(|x| client_conn.muted_loggers.contains(x)))
+    }
+}
@@ -1,17 +1,73 @@
+mod builder;
+mod complexity;
+mod concurrency;
+mod context;
 pub mod error;
-mod notification;
-mod request;
-mod sse;
-mod stdio;
-mod utils;
+mod features;
+mod history;
+mod hooks;
+mod idempotency;
+mod in_flight;
+mod logging;
+pub(crate) mod notification;
+mod outbound;
+mod prompt;
+mod queue;
+mod registry;
+pub(crate) mod request;
+mod resource_cache;
+mod resource_stream;
+pub(crate) mod response;
+mod resume;
+mod service;
+pub(crate) mod sse;
+pub(crate) mod stdio;
+mod subscription;
+mod sweep;
+mod tool_macro;
+pub(crate) mod utils;
 
-use dashmap::DashMap;
+use complexity::ComplexityLimit;
+use dashmap::{mapref::entry::Entry, DashMap};
 use error::{ApiError, Result};
-use std::sync::{Arc, Mutex, RwLock};
+use history::SessionHistory;
+use hooks::Hooks;
+use idempotency::{IdempotencyCache, IdempotencyConfig};
+use outbound::{PendingRequest, ProgressCallback};
+use queue::{MessageReceiver, MessageSender};
+use resource_cache::ResourceCache;
+use resume::DetachedClient;
+use serde_json::Value;
+use service::{BoxMcpService, ServiceLayer};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::AtomicI64;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+use sweep::SweepConfig;
 use tokio::sync::mpsc::{self, Receiver, Sender};
+// Clock of everything time based, so tests can pause and advance it
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 
 use crate::mcp::schema;
 
+pub use builder::ServerBuilder;
+pub use concurrency::ToolOverflowPolicy;
+pub use context::{ProgressReporter, RequestContext, CONTEXT_HEADERS, PARTIAL_RESULT_LOGGER};
+pub use features::{supported_methods, supported_notifications};
+pub use history::{HistoryDirection, HistoryEntry};
+pub use idempotency::IDEMPOTENCY_KEY;
+pub use outbound::OutboundProgress;
+pub use prompt::PromptTemplate;
+pub use queue::NotificationDropPolicy;
+pub use registry::Registry;
+pub use resource_stream::{ResourceChunk, RESOURCE_CHUNKS_CAPABILITY, RESOURCE_CHUNK_METHOD};
+pub use service::McpService;
+pub use tool_macro::ToolArgument;
+#[doc(hidden)]
+pub use tool_macro::{tool_argument, tool_definition};
+
 pub struct Message {
     pub session_id: SessionId,
     pub sse_message: schema::JSONRPCMessage,
@@ -27,43 +83,241 @@ enum InitializeStatus {
 
 type SessionId = String;
 
+/// Messages queued per client before senders wait or notifications are dropped
+const DEFAULT_QUEUE_DEPTH: usize = 32;
+
+/// Idle time before an SSE stream gets a keep-alive comment
+const DEFAULT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Session ids generated for a new session before giving up, e.g. on a generator that keeps
+/// returning an id in use
+const SESSION_ID_ATTEMPTS: usize = 8;
+
+/// Shared handle to a running server, for registering handlers and talking to clients
+/// while it serves
+pub type ServerHandle = Arc<Server>;
+
 #[derive(Debug)]
 pub struct Server {
     port: usize,
-    clients: DashMap<SessionId, Arc<Mutex<ClientConn>>>,
+    // Shared with the close listener so both sides remove from the same map
+    pub(crate) clients: Arc<DashMap<SessionId, Arc<Mutex<ClientConn>>>>,
+    // Sessions sent here are closed by the listener task, e.g. by the idle sweeper
     send_close_client: Sender<SessionId>,
-    name: String,
-    version: String,
-    capabilities: schema::ServerCapabilities,
+    recv_close_client: Mutex<Option<Receiver<SessionId>>>,
+    info: schema::Implementation,
+    instructions: Option<String>,
+    // Describe the registered tools and prompts when there are no explicit instructions
+    generated_instructions: bool,
+    endpoint: String,
+    // Empty or starting with `/` and without a trailing one
+    path_prefix: String,
+    capabilities: RwLock<schema::ServerCapabilities>,
+    capabilities_route: bool,
+    require_initialize: bool,
+    registry: RwLock<Registry>,
+    request_timeout: Option<Duration>,
+    shutdown_timeout: Option<Duration>,
+    keep_alive_interval: Duration,
+    idempotency: Option<IdempotencyConfig>,
+    // Contents of `resources/read` shared by every session, when enabled
+    resource_cache: Option<Mutex<ResourceCache>>,
+    hooks: Hooks,
+    pretty_json: bool,
+    history_capacity: Option<usize>,
+    max_message_size: Option<usize>,
+    complexity_limit: Option<ComplexityLimit>,
+    // Request methods served, all of them when unset
+    enabled_methods: Option<HashSet<String>>,
+    queue_depth: usize,
+    notification_drop_policy: NotificationDropPolicy,
+    progress_interval: Option<Duration>,
+    resource_subscriptions: bool,
+    session_resume_timeout: Option<Duration>,
+    sweep: SweepConfig,
+    // Shared by every tool call, see `ServerBuilder::tool_concurrency_limit`
+    tool_permits: Option<Arc<tokio::sync::Semaphore>>,
+    tool_overflow_policy: ToolOverflowPolicy,
+    service_layer: Option<ServiceLayer>,
+    next_request_id: AtomicI64,
+    started_at: Instant,
 }
 
 impl Server {
     // TODO maybe faster and more memory efficient to just clone th
-    fn new(name: &str, version: &str, port: usize, send: Sender<SessionId>) -> Self {
+    pub(crate) fn new(name: &str, version: &str, port: usize) -> Self {
+        let (send, recv) = mpsc::channel(32);
+
         Self {
-            name: String::from(name),
-            version: String::from(version),
-            port,
-            capabilities: schema::ServerCapabilities {
-                experimental: None,
-                logging: None,
-                prompts: None,
-                resources: None,
-                tools: None,
+            info: schema::Implementation {
+                name: String::from(name),
+                version: String::from(version),
             },
-            clients: DashMap::new(),
+            port,
+            instructions: None,
+            generated_instructions: false,
+            capabilities: RwLock::new(schema::ServerCapabilities::default()),
+            clients: Arc::new(DashMap::new()),
             send_close_client: send,
+            recv_close_client: Mutex::new(Some(recv)),
+            endpoint: String::from("messages"),
+            path_prefix: String::new(),
+            capabilities_route: false,
+            require_initialize: true,
+            registry: RwLock::new(Registry::default()),
+            request_timeout: None,
+            shutdown_timeout: None,
+            keep_alive_interval: DEFAULT_KEEP_ALIVE_INTERVAL,
+            idempotency: None,
+            resource_cache: None,
+            hooks: Hooks::default(),
+            pretty_json: false,
+            history_capacity: None,
+            max_message_size: None,
+            complexity_limit: None,
+            enabled_methods: None,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            notification_drop_policy: NotificationDropPolicy::default(),
+            progress_interval: None,
+            resource_subscriptions: false,
+            session_resume_timeout: None,
+            sweep: SweepConfig::default(),
+            tool_permits: None,
+            tool_overflow_policy: ToolOverflowPolicy::default(),
+            service_layer: None,
+            next_request_id: AtomicI64::new(0),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Name and version reported to clients
+    pub fn info(&self) -> &schema::Implementation {
+        &self.info
+    }
+
+    /// Replaces the capabilities advertised on initialize. Capabilities are only exchanged
+    /// during the handshake, so sessions that already initialized keep the old ones
+    pub fn set_capabilities(&self, capabilities: schema::ServerCapabilities) -> Result<()> {
+        *self
+            .capabilities
+            .write()
+            .map_err(|_| ApiError::PoisonedLock)? = capabilities;
+
+        Ok(())
+    }
+
+    /// Protocol version negotiated with the client on initialize
+    pub fn protocol_version(&self, session_id: &SessionId) -> Result<schema::ProtocolVersion> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(client_conn.protocol_version.clone())
+    }
+
+    /// Experimental capabilities the client sent on initialize, empty if it sent none
+    pub fn client_experimental(&self, session_id: &SessionId) -> Result<HashMap<String, Value>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(client_conn
+            .capabilities
+            .experimental
+            .clone()
+            .unwrap_or_default())
+    }
+
+    /// Names of the experimental capabilities both the client and the server advertise, the
+    /// non-standard features the session can use
+    pub fn shared_experimental(&self, session_id: &SessionId) -> Result<Vec<String>> {
+        let client = self.client_experimental(session_id)?;
+        let server = self
+            .capabilities
+            .read()
+            .map_err(|_| ApiError::PoisonedLock)?;
+
+        let mut shared: Vec<_> = server
+            .experimental
+            .iter()
+            .flatten()
+            .filter(|(name, _)| client.contains_key(*name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        shared.sort();
+
+        Ok(shared)
+    }
+
+    /// Sessions whose client capabilities match `predicate`, e.g. to only send sampling
+    /// requests to clients that support them
+    pub fn sessions_with_capability<F>(&self, predicate: F) -> Result<Vec<SessionId>>
+    where
+        F: Fn(&schema::ClientCapabilities) -> bool,
+    {
+        let mut session_ids = Vec::new();
+
+        for entry in self.clients.iter() {
+            let client_conn = entry.value().lock().map_err(|_| ApiError::PoisonedLock)?;
+            if predicate(&client_conn.capabilities) {
+                session_ids.push(entry.key().clone());
+            }
+        }
+
+        Ok(session_ids)
+    }
+
+    /// Whether requests for `method` are served, see [`ServerBuilder::enabled_methods`]
+    pub(crate) fn is_method_enabled(&self, method: &str) -> bool {
+        matches!(method, "initialize" | "ping")
+            || self
+                .enabled_methods
+                .as_ref()
+                .is_none_or(|enabled| enabled.contains(method))
+    }
+
+    /// Id for a new session from the configured generator
+    pub(crate) fn new_session_id(&self) -> SessionId {
+        match &self.hooks.session_id {
+            Some(generator) => generator(),
+            None => uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Opens a session under a generated id, generating another one if it is already taken
+    /// so a live session is never replaced
+    pub(crate) fn new_session(&self) -> Result<Client> {
+        let mut session_id = self.new_session_id();
+        for _ in 1..SESSION_ID_ATTEMPTS {
+            match self.clients.entry(session_id.clone()) {
+                Entry::Occupied(_) => {
+                    tracing::warn!(session_id, "generated session id already in use");
+                    session_id = self.new_session_id();
+                }
+                Entry::Vacant(entry) => {
+                    let (client_conn, client) = self.client_conn(&session_id);
+                    entry.insert(client_conn);
+                    return Ok(client);
+                }
+            }
         }
+
+        Err(ApiError::SessionIdInUse(session_id))
     }
 
-    fn new_connection(&self, session_id: &str) -> Result<Client> {
-        let (send, recv): (Sender<Message>, Receiver<Message>) = mpsc::channel(32);
+    /// Opens a session under `session_id`, replacing any session that has it
+    #[cfg(test)]
+    pub(crate) fn new_connection(&self, session_id: &str) -> Result<Client> {
+        let (client_conn, client) = self.client_conn(session_id);
 
         {
-            self.clients.insert(
-                session_id.to_string(),
-                Arc::new(Mutex::new(ClientConn::new(session_id, send, None))),
-            );
+            self.clients.insert(session_id.to_string(), client_conn);
             // Drop lock faster
             // self.clients
             //     .write()
@@ -74,10 +328,27 @@ impl Server {
             //     );
         }
 
-        Ok(Client::new(session_id, recv))
+        Ok(client)
+    }
+
+    /// Connection state for a new session and the client end of its queue
+    fn client_conn(&self, session_id: &str) -> (Arc<Mutex<ClientConn>>, Client) {
+        let (send, recv) = queue::channel(self.queue_depth);
+        let client_conn = ClientConn::new(
+            session_id,
+            send,
+            None,
+            self.history_capacity.map(SessionHistory::new),
+        );
+
+        (
+            Arc::new(Mutex::new(client_conn)),
+            Client::new(session_id, recv),
+        )
     }
 
-    fn close_connection(&self, session_id: &SessionId) -> Result<()> {
+    /// Removes the session, returning whether it was still connected
+    pub(crate) fn close_connection(&self, session_id: &SessionId) -> Result<bool> {
         tracing::debug!("close client connection");
 
         // TODO later handler error where you cannot write to map
@@ -86,7 +357,29 @@ impl Server {
         //     .or_else(|_| Err(ApiError::PoisonedLock))?
         //     .remove(session_id);
 
-        self.clients.remove(session_id);
+        let removed = match self.clients.remove(session_id) {
+            Some((_, conn)) => {
+                let cancelled = conn
+                    .lock()
+                    .map_err(|_| ApiError::PoisonedLock)?
+                    .cancel_pending_requests();
+                if !cancelled.is_empty() {
+                    tracing::debug!(
+                        session_id,
+                        count = cancelled.len(),
+                        "cancelled pending requests"
+                    );
+                }
+                true
+            }
+            None => false,
+        };
+
+        if removed {
+            if let Some(on_disconnect) = &self.hooks.on_disconnect {
+                on_disconnect(session_id);
+            }
+        }
 
         {
             // let len = self
@@ -98,61 +391,236 @@ impl Server {
             tracing::debug!("client_map_size" = len);
         }
 
+        Ok(removed)
+    }
+
+    /// Forcibly ends a session, e.g. when its credentials are revoked. Pending requests to
+    /// the client fail and its SSE stream ends once in-flight messages are flushed.
+    ///
+    /// Returns whether the session existed
+    pub fn terminate_session(&self, session_id: &str) -> Result<bool> {
+        tracing::debug!(session_id, "terminating session");
+        self.close_connection(&session_id.to_string())
+    }
+
+    /// Terminates every session, ending their SSE streams
+    pub(crate) fn terminate_all_sessions(&self) -> Result<usize> {
+        let session_ids: Vec<SessionId> = self.clients.iter().map(|x| x.key().clone()).collect();
+        let mut terminated = 0;
+
+        for session_id in session_ids {
+            if self.terminate_session(&session_id)? {
+                terminated += 1;
+            }
+        }
+
+        Ok(terminated)
+    }
+
+    /// Queues a message on the client's channel to be delivered by its transport.
+    /// If the transport is already gone the stale session is dropped and the message discarded
+    pub(crate) async fn send_message(
+        &self,
+        session_id: &SessionId,
+        message: schema::JSONRPCMessage,
+    ) -> Result<()> {
+        let (conn, tx) = {
+            let lock = self
+                .clients
+                .get(session_id)
+                .ok_or(ApiError::MissingClient)?;
+
+            let conn = lock.clone();
+            let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+            if let Some(history) = client_conn.history.as_mut() {
+                history.push(HistoryDirection::Outbound, &message);
+            }
+            (conn, client_conn.send.clone())
+        };
+
+        let message = Message {
+            session_id: session_id.to_owned(),
+            sse_message: message,
+        };
+        let sent = if queue::is_droppable(&message.sse_message) {
+            tx.send_notification(message, self.notification_drop_policy)
+                .await
+                .map(|dropped| {
+                    if let Some(dropped) = dropped {
+                        tracing::warn!(
+                            session_id,
+                            notification = ?dropped.sse_message,
+                            "client queue full, dropping notification"
+                        );
+                    }
+                })
+        } else {
+            tx.send(message).await
+        };
+
+        if sent.is_err() {
+            tracing::debug!(session_id, "client channel closed, dropping message");
+            // Only remove the connection we sent to, the session may have reconnected since
+            self.clients
+                .remove_if(session_id, |_, current| Arc::ptr_eq(current, &conn));
+        }
+
         Ok(())
     }
 
-    async fn listen(
-        clients: DashMap<SessionId, Arc<Mutex<ClientConn>>>,
-        recv_close_client: Receiver<String>,
-    ) {
+    /// Sends a notification to every connected session, e.g. `notifications/tools/list_changed`.
+    /// Sessions that fail to receive it are skipped.
+    ///
+    /// Returns the number of sessions it was sent to
+    pub async fn broadcast_notification(
+        &self,
+        params: schema::NotificationParams,
+    ) -> Result<usize> {
+        let session_ids: Vec<SessionId> = self.clients.iter().map(|x| x.key().clone()).collect();
+        let mut sent = 0;
+
+        for session_id in session_ids {
+            let notification = schema::JSONRPCNotification {
+                json_rpc: schema::JSONRPC_VERSION.into(),
+                params: params.clone(),
+            };
+
+            match self
+                .send_message(
+                    &session_id,
+                    schema::JSONRPCMessage::Notification(notification),
+                )
+                .await
+            {
+                Ok(()) => sent += 1,
+                // Session closed since the ids were collected
+                Err(ApiError::MissingClient) => (),
+                Err(err) => tracing::warn!(session_id, "failed to broadcast notification: {err}"),
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Serializes an outbound message in the configured format. Messages over the size limit
+    /// are replaced with an error so the client is told instead of getting a broken frame
+    ///
+    /// Every message leaving the server goes through here, so this is also where `jsonrpc`
+    /// is forced to 2.0 whatever the message was built with
+    pub(crate) fn serialize_message(
+        &self,
+        message: &schema::JSONRPCMessage,
+    ) -> serde_json::Result<String> {
+        let fixed;
+        let message = if message.json_rpc() == schema::JSONRPC_VERSION {
+            message
+        } else {
+            tracing::warn!(
+                json_rpc = message.json_rpc(),
+                "outbound message with wrong jsonrpc version"
+            );
+            let mut message = message.to_owned();
+            message.set_json_rpc_version();
+            fixed = message;
+            &fixed
+        };
+        let data = self.to_json(message)?;
+
+        match self.max_message_size {
+            Some(max) if data.len() > max => {
+                tracing::warn!(size = data.len(), max, "outbound message too large");
+                self.to_json(&utils::oversized_message(message, data.len(), max))
+            }
+            _ => Ok(data),
+        }
+    }
+
+    fn to_json(&self, message: &schema::JSONRPCMessage) -> serde_json::Result<String> {
+        if self.pretty_json {
+            serde_json::to_string_pretty(message)
+        } else {
+            serde_json::to_string(message)
+        }
+    }
+
+    // Holds the server weakly, the sender lives in it so the channel would never close
+    async fn listen(server: Weak<Server>, recv_close_client: Receiver<String>) {
         let mut rx = recv_close_client;
-        loop {
-            tokio::select! {
-                Some(session_id) = rx.recv() => {
-                    // TODO lock can be poisoned here
-                    clients.remove(&session_id);
-                    // if let Some(mut map) = clients.write().ok() {
-                    //     map.remove(&session_id);
-                    // }
-                },
+        while let Some(session_id) = rx.recv().await {
+            let Some(server) = server.upgrade() else {
+                break;
             };
+            if let Err(err) = server.close_connection(&session_id) {
+                tracing::warn!(session_id, "failed to close session: {err}");
+            }
         }
     }
 
     /// Starts an SSE Server. Moves ownership to function and blocks
     pub async fn serve_sse(name: &str, version: &str, port: usize, endpoint: &str) -> Result<()> {
-        let (send, recv) = mpsc::channel(32);
+        let server = ServerBuilder::new(name, version)
+            .port(port)
+            .endpoint(endpoint)
+            .build();
 
-        let server = Server {
-            name: String::from(name),
-            version: String::from(version),
-            port,
-            capabilities: schema::ServerCapabilities {
-                experimental: None,
-                logging: None,
-                prompts: None,
-                resources: None,
-                tools: None,
-            },
-            clients: DashMap::new(),
-            send_close_client: send,
-        };
+        Arc::new(server).serve().await
+    }
+
+    /// Starts an SSE Server with the built configuration and blocks
+    pub async fn serve(self: Arc<Self>) -> Result<()> {
+        self.serve_with_shutdown(std::future::pending()).await
+    }
+
+    /// Same as [`Server::serve`], but stops accepting connections once `signal` completes and
+    /// returns when open ones are drained, or the shutdown timeout passes
+    pub async fn serve_with_shutdown<F>(self: Arc<Self>, signal: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_listener()?;
+        sse::serve(self, signal).await
+    }
 
-        let clients = server.clients.clone();
-        tokio::spawn(async move { Server::listen(clients, recv) });
+    /// Serves a single session over stdin and stdout until stdin closes. Messages are read
+    /// whole even when they span several lines and are written one per line
+    pub async fn serve_stdio(self: Arc<Self>) -> Result<()> {
+        self.spawn_listener()?;
+        stdio::serve(self).await
+    }
 
-        sse::serve(server, endpoint).await
+    /// The configured SSE routes, with state and tracing, for mounting into an existing axum
+    /// app. The endpoint advertised to clients includes the path the router is nested at.
+    /// Must be called inside a tokio runtime
+    pub fn into_router(self: Arc<Self>) -> Result<axum::Router> {
+        self.spawn_listener()?;
+        Ok(sse::router(self))
+    }
+
+    /// Starts the close listener and the session sweeper, once
+    pub(crate) fn spawn_listener(self: &Arc<Self>) -> Result<()> {
+        let recv = self
+            .recv_close_client
+            .lock()
+            .map_err(|_| ApiError::PoisonedLock)?
+            .take();
+
+        if let Some(recv) = recv {
+            tokio::spawn(Server::listen(Arc::downgrade(self), recv));
+            self.spawn_sweeper();
+        }
+
+        Ok(())
     }
 }
 
 #[derive(Debug)]
-struct Client {
-    recv: Receiver<Message>,
-    session_id: SessionId,
+pub(crate) struct Client {
+    pub(crate) recv: MessageReceiver,
+    pub(crate) session_id: SessionId,
 }
 
 impl Client {
-    fn new(session_id: &str, recv: Receiver<Message>) -> Self {
+    fn new(session_id: &str, recv: MessageReceiver) -> Self {
         Self {
             session_id: String::from(session_id),
             recv,
@@ -162,24 +630,59 @@ impl Client {
 
 impl Drop for Client {
     fn drop(&mut self) {
-        tracing::debug!("Client dropped");
+        tracing::debug!(session_id = self.session_id, "Client dropped");
     }
 }
 
-#[derive(Debug)]
-struct ClientConn {
+pub(crate) struct ClientConn {
     session_id: SessionId,
     initialize_status: InitializeStatus,
-    send: Sender<Message>,
+    send: MessageSender,
     capabilities: schema::ClientCapabilities,
     protocol_version: schema::ProtocolVersion,
+    muted_loggers: HashSet<String>,
+    // Minimum level of log messages delivered, set with `logging/setLevel`
+    log_level: Option<schema::LoggingLevel>,
+    pending_requests: HashMap<schema::RequestId, PendingRequest>,
+    progress_callbacks: HashMap<schema::ProgressToken, ProgressCallback>,
+    idempotency_cache: Option<IdempotencyCache>,
+    history: Option<SessionHistory>,
+    // Inbound requests that have not been answered yet, cancelled if the client gives up
+    in_flight: HashMap<schema::RequestId, CancellationToken>,
+    // Resource uris the client asked `notifications/resources/updated` for
+    subscriptions: HashSet<String>,
+    // Set while the SSE stream is down and the session waits to be resumed
+    detached: Option<DetachedClient>,
+    // Read by the sweeper to reap sessions that never initialize or go quiet
+    connected_at: Instant,
+    last_inbound: Instant,
+    // Layered message handling, built once so stateful layers keep their state for the whole
+    // session. It holds the server, the cycle is broken when the session is removed
+    service: Option<BoxMcpService>,
+}
+
+impl std::fmt::Debug for ClientConn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientConn")
+            .field("session_id", &self.session_id)
+            .field("initialize_status", &self.initialize_status)
+            .field("capabilities", &self.capabilities)
+            .field("protocol_version", &self.protocol_version)
+            .field("muted_loggers", &self.muted_loggers)
+            .field("log_level", &self.log_level)
+            .field("pending_requests", &self.pending_requests.keys())
+            .field("subscriptions", &self.subscriptions)
+            .field("detached", &self.detached.is_some())
+            .finish()
+    }
 }
 
 impl ClientConn {
     fn new(
         session_id: &str,
-        send: Sender<Message>,
+        send: MessageSender,
         capabilities: Option<schema::ClientCapabilities>,
+        history: Option<SessionHistory>,
     ) -> Self {
         Self {
             session_id: session_id.to_string(),
@@ -187,6 +690,18 @@ impl ClientConn {
             send,
             capabilities: capabilities.unwrap_or_default(),
             protocol_version: schema::ProtocolVersion::default(),
+            muted_loggers: HashSet::new(),
+            log_level: None,
+            pending_requests: HashMap::new(),
+            progress_callbacks: HashMap::new(),
+            idempotency_cache: None,
+            history,
+            in_flight: HashMap::new(),
+            subscriptions: HashSet::new(),
+            detached: None,
+            connected_at: Instant::now(),
+            last_inbound: Instant::now(),
+            service: None,
         }
     }
 }
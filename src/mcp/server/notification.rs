@@ -10,11 +10,13 @@ pub fn handle_notification(
     request: &schema::JSONRPCNotification,
     session_id: &SessionId,
 ) -> Result<()> {
+    let mut progress_callback = None;
+
     {
         // let map = server
         //     .clients
         //     .write()
-        //     .or_else(|_| Err(ApiError::PoisonedLock))?;
+        //     .map_err(|_| ApiError::PoisonedLock)?;
 
         // let mut client_conn = map
         //     .get(session_id)
@@ -27,14 +29,59 @@ pub fn handle_notification(
             .get(session_id)
             .ok_or(ApiError::MissingClient)?;
 
-        let mut client_conn = lock.lock().or_else(|_| Err(ApiError::PoisonedLock))?;
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
 
         match request.params {
+            // Only completes an initialize request, so a client can't skip it by notifying
             schema::NotificationParams::Initialized(_) => {
-                client_conn.initialize_status = InitializeStatus::Initialized;
+                if matches!(
+                    client_conn.initialize_status,
+                    InitializeStatus::Initializing
+                ) {
+                    client_conn.initialize_status = InitializeStatus::Initialized;
+                } else {
+                    tracing::debug!(session_id, "ignoring initialized outside initialization");
+                }
+                return Ok(());
+            }
+            // Stops tracking the request so the handler's response is dropped, and tells the
+            // handler through its context. Cancels can race with the response, so ones for
+            // unknown requests are ignored
+            schema::NotificationParams::Cancelled(ref cancelled) => {
+                let id = &cancelled.request_id;
+                let reason = cancelled.reason.as_deref().unwrap_or_default();
+                if let Some(cancellation) = client_conn.in_flight.remove(id) {
+                    cancellation.cancel();
+                    tracing::debug!(session_id, %id, reason, "client cancelled request");
+                } else {
+                    tracing::debug!(session_id, %id, reason, "ignoring cancel of unknown request");
+                }
+                return Ok(());
             }
-            _ => todo!(),
+            schema::NotificationParams::Progress(ref progress) => {
+                progress_callback = client_conn
+                    .progress_callbacks
+                    .get(&progress.progress_token)
+                    .cloned();
+            }
+            _ => (),
         }
     }
+
+    // Callbacks run without holding the client lock
+    match (progress_callback, &request.params) {
+        (Some(callback), schema::NotificationParams::Progress(progress)) => {
+            callback(progress.to_owned());
+        }
+        // Progress for a request that already finished is dropped
+        (None, schema::NotificationParams::Progress(progress)) => {
+            tracing::debug!(session_id, token = ?progress.progress_token, "no request for progress");
+        }
+        (_, params) => match &server.hooks.on_notification {
+            Some(hook) => hook(session_id, params),
+            None => tracing::debug!(session_id, ?params, "ignoring unhandled notification"),
+        },
+    }
+
     Ok(())
 }
@@ -0,0 +1,276 @@
+use serde::de::DeserializeOwned;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+use crate::mcp::schema::{self, JSONRPCMessage};
+
+use super::error::{ApiError, Result};
+use super::{ClientConn, Server, SessionId};
+
+pub(crate) type ProgressCallback = Arc<dyn Fn(schema::ProgressNotificationParams) + Send + Sync>;
+
+/// Outbound request waiting for the client's response
+pub(crate) struct PendingRequest {
+    pub(crate) send: oneshot::Sender<Result<schema::JSONRPCResponse>>,
+    pub(crate) progress_token: Option<schema::ProgressToken>,
+    pub(crate) sent_at: Instant,
+}
+
+/// Progress token attached to an outbound request and the callback receiving the client's
+/// `notifications/progress` for it
+pub struct OutboundProgress {
+    token: schema::ProgressToken,
+    callback: ProgressCallback,
+}
+
+impl OutboundProgress {
+    pub fn new<F>(token: schema::ProgressToken, callback: F) -> Self
+    where
+        F: Fn(schema::ProgressNotificationParams) + Send + Sync + 'static,
+    {
+        Self {
+            token,
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+impl Server {
+    /// Sends a `sampling/createMessage` request to the client and waits for its result
+    pub async fn create_message(
+        &self,
+        session_id: &SessionId,
+        mut params: schema::CreateMessageRequestParams,
+        progress: Option<OutboundProgress>,
+    ) -> Result<schema::CreateMessageResult> {
+        if let Some(progress) = &progress {
+            params.request_base =
+                schema::RequestBaseParams::with_progress_token(progress.token.to_owned());
+        }
+
+        match self
+            .send_server_request(
+                session_id,
+                schema::ServerRequestParams::CreateMessage(params),
+                progress,
+            )
+            .await?
+        {
+            schema::ClientResult::CreateMessage(result) => Ok(result),
+            other => Err(unexpected_result(other)),
+        }
+    }
+
+    /// Sends a `roots/list` request to the client and waits for its result
+    pub async fn list_roots(
+        &self,
+        session_id: &SessionId,
+        mut params: schema::ListRootsRequestParams,
+        progress: Option<OutboundProgress>,
+    ) -> Result<schema::ListRootResult> {
+        if let Some(progress) = &progress {
+            params.request_base =
+                schema::RequestBaseParams::with_progress_token(progress.token.to_owned());
+        }
+
+        match self
+            .send_server_request(
+                session_id,
+                schema::ServerRequestParams::ListRoots(params),
+                progress,
+            )
+            .await?
+        {
+            schema::ClientResult::ListRoot(result) => Ok(result),
+            other => Err(unexpected_result(other)),
+        }
+    }
+
+    /// Sends any server to client request and waits for its typed result
+    pub async fn request(
+        &self,
+        session_id: &SessionId,
+        params: schema::ServerRequestParams,
+    ) -> Result<schema::ClientResult> {
+        self.send_server_request(session_id, params, None).await
+    }
+
+    async fn send_server_request(
+        &self,
+        session_id: &SessionId,
+        params: schema::ServerRequestParams,
+        progress: Option<OutboundProgress>,
+    ) -> Result<schema::ClientResult> {
+        // Results are untagged on the wire, so the request decides which shape to expect
+        let decode = match &params {
+            schema::ServerRequestParams::Ping(_) => {
+                |x| parse_result(x).map(schema::ClientResult::Empty)
+            }
+            schema::ServerRequestParams::CreateMessage(_) => {
+                |x| parse_result(x).map(schema::ClientResult::CreateMessage)
+            }
+            schema::ServerRequestParams::ListRoots(_) => {
+                |x| parse_result(x).map(schema::ClientResult::ListRoot)
+            }
+        };
+
+        let response = self
+            .send_request(session_id, params.into(), progress)
+            .await?;
+
+        decode(response)
+    }
+
+    /// Sends a request to the client and waits for the correlated response
+    pub(crate) async fn send_request(
+        &self,
+        session_id: &SessionId,
+        params: schema::RequestParams,
+        progress: Option<OutboundProgress>,
+    ) -> Result<schema::JSONRPCResponse> {
+        let id = schema::RequestId::Number(self.next_request_id.fetch_add(1, Ordering::Relaxed));
+        let (send, recv) = oneshot::channel();
+
+        {
+            let lock = self
+                .clients
+                .get(session_id)
+                .ok_or(ApiError::MissingClient)?;
+
+            let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+            let progress_token = progress.map(|progress| {
+                client_conn
+                    .progress_callbacks
+                    .insert(progress.token.to_owned(), progress.callback);
+                progress.token
+            });
+
+            client_conn.pending_requests.insert(
+                id.to_owned(),
+                PendingRequest {
+                    send,
+                    progress_token,
+                    sent_at: Instant::now(),
+                },
+            );
+        }
+
+        // Same span as the response handler so both ends of the exchange share the id
+        tracing::debug_span!("outbound_request", session_id, %id)
+            .in_scope(|| tracing::debug!("sending request to client"));
+
+        let request = schema::JSONRPCRequest {
+            json_rpc: schema::JSONRPC_VERSION.into(),
+            id,
+            params,
+        };
+
+        // Forgets the request if sending fails or the caller stops waiting
+        let _pending = PendingGuard {
+            server: self,
+            session_id,
+            id: request.id.to_owned(),
+        };
+
+        self.send_message(session_id, JSONRPCMessage::Request(request))
+            .await?;
+
+        // Sender is dropped if the session goes away before the client answers
+        recv.await.map_err(|_| ApiError::SessionClosed)?
+    }
+
+    /// Ids of the requests sent to the client that are still waiting for a response
+    pub fn pending_requests(&self, session_id: &SessionId) -> Result<Vec<schema::RequestId>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        Ok(client_conn.pending_requests.keys().cloned().collect())
+    }
+
+    /// Fails every request still waiting on the client with [`ApiError::SessionClosed`],
+    /// returning the ids that were cancelled
+    pub fn cancel_pending_requests(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Vec<schema::RequestId>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        Ok(client_conn.cancel_pending_requests())
+    }
+}
+
+impl ClientConn {
+    /// Stops waiting for the response to an outbound request, along with its progress
+    pub(crate) fn take_pending_request(
+        &mut self,
+        id: &schema::RequestId,
+    ) -> Option<PendingRequest> {
+        let pending = self.pending_requests.remove(id)?;
+        if let Some(token) = &pending.progress_token {
+            self.progress_callbacks.remove(token);
+        }
+
+        Some(pending)
+    }
+    /// Wakes every awaiter of an outbound request with [`ApiError::SessionClosed`]
+    pub(crate) fn cancel_pending_requests(&mut self) -> Vec<schema::RequestId> {
+        self.pending_requests
+            .drain()
+            .map(|(id, pending)| {
+                if let Some(token) = &pending.progress_token {
+                    self.progress_callbacks.remove(token);
+                }
+                // Requester may have stopped waiting
+                _ = pending.send.send(Err(ApiError::SessionClosed));
+                id
+            })
+            .collect()
+    }
+}
+
+fn unexpected_result(result: schema::ClientResult) -> ApiError {
+    ApiError::InvalidResponse(format!("Unexpected result {result:?}"))
+}
+
+struct PendingGuard<'a> {
+    server: &'a Server,
+    session_id: &'a SessionId,
+    id: schema::RequestId,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        // Already gone once the client answered or the session closed
+        if let Some(lock) = self.server.clients.get(self.session_id) {
+            if let Ok(mut client_conn) = lock.lock() {
+                client_conn.take_pending_request(&self.id);
+            }
+        }
+    }
+}
+
+/// Extracts the typed result of a client response
+fn parse_result<T: DeserializeOwned>(response: schema::JSONRPCResponse) -> Result<T> {
+    match response {
+        schema::JSONRPCResponse::Result(res) => {
+            // Results are untagged, so decode the wire shape into the type we asked for
+            let value = serde_json::to_value(res.result)
+                .map_err(|err| ApiError::InvalidResponse(err.to_string()))?;
+
+            serde_json::from_value(value).map_err(|err| ApiError::InvalidResponse(err.to_string()))
+        }
+        schema::JSONRPCResponse::Error(err) => Err(ApiError::ClientError {
+            code: err.error.code,
+            message: err.error.message,
+        }),
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::Server;
+
+/// Prompt text with `{name}` placeholders filled from the `prompts/get` arguments.
+/// Declared optional arguments left out by the client render as empty, other unfilled
+/// placeholders are kept as is unless the template is strict
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    template: String,
+    strict: bool,
+}
+
+impl PromptTemplate {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            strict: false,
+        }
+    }
+
+    /// Rejects the request when a placeholder matches neither an argument nor a declared one
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub(crate) fn render(
+        &self,
+        prompt: &schema::Prompt,
+        arguments: &HashMap<String, String>,
+    ) -> Result<String> {
+        let declared = prompt.arguments.as_deref().unwrap_or_default();
+
+        if let Some(missing) = declared
+            .iter()
+            .find(|x| x.required == Some(true) && !arguments.contains_key(&x.name))
+        {
+            return Err(ApiError::InvalidParams(format!(
+                "Missing required argument: {}",
+                missing.name
+            )));
+        }
+
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(name) = rest[1..].split_once('}').map(|(name, _)| name) else {
+                break;
+            };
+            if !is_placeholder(name) {
+                rendered.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+
+            match arguments.get(name) {
+                Some(value) => rendered.push_str(value),
+                None if declared.iter().any(|x| x.name == name) => (),
+                None if self.strict => {
+                    return Err(ApiError::InvalidParams(format!(
+                        "Unknown placeholder: {name}"
+                    )))
+                }
+                None => rendered.push_str(&rest[..name.len() + 2]),
+            }
+            rest = &rest[name.len() + 2..];
+        }
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+}
+
+impl From<&str> for PromptTemplate {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+fn is_placeholder(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-')
+}
+
+impl Server {
+    /// Registers a prompt rendered from `template` as a single user message, without writing
+    /// a handler. Missing required arguments are rejected with `INVALID_PARAMS`
+    pub fn register_static_prompt(
+        &self,
+        prompt: schema::Prompt,
+        template: impl Into<PromptTemplate>,
+    ) -> Result<()> {
+        let template = template.into();
+        let declared = prompt.clone();
+
+        self.register_prompt(prompt, move |arguments| {
+            let text = template.render(&declared, &arguments)?;
+            Ok(vec![schema::PromptMessage::text(schema::Role::User, &text)])
+        })
+    }
+}
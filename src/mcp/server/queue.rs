@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::{SendError, TryRecvError};
+use tokio::sync::Notify;
+
+use crate::mcp::schema::{JSONRPCMessage, NotificationParams};
+
+use super::resource_stream::RESOURCE_CHUNK_METHOD;
+use super::Message;
+
+/// What happens to a notification sent to a client whose queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotificationDropPolicy {
+    /// Wait for the client to make room
+    #[default]
+    Block,
+    /// Discard the notification being sent
+    DropNewest,
+    /// Discard the oldest notification still queued. Responses and requests are never
+    /// discarded, if only those are queued the new notification is dropped instead
+    DropOldest,
+}
+
+/// Whether the drop policy applies to `message`. Resource chunks are notifications, but the
+/// read they belong to is incomplete without every one of them
+pub(crate) fn is_droppable(message: &JSONRPCMessage) -> bool {
+    match message {
+        JSONRPCMessage::Notification(notification) => !matches!(
+            &notification.params,
+            NotificationParams::Custom(params) if params.method == RESOURCE_CHUNK_METHOD
+        ),
+        _ => false,
+    }
+}
+
+/// Bounded queue of messages for one client. Works like a tokio mpsc channel, but the
+/// sending side can also evict queued notifications when it is full
+pub(crate) fn channel(capacity: usize) -> (MessageSender, MessageReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            messages: VecDeque::with_capacity(capacity),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        capacity: capacity.max(1),
+        sent: Notify::new(),
+        received: Notify::new(),
+    });
+
+    (
+        MessageSender {
+            shared: shared.clone(),
+        },
+        MessageReceiver { shared },
+    )
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    // Wakes the receiver after a push or when the last sender goes away
+    sent: Notify,
+    // Wakes blocked senders after a pop or when the receiver goes away
+    received: Notify,
+}
+
+struct State {
+    messages: VecDeque<Message>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+impl Shared {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // Nothing panics while holding the lock, recover the data rather than poisoning sends
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn pop(&self) -> Result<Message, TryRecvError> {
+        let mut state = self.state();
+
+        match state.messages.pop_front() {
+            Some(message) => {
+                self.received.notify_waiters();
+                Ok(message)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+pub(crate) struct MessageSender {
+    shared: Arc<Shared>,
+}
+
+impl MessageSender {
+    /// Waits for room in the queue. Fails if the receiver is gone
+    pub(crate) async fn send(&self, message: Message) -> Result<(), SendError<Message>> {
+        loop {
+            let received = self.shared.received.notified();
+            tokio::pin!(received);
+            received.as_mut().enable();
+
+            {
+                let mut state = self.shared.state();
+                if !state.receiver_alive {
+                    return Err(SendError(message));
+                }
+                if state.messages.len() < self.shared.capacity {
+                    state.messages.push_back(message);
+                    self.shared.sent.notify_waiters();
+                    return Ok(());
+                }
+            }
+
+            received.await;
+        }
+    }
+
+    /// Queues a notification according to `policy`, returning the notification that was
+    /// discarded to make room, if any
+    pub(crate) async fn send_notification(
+        &self,
+        message: Message,
+        policy: NotificationDropPolicy,
+    ) -> Result<Option<Message>, SendError<Message>> {
+        if policy == NotificationDropPolicy::Block {
+            return self.send(message).await.map(|_| None);
+        }
+
+        let mut state = self.shared.state();
+        if !state.receiver_alive {
+            return Err(SendError(message));
+        }
+        if state.messages.len() < self.shared.capacity {
+            state.messages.push_back(message);
+            self.shared.sent.notify_waiters();
+            return Ok(None);
+        }
+
+        let oldest = state
+            .messages
+            .iter()
+            .position(|x| is_droppable(&x.sse_message));
+
+        match (policy, oldest) {
+            (NotificationDropPolicy::DropOldest, Some(index)) => {
+                let dropped = state.messages.remove(index);
+                state.messages.push_back(message);
+                self.shared.sent.notify_waiters();
+                Ok(dropped)
+            }
+            _ => Ok(Some(message)),
+        }
+    }
+}
+
+impl Clone for MessageSender {
+    fn clone(&self) -> Self {
+        self.shared.state().senders += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for MessageSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.sent.notify_waiters();
+        }
+    }
+}
+
+impl std::fmt::Debug for MessageSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageSender")
+            .field("capacity", &self.shared.capacity)
+            .finish()
+    }
+}
+
+pub(crate) struct MessageReceiver {
+    shared: Arc<Shared>,
+}
+
+impl MessageReceiver {
+    /// Waits for the next message. Returns `None` once every sender is gone and the queue
+    /// is drained
+    pub(crate) async fn recv(&mut self) -> Option<Message> {
+        loop {
+            let sent = self.shared.sent.notified();
+            tokio::pin!(sent);
+            sent.as_mut().enable();
+
+            match self.shared.pop() {
+                Ok(message) => return Some(message),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => sent.await,
+            }
+        }
+    }
+
+    pub(crate) fn try_recv(&mut self) -> Result<Message, TryRecvError> {
+        self.shared.pop()
+    }
+}
+
+impl Drop for MessageReceiver {
+    fn drop(&mut self) {
+        self.shared.state().receiver_alive = false;
+        self.shared.received.notify_waiters();
+    }
+}
+
+impl std::fmt::Debug for MessageReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageReceiver")
+            .field("capacity", &self.shared.capacity)
+            .finish()
+    }
+}
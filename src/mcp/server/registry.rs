@@ -0,0 +1,620 @@
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::resource_stream::{ResourceChunk, RESOURCE_CHUNKS_CAPABILITY};
+use super::{RequestContext, Server};
+
+pub(crate) type ToolHandler = Arc<
+    dyn Fn(
+            RequestContext,
+            Option<HashMap<String, Value>>,
+        ) -> BoxFuture<'static, Result<schema::CallToolResult>>
+        + Send
+        + Sync,
+>;
+
+pub(crate) type PromptHandler = Arc<
+    dyn Fn(RequestContext, HashMap<String, String>) -> Result<Vec<schema::PromptMessage>>
+        + Send
+        + Sync,
+>;
+
+pub(crate) type ResourceHandler = Arc<
+    dyn Fn(RequestContext, String) -> BoxFuture<'static, Result<schema::ReadResourceResult>>
+        + Send
+        + Sync,
+>;
+
+pub(crate) type ResourceStreamHandler =
+    Arc<dyn Fn(String) -> BoxStream<'static, Result<ResourceChunk>> + Send + Sync>;
+
+/// How a resource produces its contents on `resources/read`
+#[derive(Clone)]
+pub(crate) enum ResourceReader {
+    Whole(ResourceHandler),
+    Stream(ResourceStreamHandler),
+}
+
+pub(crate) type CompletionHandler = Arc<
+    dyn Fn(RequestContext, schema::CompleteRequestArgument) -> Result<schema::CompleteResult>
+        + Send
+        + Sync,
+>;
+
+pub(crate) type CustomMethodHandler =
+    Arc<dyn Fn(RequestContext, Value) -> BoxFuture<'static, Result<Value>> + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) struct RegisteredTool {
+    pub(crate) tool: schema::Tool,
+    pub(crate) handler: ToolHandler,
+    pub(crate) timeout: Option<Duration>,
+    // Limits calls of this tool running at once
+    pub(crate) permits: Option<Arc<Semaphore>>,
+}
+
+#[derive(Clone)]
+pub(crate) struct RegisteredPrompt {
+    pub(crate) prompt: schema::Prompt,
+    pub(crate) handler: PromptHandler,
+}
+
+#[derive(Clone)]
+pub(crate) struct RegisteredResource {
+    pub(crate) resource: schema::Resource,
+    pub(crate) reader: ResourceReader,
+}
+
+/// Tools, prompts and resources exposed by the server, keyed by name or uri. Ordered so list
+/// responses are stable across calls.
+///
+/// Built by registering on a staging [`Server`] and taking its registry with
+/// [`Server::into_registry`], then installed with [`Server::replace_registry`]
+#[derive(Default, Clone)]
+pub struct Registry {
+    pub(crate) tools: BTreeMap<String, RegisteredTool>,
+    pub(crate) prompts: BTreeMap<String, RegisteredPrompt>,
+    pub(crate) resources: BTreeMap<String, RegisteredResource>,
+    pub(crate) resource_templates: BTreeMap<String, schema::ResourceTemplate>,
+    pub(crate) completions: HashMap<schema::CompleteRequestRef, CompletionHandler>,
+    pub(crate) custom_methods: HashMap<String, CustomMethodHandler>,
+}
+
+impl Registry {
+    /// Whether `reference` points at a registered prompt, resource or resource template
+    pub(crate) fn contains_ref(&self, reference: &schema::CompleteRequestRef) -> bool {
+        match reference {
+            schema::CompleteRequestRef::Prompt { name } => self.prompts.contains_key(name),
+            schema::CompleteRequestRef::Resource { uri } => {
+                self.resources.contains_key(uri) || self.resource_templates.contains_key(uri)
+            }
+        }
+    }
+}
+
+fn validate_tool(tool: &schema::Tool) -> Result<()> {
+    if tool.name.trim().is_empty() {
+        return Err(ApiError::InvalidTool("tool name is empty".to_string()));
+    }
+
+    for schema in std::iter::once(&tool.input_schema).chain(&tool.output_schema) {
+        let schema::ToolInputSchemaType::Object(object) = schema;
+        let properties = object.properties.as_ref();
+        if let Some(missing) = object
+            .required
+            .iter()
+            .find(|x| !properties.is_some_and(|properties| properties.contains_key(*x)))
+        {
+            return Err(ApiError::InvalidTool(format!(
+                "{} requires {missing}, which is not in its properties",
+                tool.name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks a successful result of a tool with an output schema carries `structuredContent`
+/// with the required properties. Property types are not checked
+pub(crate) fn validate_tool_output(
+    output_schema: Option<&schema::ToolInputSchemaType>,
+    result: &schema::CallToolResult,
+) -> std::result::Result<(), String> {
+    let Some(schema::ToolInputSchemaType::Object(output_schema)) = output_schema else {
+        return Ok(());
+    };
+    if result.is_error == Some(true) {
+        return Ok(());
+    }
+
+    let Some(Value::Object(structured)) = &result.structured_content else {
+        return Err("Tool declares an output schema but returned no structured content".into());
+    };
+    match output_schema
+        .required
+        .iter()
+        .find(|x| !structured.contains_key(*x))
+    {
+        Some(missing) => Err(format!("Structured content is missing {missing}")),
+        None => Ok(()),
+    }
+}
+
+fn parse_arguments<A: DeserializeOwned>(arguments: Option<HashMap<String, Value>>) -> Result<A> {
+    let arguments = Value::Object(arguments.unwrap_or_default().into_iter().collect());
+
+    serde_path_to_error::deserialize(arguments).map_err(|err| ApiError::InvalidArguments {
+        path: err.path().to_string(),
+        message: err.inner().to_string(),
+    })
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registry")
+            .field("tools", &self.tools.keys())
+            .field("prompts", &self.prompts.keys())
+            .field("resources", &self.resources.keys())
+            .field("resource_templates", &self.resource_templates.keys())
+            .field("completions", &self.completions.keys())
+            .field("custom_methods", &self.custom_methods.keys())
+            .finish()
+    }
+}
+
+impl Server {
+    /// Registers a tool served by `tools/list` and `tools/call`. Fails if the name is empty or
+    /// taken, or the input schema requires a property it doesn't define.
+    ///
+    /// Handlers live in the shared server and run on any runtime thread, so the closure must be
+    /// `Send + Sync + 'static` and the future it returns `Send + 'static`. This holds for every
+    /// registration method and is checked at the call, e.g. capturing an `Rc` is rejected:
+    ///
+    /// ```compile_fail
+    /// # use rust_mcp::prelude::*;
+    /// # let server = ServerBuilder::new("test", "0.1").build();
+    /// # let tool: schema::Tool = serde_json::from_value(serde_json::json!({
+    /// #     "name": "count", "inputSchema": { "type": "object", "required": [] }
+    /// # })).unwrap();
+    /// let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+    /// server.register_tool(tool, move |_| {
+    ///     calls.set(calls.get() + 1);
+    ///     async { Ok(schema::CallToolResult::text("counted")) }
+    /// });
+    /// ```
+    ///
+    /// As is holding one across an `.await` in the returned future:
+    ///
+    /// ```compile_fail
+    /// # use rust_mcp::prelude::*;
+    /// # let server = ServerBuilder::new("test", "0.1").build();
+    /// # let tool: schema::Tool = serde_json::from_value(serde_json::json!({
+    /// #     "name": "wait", "inputSchema": { "type": "object", "required": [] }
+    /// # })).unwrap();
+    /// server.register_tool(tool, |_| async {
+    ///     let label = std::rc::Rc::new("waited");
+    ///     tokio::task::yield_now().await;
+    ///     Ok(schema::CallToolResult::text(&label))
+    /// });
+    /// ```
+    pub fn register_tool<F, Fut>(&self, tool: schema::Tool, handler: F) -> Result<()>
+    where
+        F: Fn(Option<HashMap<String, Value>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<schema::CallToolResult>> + Send + 'static,
+    {
+        self.register_tool_with_timeout(tool, None, handler)
+    }
+
+    /// Same as [`Server::register_tool`], but the arguments are deserialized into `A` first.
+    /// Arguments that don't fit are rejected with `INVALID_PARAMS`, naming the path of the
+    /// offending field in the error `data`
+    pub fn register_typed_tool<A, F, Fut>(&self, tool: schema::Tool, handler: F) -> Result<()>
+    where
+        A: DeserializeOwned + Send + 'static,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<schema::CallToolResult>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+
+        self.register_tool(tool, move |arguments| {
+            let handler = handler.clone();
+            async move { handler(parse_arguments(arguments)?).await }
+        })
+    }
+
+    /// Same as [`Server::register_tool`], but calls running longer than `timeout` are cancelled.
+    /// Overrides the server request timeout for this tool
+    pub fn register_tool_with_timeout<F, Fut>(
+        &self,
+        tool: schema::Tool,
+        timeout: Option<Duration>,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(Option<HashMap<String, Value>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<schema::CallToolResult>> + Send + 'static,
+    {
+        self.register_tool_with_context(tool, timeout, move |_, args| handler(args))
+    }
+
+    /// Same as [`Server::register_tool_with_timeout`], but the handler also gets the
+    /// [`RequestContext`] of the call, e.g. to report progress or stop once the client cancels
+    pub fn register_tool_with_context<F, Fut>(
+        &self,
+        tool: schema::Tool,
+        timeout: Option<Duration>,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(RequestContext, Option<HashMap<String, Value>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<schema::CallToolResult>> + Send + 'static,
+    {
+        let handler: ToolHandler = Arc::new(move |context, args| Box::pin(handler(context, args)));
+        self.insert_tool(tool, timeout, handler)
+    }
+
+    fn insert_tool(
+        &self,
+        tool: schema::Tool,
+        timeout: Option<Duration>,
+        handler: ToolHandler,
+    ) -> Result<()> {
+        validate_tool(&tool)?;
+
+        let mut registry = self.registry.write().map_err(|_| ApiError::PoisonedLock)?;
+        if registry.tools.contains_key(&tool.name) {
+            return Err(ApiError::InvalidTool(format!(
+                "{} is already registered",
+                tool.name
+            )));
+        }
+
+        registry.tools.insert(
+            tool.name.clone(),
+            RegisteredTool {
+                tool,
+                handler,
+                timeout,
+                permits: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Registers a prompt served by `prompts/list` and `prompts/get`. The handler builds the
+    /// prompt messages from the request arguments and has the same `Send + Sync + 'static`
+    /// bounds as tool handlers
+    pub fn register_prompt<F>(&self, prompt: schema::Prompt, handler: F) -> Result<()>
+    where
+        F: Fn(HashMap<String, String>) -> Result<Vec<schema::PromptMessage>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.register_prompt_with_context(prompt, move |_, arguments| handler(arguments))
+    }
+
+    /// Same as [`Server::register_prompt`], but the handler also gets the [`RequestContext`]
+    /// of the request
+    pub fn register_prompt_with_context<F>(&self, prompt: schema::Prompt, handler: F) -> Result<()>
+    where
+        F: Fn(RequestContext, HashMap<String, String>) -> Result<Vec<schema::PromptMessage>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.registry
+            .write()
+            .map_err(|_| ApiError::PoisonedLock)?
+            .prompts
+            .insert(
+                prompt.name.clone(),
+                RegisteredPrompt {
+                    prompt,
+                    handler: Arc::new(handler),
+                },
+            );
+
+        Ok(())
+    }
+
+    /// Registers a resource served by `resources/list` and `resources/read`. The handler
+    /// receives the requested uri. Like tool handlers, it and its future must be `Send` and
+    /// `'static`
+    pub fn register_resource<F, Fut>(&self, resource: schema::Resource, handler: F) -> Result<()>
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<schema::ReadResourceResult>> + Send + 'static,
+    {
+        self.register_resource_with_context(resource, move |_, uri| handler(uri))
+    }
+
+    /// Same as [`Server::register_resource`], but the handler also gets the [`RequestContext`]
+    /// of the read
+    pub fn register_resource_with_context<F, Fut>(
+        &self,
+        resource: schema::Resource,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(RequestContext, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<schema::ReadResourceResult>> + Send + 'static,
+    {
+        let handler: ResourceHandler =
+            Arc::new(move |context, uri| Box::pin(handler(context, uri)));
+
+        self.insert_resource(resource, ResourceReader::Whole(handler))
+    }
+
+    pub(crate) fn insert_resource(
+        &self,
+        resource: schema::Resource,
+        reader: ResourceReader,
+    ) -> Result<()> {
+        let mut registry = self.registry.write().map_err(|_| ApiError::PoisonedLock)?;
+        self.invalidate_resource(&resource.uri)?;
+        registry.resources.insert(
+            resource.uri.clone(),
+            RegisteredResource { resource, reader },
+        );
+
+        Ok(())
+    }
+
+    /// Registers a resource template served by `resources/templates/list`. Templates can be
+    /// referenced by completions
+    pub fn register_resource_template(&self, template: schema::ResourceTemplate) -> Result<()> {
+        self.registry
+            .write()
+            .map_err(|_| ApiError::PoisonedLock)?
+            .resource_templates
+            .insert(template.uri_template.clone(), template);
+
+        Ok(())
+    }
+
+    /// Registers the `completion/complete` provider for a prompt or resource reference. The
+    /// provider only runs while the referenced prompt, resource or template is registered.
+    /// It is shared across threads, hence `Send + Sync + 'static`
+    pub fn register_completion<F>(
+        &self,
+        reference: schema::CompleteRequestRef,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(schema::CompleteRequestArgument) -> Result<schema::CompleteResult>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.register_completion_with_context(reference, move |_, argument| handler(argument))
+    }
+
+    /// Same as [`Server::register_completion`], but the provider also gets the
+    /// [`RequestContext`] of the request
+    pub fn register_completion_with_context<F>(
+        &self,
+        reference: schema::CompleteRequestRef,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(RequestContext, schema::CompleteRequestArgument) -> Result<schema::CompleteResult>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.registry
+            .write()
+            .map_err(|_| ApiError::PoisonedLock)?
+            .completions
+            .insert(reference, Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Registers a handler for a method outside the spec, e.g. an experimental extension.
+    /// The handler gets the raw params (`null` when absent) and returns the result object.
+    /// Handlers registered for spec methods are never called. Bounds are those of
+    /// [`Server::register_tool`]
+    pub fn register_method<F, Fut>(&self, method: &str, handler: F) -> Result<()>
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.register_method_with_context(method, move |_, params| handler(params))
+    }
+
+    /// Same as [`Server::register_method`], but the handler also gets the [`RequestContext`]
+    /// of the request
+    pub fn register_method_with_context<F, Fut>(&self, method: &str, handler: F) -> Result<()>
+    where
+        F: Fn(RequestContext, Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        let handler: CustomMethodHandler =
+            Arc::new(move |context, params| Box::pin(handler(context, params)));
+
+        self.registry
+            .write()
+            .map_err(|_| ApiError::PoisonedLock)?
+            .custom_methods
+            .insert(method.to_string(), handler);
+
+        Ok(())
+    }
+
+    /// Instructions sent on initialize, generated from the registry if enabled and none were set
+    pub(crate) fn initialize_instructions(&self) -> Result<Option<String>> {
+        if self.instructions.is_some() || !self.generated_instructions {
+            return Ok(self.instructions.clone());
+        }
+
+        let registry = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+        let describe = |name: &str, description: Option<&String>| match description {
+            Some(description) => format!("- {name}: {description}"),
+            None => format!("- {name}"),
+        };
+
+        let mut sections = Vec::new();
+        if !registry.tools.is_empty() {
+            let tools = registry
+                .tools
+                .values()
+                .map(|x| describe(&x.tool.name, x.tool.description.as_ref()));
+            sections.push(format!(
+                "Available tools:\n{}",
+                tools.collect::<Vec<_>>().join("\n")
+            ));
+        }
+        if !registry.prompts.is_empty() {
+            let prompts = registry
+                .prompts
+                .values()
+                .map(|x| describe(&x.prompt.name, x.prompt.description.as_ref()));
+            sections.push(format!(
+                "Available prompts:\n{}",
+                prompts.collect::<Vec<_>>().join("\n")
+            ));
+        }
+
+        Ok((!sections.is_empty()).then(|| sections.join("\n\n")))
+    }
+
+    /// Capabilities sent on initialize. Features with registered entries are advertised even
+    /// if they were not configured explicitly
+    pub(crate) fn advertised_capabilities(&self) -> Result<schema::ServerCapabilities> {
+        let registry = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+        let mut capabilities = self
+            .capabilities
+            .read()
+            .map_err(|_| ApiError::PoisonedLock)?
+            .clone();
+
+        if !registry.tools.is_empty() {
+            capabilities.tools.get_or_insert_with(HashMap::new);
+        }
+        if !registry.prompts.is_empty() {
+            capabilities.prompts.get_or_insert_with(HashMap::new);
+        }
+        if !registry.resources.is_empty() || !registry.resource_templates.is_empty() {
+            capabilities.resources.get_or_insert_with(HashMap::new);
+        }
+        if self.resource_subscriptions {
+            capabilities
+                .resources
+                .get_or_insert_with(HashMap::new)
+                .entry("subscribe".to_string())
+                .or_insert(Value::Bool(true));
+        }
+        if registry
+            .resources
+            .values()
+            .any(|x| matches!(x.reader, ResourceReader::Stream(_)))
+        {
+            capabilities
+                .experimental
+                .get_or_insert_with(HashMap::new)
+                .entry(RESOURCE_CHUNKS_CAPABILITY.to_string())
+                .or_insert_with(|| Value::Object(Default::default()));
+        }
+
+        Ok(capabilities)
+    }
+}
+
+impl Server {
+    /// Names of the registered tools, sorted
+    pub fn tool_names(&self) -> Result<Vec<String>> {
+        let registry = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(registry.tools.keys().cloned().collect())
+    }
+
+    /// Names of the registered prompts, sorted
+    pub fn prompt_names(&self) -> Result<Vec<String>> {
+        let registry = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(registry.prompts.keys().cloned().collect())
+    }
+
+    /// Uris of the registered resources, sorted. Templates are not included
+    pub fn resource_uris(&self) -> Result<Vec<String>> {
+        let registry = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(registry.resources.keys().cloned().collect())
+    }
+
+    /// Copy of the current tools, prompts and resources, e.g. to restore them after a reload
+    pub fn snapshot_registry(&self) -> Result<Registry> {
+        let registry = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        Ok(registry.clone())
+    }
+
+    /// Takes the registry of a staging server that was only used for registration
+    pub fn into_registry(self) -> Result<Registry> {
+        self.registry
+            .into_inner()
+            .map_err(|_| ApiError::PoisonedLock)
+    }
+
+    /// Atomically installs `registry` in place of the current one without dropping sessions,
+    /// returning the old one. Sessions get a `list_changed` notification for each of the
+    /// tool, prompt and resource lists that differ. Calls already running keep the handler
+    /// they started with, new calls use the new registry
+    pub async fn replace_registry(&self, registry: Registry) -> Result<Registry> {
+        let old = {
+            let mut current = self.registry.write().map_err(|_| ApiError::PoisonedLock)?;
+            // Under the registry lock, so no read pairs an old handler with the new cache
+            self.invalidate_resources()?;
+            std::mem::replace(&mut *current, registry)
+        };
+
+        let changed = {
+            let new = self.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+            let tools = |x: &Registry| x.tools.values().map(|x| x.tool.clone()).collect::<Vec<_>>();
+            let prompts = |x: &Registry| {
+                x.prompts
+                    .values()
+                    .map(|x| x.prompt.clone())
+                    .collect::<Vec<_>>()
+            };
+            let resources = |x: &Registry| {
+                (
+                    x.resources
+                        .values()
+                        .map(|x| x.resource.clone())
+                        .collect::<Vec<_>>(),
+                    x.resource_templates.clone(),
+                )
+            };
+
+            [
+                (tools(&old) != tools(&new))
+                    .then(|| schema::NotificationParams::ToolListChanged(Default::default())),
+                (prompts(&old) != prompts(&new))
+                    .then(|| schema::NotificationParams::PromptListChanged(Default::default())),
+                (resources(&old) != resources(&new))
+                    .then(|| schema::NotificationParams::ResourceListChanged(Default::default())),
+            ]
+        };
+
+        for params in changed.into_iter().flatten() {
+            self.broadcast_notification(params).await?;
+        }
+
+        Ok(old)
+    }
+}
@@ -1,100 +1,690 @@
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
 use crate::mcp::schema::{self, JSONRPCMessage};
 
+use super::concurrency;
+use super::context::progress_token;
 use super::error::{ApiError, Result};
-use super::utils::create_error_response;
+use super::in_flight::duplicate_request_response;
+use super::registry::{validate_tool_output, ResourceReader};
+use super::resource_cache::CachedResource;
+use super::subscription::handle_subscribe;
+use super::utils::{
+    catch_panic_async, create_error_response, create_result_response, effective_timeout,
+    error_response_for, tool_error_result, with_timeout, with_timeout_blocking,
+};
 use super::InitializeStatus;
+use super::{ProgressReporter, RequestContext};
 use super::{Server, SessionId};
 
-pub fn handle_request(
+/// Handles a request that came without transport headers
+#[cfg(test)]
+pub async fn handle_request(
     server: &Server,
     request: &schema::JSONRPCRequest,
     session_id: &SessionId,
 ) -> Result<JSONRPCMessage> {
-    {
-        // let map = server
-        //     .clients
-        //     .write()
-        //     .or_else(|_| Err(ApiError::PoisonedLock))?;
+    handle_request_with_headers(server, request, session_id, HeaderMap::new()).await
+}
+
+/// Handles a request, with the transport headers handed to its context
+pub(crate) async fn handle_request_with_headers(
+    server: &Server,
+    request: &schema::JSONRPCRequest,
+    session_id: &SessionId,
+    headers: HeaderMap,
+) -> Result<JSONRPCMessage> {
+    // Null is only for responses, a request with it could never be answered unambiguously
+    if request.id == schema::RequestId::Null {
+        return Ok(create_error_response(
+            &request.id,
+            schema::INVALID_REQUEST,
+            "Request id must be a string or a number",
+        ));
+    }
+
+    let Some((context, rejection)) = begin_request(server, request, session_id, headers)? else {
+        return Ok(duplicate_request_response(&request.id));
+    };
+
+    // Stops tracking the request if no response comes out of it, so its id doesn't leak
+    let tracked = server.tracked_request(session_id, &request.id);
+    let response = handle_tracked_request(server, request, session_id, context, rejection).await;
+    if response.is_ok() {
+        tracked.answered();
+    }
+
+    response
+}
+
+async fn handle_tracked_request(
+    server: &Server,
+    request: &schema::JSONRPCRequest,
+    session_id: &SessionId,
+    context: RequestContext,
+    rejection: Option<&'static str>,
+) -> Result<JSONRPCMessage> {
+    // `sampling/createMessage` and `roots/list` only go from server to client
+    if request.params.is_server_request() {
+        tracing::debug!(
+            session_id,
+            method = request.params.method(),
+            "server-only request"
+        );
+        return Ok(create_error_response(
+            &request.id,
+            schema::INVALID_REQUEST,
+            "This request is not valid from a client",
+        ));
+    }
+
+    if let Some(rejection) = rejection {
+        return Ok(create_error_response(
+            &request.id,
+            schema::INVALID_REQUEST,
+            rejection,
+        ));
+    }
+
+    let method = request.params.method();
+    if !server.is_method_enabled(method) {
+        tracing::debug!(session_id, method, "method disabled");
+        return Ok(create_error_response(
+            &request.id,
+            schema::METHOD_NOT_FOUND,
+            &format!("Unknown method: {method}"),
+        ));
+    }
+
+    if let Some(limit) = &server.complexity_limit {
+        let params = serde_json::to_value(&request.params)
+            .map_err(|err| ApiError::HandlerError(err.to_string()))?;
+        if let Some(reason) = limit.check(&params) {
+            tracing::warn!(session_id, id = %request.id, "{reason}");
+            return Ok(create_error_response(
+                &request.id,
+                schema::INVALID_PARAMS,
+                &reason,
+            ));
+        }
+    }
+
+    match &request.params {
+        schema::RequestParams::Initialize(init) => {
+            let result = handle_initialize(server, init, session_id);
+            if result.is_err() {
+                // Let the client retry instead of leaving the session stuck initializing
+                reset_initialize(server, session_id)?;
+            }
+
+            Ok(into_response(&request.id, result))
+        }
+        schema::RequestParams::ListTools(_) => {
+            Ok(into_response(&request.id, handle_list_tools(server)))
+        }
+        schema::RequestParams::CallTool(params) => handle_call_tool(server, params, context).await,
+        schema::RequestParams::ListPrompts(_) => {
+            Ok(into_response(&request.id, handle_list_prompts(server)))
+        }
+        schema::RequestParams::GetPrompt(params) => {
+            handle_get_prompt(server, params, context).await
+        }
+        schema::RequestParams::ListResources(_) => {
+            Ok(into_response(&request.id, handle_list_resources(server)))
+        }
+        schema::RequestParams::ReadResource(params) => {
+            handle_read_resource(server, params, context).await
+        }
+        schema::RequestParams::ListResourceTemplate(_) => Ok(into_response(
+            &request.id,
+            handle_list_resource_templates(server),
+        )),
+        schema::RequestParams::CompleteRequest(params) => {
+            handle_complete(server, params, context).await
+        }
+        schema::RequestParams::SetLevel(params) => Ok(into_response(
+            &request.id,
+            handle_set_level(server, params, session_id),
+        )),
+        schema::RequestParams::Subscribe(_) | schema::RequestParams::Unsubscribe(_)
+            if !server.resource_subscriptions =>
+        {
+            tracing::debug!(session_id, method, "resource subscriptions disabled");
+            Ok(create_error_response(
+                &request.id,
+                schema::METHOD_NOT_FOUND,
+                &format!("Unknown method: {method}"),
+            ))
+        }
+        schema::RequestParams::Subscribe(params) => Ok(into_response(
+            &request.id,
+            handle_subscribe(server, &params.uri, session_id, true),
+        )),
+        schema::RequestParams::Unsubscribe(params) => Ok(into_response(
+            &request.id,
+            handle_subscribe(server, &params.uri, session_id, false),
+        )),
+        schema::RequestParams::Custom(params) => {
+            handle_custom_method(server, params, context).await
+        }
+        // Usually answered by `Server::dispatch_message`, but a service driven directly still
+        // gets them
+        schema::RequestParams::Ping(_) => Ok(create_result_response(
+            &request.id,
+            schema::ResultEnum::Empty(schema::EmptyResult::default()),
+        )),
+        schema::RequestParams::CreateMessage(_) | schema::RequestParams::ListRoots(_) => {
+            unreachable!("server-only requests are rejected above")
+        }
+    }
+}
+
+/// Tracks the request and checks the session is in a state to handle it, returning the
+/// context handlers get and why it is rejected, if it is. `None` if its id is already in
+/// flight. An initialize moves the session to `Initializing` under the same lock hold as the
+/// check, so only one of several concurrent initialize requests can win
+fn begin_request(
+    server: &Server,
+    request: &schema::JSONRPCRequest,
+    session_id: &SessionId,
+    headers: HeaderMap,
+) -> Result<Option<(RequestContext, Option<&'static str>)>> {
+    let lock = server
+        .clients
+        .get(session_id)
+        .ok_or(ApiError::MissingClient)?;
+
+    let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+
+    // Replacing the entry would lose the first request's cancellation token
+    if client_conn.in_flight.contains_key(&request.id) {
+        return Ok(None);
+    }
+    let cancellation = CancellationToken::new();
+    client_conn
+        .in_flight
+        .insert(request.id.clone(), cancellation.clone());
+
+    let progress = ProgressReporter::new(
+        progress_token(&request.params),
+        session_id,
+        lock.clone(),
+        server.notification_drop_policy,
+        server.progress_interval,
+    );
+    let context = RequestContext::new(
+        session_id,
+        &request.id,
+        client_conn.protocol_version.clone(),
+        progress,
+        cancellation,
+        headers,
+    );
+
+    let rejection = match (&request.params, &client_conn.initialize_status) {
+        (schema::RequestParams::Initialize(_), InitializeStatus::NotInitialized) => {
+            client_conn.initialize_status = InitializeStatus::Initializing;
+            None
+        }
+        (schema::RequestParams::Initialize(_), InitializeStatus::Initializing) => {
+            Some("Connection already initializing")
+        }
+        (schema::RequestParams::Initialize(_), InitializeStatus::Initialized) => {
+            Some("Connection already initialized")
+        }
+        // Pings work in any state, same as when `Server::dispatch_message` answers them
+        (schema::RequestParams::Ping(_), _) => None,
+        (_, InitializeStatus::NotInitialized) if server.require_initialize => {
+            Some("Connection not initialized")
+        }
+        _ => None,
+    };
+
+    Ok(Some((context, rejection)))
+}
 
-        // let mut client_conn = map
-        //     .get(session_id)
-        //     .ok_or(ApiError::MissingClient)?
-        //     .lock()
-        //     .or_else(|_| Err(ApiError::PoisonedLock))?;
+fn reset_initialize(server: &Server, session_id: &SessionId) -> Result<()> {
+    let lock = server
+        .clients
+        .get(session_id)
+        .ok_or(ApiError::MissingClient)?;
 
+    lock.lock()
+        .map_err(|_| ApiError::PoisonedLock)?
+        .initialize_status = InitializeStatus::NotInitialized;
+
+    Ok(())
+}
+
+/// Wraps a handler outcome in the JSON-RPC envelope answering `id`
+fn into_response(id: &schema::RequestId, result: Result<schema::ServerResult>) -> JSONRPCMessage {
+    match result {
+        Ok(result) => create_result_response(id, result.into()),
+        Err(err) => {
+            tracing::debug!(%id, "request failed: {err}");
+            error_response_for(id, &err)
+        }
+    }
+}
+
+pub(crate) fn handle_initialize(
+    server: &Server,
+    request: &schema::InitializeRequestParams,
+    session_id: &SessionId,
+) -> Result<schema::ServerResult> {
+    request
+        .capabilities
+        .validate()
+        .map_err(|err| ApiError::InvalidParams(format!("Invalid client capabilities: {err}")))?;
+
+    let protocol_version = schema::ProtocolVersion::negotiate(&request.protocol_version);
+    {
         let lock = server
             .clients
             .get(session_id)
             .ok_or(ApiError::MissingClient)?;
 
-        let mut client_conn = lock.lock().or_else(|_| Err(ApiError::PoisonedLock))?;
-
-        if let schema::RequestParams::Initialize(ref init) = request.params {
-            match client_conn.initialize_status {
-                InitializeStatus::NotInitialized => {
-                    client_conn.initialize_status = InitializeStatus::Initializing;
-                    client_conn.capabilities = init.capabilities.clone();
-                }
-                InitializeStatus::Initializing => {
-                    return Ok(create_error_response(
-                        &request.id,
-                        schema::INVALID_REQUEST,
-                        "Connection already initializing",
-                    ))
-                }
-                InitializeStatus::Initialized => {
-                    return Ok(create_error_response(
-                        &request.id,
-                        schema::INVALID_REQUEST,
-                        "Connection already initialized",
-                    ))
-                }
-            };
-        } else {
-            match client_conn.initialize_status {
-                InitializeStatus::NotInitialized => {
-                    return Ok(create_error_response(
-                        &request.id,
-                        schema::INVALID_REQUEST,
-                        "Connection not initialized",
-                    ))
-                }
-                _ => (),
-            };
+        // Only stored once valid, a rejected initialize leaves nothing behind
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        client_conn.protocol_version = protocol_version.clone();
+        client_conn.capabilities = request.capabilities.clone();
+    }
+
+    Ok(schema::ServerResult::Initialize(Box::new(
+        schema::InitializeResult {
+            protocol_version,
+            capabilities: server.advertised_capabilities()?,
+            server_info: server.info.clone(),
+            instructions: server.initialize_instructions()?,
+        },
+    )))
+}
+
+fn handle_set_level(
+    server: &Server,
+    params: &schema::SetLevelRequestParams,
+    session_id: &SessionId,
+) -> Result<schema::ServerResult> {
+    server.set_log_level(session_id, params.level)?;
+
+    Ok(schema::ServerResult::Empty(schema::EmptyResult::default()))
+}
+
+fn handle_list_tools(server: &Server) -> Result<schema::ServerResult> {
+    let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+    let result = schema::ListToolsResult {
+        paginated_base: schema::PaginatedResult { next_cursor: None },
+        tools: registry.tools.values().map(|x| x.tool.clone()).collect(),
+    };
+
+    Ok(schema::ServerResult::ListTools(result))
+}
+
+async fn handle_call_tool(
+    server: &Server,
+    params: &schema::CallToolRequestParams,
+    context: RequestContext,
+) -> Result<JSONRPCMessage> {
+    let session_id = context.session_id();
+    let id = context.request_id();
+    let idempotency_key = server.idempotency_key(params);
+    if let Some(key) = &idempotency_key {
+        if let Some(result) = server.cached_tool_result(session_id, &params.name, key)? {
+            tracing::debug!(tool = params.name, key, "returning cached tool result");
+            return Ok(create_result_response(
+                id,
+                schema::ResultEnum::CallTool(result),
+            ));
         }
     }
-    match &request.params {
-        schema::RequestParams::Initialize(init) => {
-            let response = handle_initialize(server, init, session_id, &request.id);
 
-            Ok(response)
+    let (handler, timeout, tool_permits, output_schema) = {
+        // Block so the registry lock is not held across the tool execution
+        let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        match registry.tools.get(&params.name) {
+            Some(registered) => (
+                registered.handler.clone(),
+                effective_timeout(
+                    registered.timeout.or(server.request_timeout),
+                    params.request_base.timeout_hint(),
+                ),
+                registered.permits.clone(),
+                registered.tool.output_schema.clone(),
+            ),
+            None => {
+                return Ok(create_error_response(
+                    id,
+                    schema::METHOD_NOT_FOUND,
+                    &format!("Unknown tool: {}", params.name),
+                ))
+            }
+        }
+    };
+
+    // Waiting for a permit counts against the timeout and stops when the request is cancelled
+    let call = async {
+        let Some(permits) = acquire_tool_permits(server, tool_permits.as_ref(), &context).await?
+        else {
+            return Ok(None);
+        };
+        let result =
+            catch_panic_async(async { handler(context.clone(), params.arguments.clone()).await })
+                .await;
+        drop(permits);
+        result.map(Some)
+    };
+
+    let result = match with_timeout(timeout, call).await {
+        Ok(Some(result)) => Ok(result),
+        Ok(None) => {
+            tracing::debug!(tool = params.name, "too many tool calls running");
+            return Ok(create_error_response(
+                id,
+                schema::SERVER_BUSY,
+                &format!("Too many calls running, retry {} later", params.name),
+            ));
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(result) => {
+            if let Err(message) = validate_tool_output(output_schema.as_ref(), &result) {
+                tracing::warn!(tool = params.name, "invalid tool output: {message}");
+                return Ok(create_error_response(id, schema::INTERNAL_ERROR, &message));
+            }
+            if let Some(key) = &idempotency_key {
+                server.cache_tool_result(session_id, &params.name, key, result.clone())?;
+            }
+
+            Ok(create_result_response(
+                id,
+                schema::ResultEnum::CallTool(result),
+            ))
+        }
+        Err(
+            err @ (ApiError::Timeout(_)
+            | ApiError::Cancelled
+            | ApiError::HandlerPanicked
+            | ApiError::InvalidArguments { .. }),
+        ) => Ok(error_response_for(id, &err)),
+        Err(err) => {
+            tracing::debug!(tool = params.name, "tool failed: {err}");
+            Ok(create_result_response(
+                id,
+                schema::ResultEnum::CallTool(tool_error_result(&err.to_string())),
+            ))
         }
-        _ => unimplemented!(),
     }
 }
 
-fn handle_initialize(
+/// Permits to run a tool, the tool's own first so a call waiting on it doesn't hold a server
+/// one. `None` when the overflow policy rejects the call
+async fn acquire_tool_permits(
     server: &Server,
-    _request: &schema::InitializeRequestParams,
-    _session_id: &SessionId,
-    id: &schema::RequestId,
-) -> JSONRPCMessage {
-    let initialize_result = schema::JSONRPCResult {
-        id: id.to_owned(),
-        json_rpc: schema::JSONRPC_VERSION.into(),
-        result: schema::Result {
-            base: schema::ResultBase::default(),
-            defined_fields: schema::ResultEnum::Initialize(schema::InitializeResult {
-                protocol_version: schema::LATEST_PROTOCOL_VERSION.to_owned(),
-                capabilities: server.capabilities.clone(),
-                server_info: schema::Implementation {
-                    name: server.name.to_owned(),
-                    version: server.name.to_owned(),
-                },
-                instructions: None,
+    tool_permits: Option<&Arc<Semaphore>>,
+    context: &RequestContext,
+) -> Result<Option<Vec<OwnedSemaphorePermit>>> {
+    let mut permits = Vec::new();
+    for limit in [tool_permits, server.tool_permits.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        let permit = tokio::select! {
+            permit = concurrency::acquire(limit, server.tool_overflow_policy) => permit,
+            _ = context.cancellation().cancelled() => return Err(ApiError::Cancelled),
+        };
+        match permit {
+            Some(permit) => permits.push(permit),
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(permits))
+}
+
+fn handle_list_prompts(server: &Server) -> Result<schema::ServerResult> {
+    let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+    let result = schema::ListPromptsResult {
+        paginated_base: schema::PaginatedResult { next_cursor: None },
+        prompts: registry
+            .prompts
+            .values()
+            .map(|x| x.prompt.clone())
+            .collect(),
+    };
+
+    Ok(schema::ServerResult::ListPrompts(result))
+}
+
+async fn handle_get_prompt(
+    server: &Server,
+    params: &schema::GetPromptRequestParams,
+    context: RequestContext,
+) -> Result<JSONRPCMessage> {
+    let id = context.request_id();
+    let (description, handler) = {
+        let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        match registry.prompts.get(&params.name) {
+            Some(registered) => (
+                registered.prompt.description.clone(),
+                registered.handler.clone(),
+            ),
+            None => {
+                return Ok(create_error_response(
+                    id,
+                    schema::INVALID_PARAMS,
+                    &format!("Unknown prompt: {}", params.name),
+                ))
+            }
+        }
+    };
+
+    let (handler_context, arguments) = (
+        context.clone(),
+        params.arguments.clone().unwrap_or_default(),
+    );
+    let timeout = effective_timeout(server.request_timeout, params.request_base.timeout_hint());
+
+    match with_timeout_blocking(timeout, move || handler(handler_context, arguments)).await {
+        Ok(messages) => Ok(create_result_response(
+            id,
+            schema::ResultEnum::GetPrompt(schema::GetPromptResult {
+                description,
+                messages,
             }),
-        },
+        )),
+        Err(err) => Ok(error_response_for(id, &err)),
+    }
+}
+
+fn handle_list_resources(server: &Server) -> Result<schema::ServerResult> {
+    let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+    let result = schema::ListResourcesResult {
+        paginated_base: schema::PaginatedResult { next_cursor: None },
+        resources: registry
+            .resources
+            .values()
+            .map(|x| x.resource.clone())
+            .collect(),
+    };
+
+    Ok(schema::ServerResult::ListResources(result))
+}
+
+async fn handle_read_resource(
+    server: &Server,
+    params: &schema::ReadResourceRequestParams,
+    context: RequestContext,
+) -> Result<JSONRPCMessage> {
+    let (id, session_id) = (context.request_id(), context.session_id());
+    let (reader, mime_type, cached) = {
+        let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        match registry.resources.get(&params.uri) {
+            // Looked up under the registry lock, so the cache generation matches the handler
+            Some(registered) => (
+                registered.reader.clone(),
+                registered.resource.mime_type.clone(),
+                server.cached_resource(&params.uri)?,
+            ),
+            None => {
+                return Ok(create_error_response(
+                    id,
+                    schema::INVALID_PARAMS,
+                    &format!("Unknown resource: {}", params.uri),
+                ))
+            }
+        }
+    };
+
+    let timeout = effective_timeout(server.request_timeout, params.request_base.timeout_hint());
+    let handler = match reader {
+        ResourceReader::Whole(handler) => handler,
+        ResourceReader::Stream(handler) => {
+            let stream = handler(params.uri.clone());
+            let resource = schema::ResourceContents::new(&params.uri, mime_type.as_deref());
+            return server
+                .stream_resource(session_id, id, resource, timeout, stream)
+                .await;
+        }
+    };
+
+    let generation = match cached {
+        CachedResource::Hit(result) => {
+            tracing::debug!(uri = params.uri, "resource read from cache");
+            return Ok(create_result_response(
+                id,
+                schema::ResultEnum::ReadResource(Arc::unwrap_or_clone(result)),
+            ));
+        }
+        CachedResource::Miss { generation } => generation,
+    };
+
+    let read = catch_panic_async(async { handler(context.clone(), params.uri.clone()).await });
+    match with_timeout(timeout, read).await {
+        Ok(result) => {
+            let result = Arc::new(result);
+            server.cache_resource(&params.uri, generation, result.clone())?;
+            Ok(create_result_response(
+                id,
+                schema::ResultEnum::ReadResource(Arc::unwrap_or_clone(result)),
+            ))
+        }
+        Err(err) => Ok(error_response_for(id, &err)),
+    }
+}
+
+fn handle_list_resource_templates(server: &Server) -> Result<schema::ServerResult> {
+    let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+    let result = schema::ListResourcesTemplateResult {
+        paginated_base: schema::PaginatedResult { next_cursor: None },
+        resource_templates: registry.resource_templates.values().cloned().collect(),
     };
 
-    JSONRPCMessage::Response(schema::JSONRPCResponse::Result(initialize_result))
+    Ok(schema::ServerResult::ListResourcesTemplate(result))
+}
+
+async fn handle_complete(
+    server: &Server,
+    params: &schema::CompleteRequestParams,
+    context: RequestContext,
+) -> Result<JSONRPCMessage> {
+    let id = context.request_id();
+    let handler = {
+        let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        // A provider for a prompt or resource that is no longer registered must not run
+        if registry.contains_ref(&params.r#ref) {
+            registry.completions.get(&params.r#ref).cloned()
+        } else {
+            None
+        }
+    };
+
+    let Some(handler) = handler else {
+        tracing::debug!(reference = ?params.r#ref, "no completions for reference");
+        return Ok(create_result_response(
+            id,
+            schema::ResultEnum::Complete(schema::CompleteResult {
+                values: vec![],
+                total: None,
+                has_more: None,
+            }),
+        ));
+    };
+
+    let (handler_context, argument) = (context.clone(), params.argument.clone());
+    let timeout = effective_timeout(server.request_timeout, params.request_base.timeout_hint());
+
+    match with_timeout_blocking(timeout, move || handler(handler_context, argument)).await {
+        Ok(completion) => Ok(create_result_response(
+            id,
+            schema::ResultEnum::Complete(completion),
+        )),
+        Err(err) => Ok(error_response_for(id, &err)),
+    }
+}
+
+async fn handle_custom_method(
+    server: &Server,
+    params: &schema::CustomRequestParams,
+    context: RequestContext,
+) -> Result<JSONRPCMessage> {
+    let id = context.request_id();
+    // Spec methods only land here when their params did not match the schema
+    if schema::REQUEST_METHODS.contains(&params.method.as_str()) {
+        return Ok(create_error_response(
+            id,
+            schema::INVALID_PARAMS,
+            &format!("Invalid params for {}", params.method),
+        ));
+    }
+
+    let handler = {
+        let registry = server.registry.read().map_err(|_| ApiError::PoisonedLock)?;
+
+        match registry.custom_methods.get(&params.method) {
+            Some(handler) => handler.clone(),
+            None => {
+                return Ok(create_error_response(
+                    id,
+                    schema::METHOD_NOT_FOUND,
+                    &format!("Unknown method: {}", params.method),
+                ))
+            }
+        }
+    };
+
+    let hint = schema::RequestBaseParams::deserialize(&params.params)
+        .ok()
+        .and_then(|base| base.timeout_hint());
+    match with_timeout(
+        effective_timeout(server.request_timeout, hint),
+        catch_panic_async(async { handler(context.clone(), params.params.clone()).await }),
+    )
+    .await
+    {
+        Ok(Value::Object(result)) => Ok(create_result_response(
+            id,
+            schema::ResultEnum::Custom(result),
+        )),
+        Ok(_) => Ok(create_error_response(
+            id,
+            schema::INTERNAL_ERROR,
+            &format!("Result of {} is not an object", params.method),
+        )),
+        Err(err) => Ok(error_response_for(id, &err)),
+    }
 }
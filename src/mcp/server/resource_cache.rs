@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::Server;
+
+/// Least recently used contents of `resources/read`, keyed by uri
+#[derive(Debug)]
+pub(crate) struct ResourceCache {
+    capacity: usize,
+    entries: HashMap<String, Arc<schema::ReadResourceResult>>,
+    // Front is the least recently used uri
+    order: VecDeque<String>,
+    // Set from `last_generation` on invalidation, so a read that started before it doesn't
+    // cache stale contents
+    generations: HashMap<String, u64>,
+    // Generation of every uri not invalidated since the cache was last cleared
+    cleared: u64,
+    last_generation: u64,
+}
+
+/// Outcome of looking a resource up in the cache
+pub(crate) enum CachedResource {
+    Hit(Arc<schema::ReadResourceResult>),
+    /// Pass `generation` back to [`Server::cache_resource`] once the contents are read
+    Miss {
+        generation: u64,
+    },
+}
+
+impl ResourceCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            generations: HashMap::new(),
+            cleared: 0,
+            last_generation: 0,
+        }
+    }
+
+    fn get(&mut self, uri: &str) -> CachedResource {
+        match self.entries.get(uri).cloned() {
+            Some(result) => {
+                self.touch(uri);
+                CachedResource::Hit(result)
+            }
+            None => CachedResource::Miss {
+                generation: self.generation(uri),
+            },
+        }
+    }
+
+    fn generation(&self, uri: &str) -> u64 {
+        self.generations.get(uri).copied().unwrap_or(self.cleared)
+    }
+
+    fn next_generation(&mut self) -> u64 {
+        self.last_generation += 1;
+        self.last_generation
+    }
+
+    fn insert(&mut self, uri: &str, generation: u64, result: Arc<schema::ReadResourceResult>) {
+        if self.capacity == 0 || self.generation(uri) != generation {
+            return;
+        }
+
+        if self.entries.insert(uri.to_string(), result).is_some() {
+            self.touch(uri);
+        } else {
+            self.order.push_back(uri.to_string());
+        }
+
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn remove(&mut self, uri: &str) -> bool {
+        let generation = self.next_generation();
+        self.generations.insert(uri.to_string(), generation);
+        self.order.retain(|x| x != uri);
+        self.entries.remove(uri).is_some()
+    }
+
+    /// Invalidates every uri, including reads still running
+    fn clear(&mut self) {
+        self.cleared = self.next_generation();
+        self.generations.clear();
+        self.order.clear();
+        self.entries.clear();
+    }
+
+    fn touch(&mut self, uri: &str) {
+        if let Some(pos) = self.order.iter().position(|x| x == uri) {
+            if let Some(uri) = self.order.remove(pos) {
+                self.order.push_back(uri);
+            }
+        }
+    }
+}
+
+impl Server {
+    pub(crate) fn cached_resource(&self, uri: &str) -> Result<CachedResource> {
+        let Some(cache) = &self.resource_cache else {
+            return Ok(CachedResource::Miss { generation: 0 });
+        };
+
+        Ok(lock(cache)?.get(uri))
+    }
+
+    /// Caches contents read after a miss, unless `uri` was invalidated since
+    pub(crate) fn cache_resource(
+        &self,
+        uri: &str,
+        generation: u64,
+        result: Arc<schema::ReadResourceResult>,
+    ) -> Result<()> {
+        if let Some(cache) = &self.resource_cache {
+            lock(cache)?.insert(uri, generation, result);
+        }
+
+        Ok(())
+    }
+
+    /// Drops the cached contents of `uri` so the next read runs its handler again. Called by
+    /// [`Server::notify_resource_updated`] and when the resource is registered again
+    ///
+    /// Returns whether anything was cached
+    pub fn invalidate_resource(&self, uri: &str) -> Result<bool> {
+        match &self.resource_cache {
+            Some(cache) => Ok(lock(cache)?.remove(uri)),
+            None => Ok(false),
+        }
+    }
+
+    /// Drops every cached resource, e.g. once the handlers they were read with are replaced
+    pub(crate) fn invalidate_resources(&self) -> Result<()> {
+        if let Some(cache) = &self.resource_cache {
+            lock(cache)?.clear();
+        }
+
+        Ok(())
+    }
+}
+
+fn lock(cache: &Mutex<ResourceCache>) -> Result<std::sync::MutexGuard<'_, ResourceCache>> {
+    cache.lock().map_err(|_| ApiError::PoisonedLock)
+}
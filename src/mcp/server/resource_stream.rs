@@ -0,0 +1,168 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use futures::stream::{Stream, StreamExt};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::mcp::schema::{self, JSONRPCMessage};
+
+use super::error::Result;
+use super::registry::{ResourceReader, ResourceStreamHandler};
+use super::utils::{create_result_response, error_response_for, with_timeout};
+use super::{Server, SessionId};
+
+/// Notification carrying one chunk of a streamed `resources/read`. Params hold the `requestId`
+/// and `uri` of the read, the chunk `index` and either `text` or base64 `blob`
+pub const RESOURCE_CHUNK_METHOD: &str = "notifications/resources/chunk";
+
+/// Experimental capability for [`RESOURCE_CHUNK_METHOD`]. The server advertises it once a
+/// streamed resource is registered and only streams to clients that declare it too
+pub const RESOURCE_CHUNKS_CAPABILITY: &str = "resourceChunks";
+
+/// Piece of a streamed resource
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceChunk {
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Server {
+    /// Registers a resource whose contents are produced as a stream of chunks. For clients with
+    /// the [`RESOURCE_CHUNKS_CAPABILITY`], each chunk is sent as a [`RESOURCE_CHUNK_METHOD`]
+    /// notification as soon as the client's queue has room, so the whole resource is never
+    /// held in memory. Chunks are never dropped by the [`super::NotificationDropPolicy`]. The
+    /// `resources/read` response then carries no contents and the number of chunks in
+    /// `_meta.chunks`. Other clients get the chunks joined into regular `contents`.
+    ///
+    /// The whole stream is bound by the request timeout. It is polled from a runtime thread,
+    /// so it must be `Send + 'static`
+    pub fn register_resource_stream<F, S>(
+        &self,
+        resource: schema::Resource,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(String) -> S + Send + Sync + 'static,
+        S: Stream<Item = Result<ResourceChunk>> + Send + 'static,
+    {
+        let handler: ResourceStreamHandler = Arc::new(move |uri| handler(uri).boxed());
+
+        self.insert_resource(resource, ResourceReader::Stream(handler))
+    }
+
+    pub(crate) async fn stream_resource<S>(
+        &self,
+        session_id: &SessionId,
+        id: &schema::RequestId,
+        resource: schema::ResourceContents,
+        timeout: Option<Duration>,
+        stream: S,
+    ) -> Result<JSONRPCMessage>
+    where
+        S: Stream<Item = Result<ResourceChunk>>,
+    {
+        if !self
+            .client_experimental(session_id)?
+            .contains_key(RESOURCE_CHUNKS_CAPABILITY)
+        {
+            let contents = with_timeout(timeout, collect_chunks(resource, stream)).await;
+            return Ok(match contents {
+                Ok(contents) => create_result_response(
+                    id,
+                    schema::ResultEnum::ReadResource(schema::ReadResourceResult { contents }),
+                ),
+                Err(err) => error_response_for(id, &err),
+            });
+        }
+
+        let uri = &resource.uri;
+        let send_chunks = async {
+            let mut stream = std::pin::pin!(stream);
+            let mut index = 0;
+
+            while let Some(chunk) = stream.next().await {
+                let mut params = json!({ "requestId": id, "uri": uri, "index": index });
+                match chunk? {
+                    ResourceChunk::Text(text) => params["text"] = json!(text),
+                    ResourceChunk::Blob(bytes) => {
+                        params["blob"] = json!(BASE64_STANDARD.encode(bytes))
+                    }
+                }
+
+                let notification = schema::JSONRPCNotification {
+                    json_rpc: schema::JSONRPC_VERSION.into(),
+                    params: schema::NotificationParams::Custom(schema::CustomNotificationParams {
+                        method: RESOURCE_CHUNK_METHOD.to_string(),
+                        params,
+                    }),
+                };
+                // Chunks are never dropped, whatever the drop policy, so this waits for room
+                // in the client's queue, which bounds how far the stream runs ahead
+                self.send_message(session_id, JSONRPCMessage::Notification(notification))
+                    .await?;
+                index += 1;
+            }
+
+            Ok(index)
+        };
+
+        let chunks = match with_timeout(timeout, send_chunks).await {
+            Ok(chunks) => chunks,
+            Err(err) => return Ok(error_response_for(id, &err)),
+        };
+
+        Ok(JSONRPCMessage::Response(schema::JSONRPCResponse::Result(
+            schema::JSONRPCResult {
+                json_rpc: schema::JSONRPC_VERSION.into(),
+                id: id.to_owned(),
+                result: schema::Result {
+                    base: schema::ResultBase {
+                        meta: Some([("chunks".to_string(), json!(chunks))].into()),
+                        ..Default::default()
+                    },
+                    defined_fields: schema::ResultEnum::ReadResource(schema::ReadResourceResult {
+                        contents: vec![],
+                    }),
+                },
+            },
+        )))
+    }
+}
+
+/// Joins the chunks into contents for a client that can't receive them one by one. Consecutive
+/// chunks of the same kind make up one content
+async fn collect_chunks<S>(
+    resource: schema::ResourceContents,
+    stream: S,
+) -> Result<Vec<schema::ContentsResource>>
+where
+    S: Stream<Item = Result<ResourceChunk>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut contents = Vec::new();
+
+    while let Some(chunk) = stream.next().await {
+        match (contents.last_mut(), chunk?) {
+            (Some(schema::ContentsResource::Text(last)), ResourceChunk::Text(text)) => {
+                last.text.push_str(&text)
+            }
+            (Some(schema::ContentsResource::Blob(last)), ResourceChunk::Blob(bytes)) => {
+                last.blob.0.extend(bytes)
+            }
+            (_, ResourceChunk::Text(text)) => contents.push(schema::ContentsResource::Text(
+                schema::TextResourceContents {
+                    resource_contents_base: resource.clone(),
+                    text,
+                },
+            )),
+            (_, ResourceChunk::Blob(bytes)) => contents.push(schema::ContentsResource::Blob(
+                schema::BlobResourceContents {
+                    resource_contents_base: resource.clone(),
+                    blob: schema::Base64Bytes(bytes),
+                },
+            )),
+        }
+    }
+
+    Ok(contents)
+}
@@ -0,0 +1,44 @@
+use crate::mcp::schema;
+use crate::mcp::server::error::ApiError;
+
+use super::error::Result;
+use super::{Server, SessionId};
+
+/// Routes a client response to the outbound request awaiting it
+pub fn handle_response(
+    server: &Server,
+    response: &schema::JSONRPCResponse,
+    session_id: &SessionId,
+) -> Result<()> {
+    let id = match response {
+        schema::JSONRPCResponse::Result(res) => &res.id,
+        schema::JSONRPCResponse::Error(err) => &err.id,
+    };
+
+    let pending = {
+        let lock = server
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        client_conn.take_pending_request(id)
+    };
+
+    match pending {
+        Some(pending) => {
+            let latency = pending.sent_at.elapsed();
+            tracing::debug_span!("outbound_request", session_id, %id).in_scope(|| {
+                tracing::info!(
+                    latency_us = latency.as_micros() as u64,
+                    "client answered request"
+                )
+            });
+            // Requester may have stopped waiting
+            _ = pending.send.send(Ok(response.to_owned()))
+        }
+        None => tracing::warn!(%id, "response for unknown request"),
+    }
+
+    Ok(())
+}
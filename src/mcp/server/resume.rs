@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::error::{ApiError, Result};
+use super::{Client, Server, SessionId};
+
+/// Client of a session whose SSE stream dropped, kept so messages queue up until it resumes
+pub(crate) struct DetachedClient {
+    client: Client,
+    since: Instant,
+}
+
+impl Server {
+    /// Keeps the session of a dropped stream open for the resume timeout instead of closing
+    /// it. Messages sent meanwhile stay in its queue and are delivered when the client
+    /// reconnects with its session id. Returns whether the session was kept
+    pub(crate) fn detach_client(self: &Arc<Self>, client: Client) -> Result<bool> {
+        let Some(timeout) = self.session_resume_timeout else {
+            return Ok(false);
+        };
+        // The expiry timer needs a runtime
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return Ok(false);
+        };
+
+        let session_id = client.session_id.clone();
+        {
+            let Some(lock) = self.clients.get(&session_id) else {
+                return Ok(false);
+            };
+
+            lock.lock().map_err(|_| ApiError::PoisonedLock)?.detached = Some(DetachedClient {
+                client,
+                since: Instant::now(),
+            });
+        }
+        tracing::debug!(session_id, ?timeout, "stream dropped, waiting for resume");
+
+        let server = self.clone();
+        runtime.spawn(async move {
+            tokio::time::sleep(timeout).await;
+            if let Err(err) = server.expire_detached(&session_id, timeout) {
+                tracing::warn!(session_id, "failed to expire session: {err}");
+            }
+        });
+
+        Ok(true)
+    }
+
+    /// Takes the client of a detached session for a new stream, `None` if the session is gone
+    /// or still has a stream
+    pub(crate) fn resume_client(&self, session_id: &SessionId) -> Result<Option<Client>> {
+        let Some(lock) = self.clients.get(session_id) else {
+            return Ok(None);
+        };
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        Ok(client_conn.detached.take().map(|detached| detached.client))
+    }
+
+    /// Closes the session if it is still detached since before `timeout`. A session that
+    /// resumed and dropped again is left to the timer of its latest detach
+    fn expire_detached(&self, session_id: &SessionId, timeout: Duration) -> Result<()> {
+        let expired = {
+            let Some(lock) = self.clients.get(session_id) else {
+                return Ok(());
+            };
+
+            let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+            client_conn
+                .detached
+                .as_ref()
+                .is_some_and(|detached| detached.since.elapsed() >= timeout)
+        };
+
+        if expired {
+            tracing::debug!(session_id, "session not resumed in time");
+            self.close_connection(session_id)?;
+        }
+
+        Ok(())
+    }
+}
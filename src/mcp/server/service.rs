@@ -0,0 +1,237 @@
+use axum::http::HeaderMap;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::util::BoxCloneService;
+use tower::{Layer, Service, ServiceExt};
+use tracing::Instrument;
+
+use crate::mcp::schema::{
+    EmptyResult, JSONRPCMessage, JSONRPCRequest, RequestId, RequestParams, ResultEnum,
+};
+
+use super::error::{ApiError, Result};
+use super::in_flight::duplicate_request_response;
+use super::notification::handle_notification;
+use super::request::handle_request_with_headers;
+use super::response::handle_response;
+use super::utils::create_result_response;
+use super::{Server, SessionId};
+
+/// Message handling of one session as a [`tower::Service`], so transports can share it and
+/// users can wrap it in their own layers. Requests resolve to their response, notifications
+/// and client responses to `None`
+#[derive(Debug, Clone)]
+pub struct McpService {
+    server: Arc<Server>,
+    session_id: SessionId,
+    headers: HeaderMap,
+}
+
+impl McpService {
+    pub fn new(server: Arc<Server>, session_id: &str) -> Self {
+        Self {
+            server,
+            session_id: session_id.to_string(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Transport headers handed to the [`super::RequestContext`] of each request, unless the
+    /// transport passes the headers of the message being handled
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+pub(crate) type BoxMcpService = BoxCloneService<JSONRPCMessage, Option<JSONRPCMessage>, ApiError>;
+
+/// User middleware applied to the service of every session
+#[derive(Clone)]
+pub(crate) struct ServiceLayer(Arc<dyn Fn(McpService) -> BoxMcpService + Send + Sync>);
+
+impl ServiceLayer {
+    pub(crate) fn new<L>(layer: L) -> Self
+    where
+        L: Layer<McpService> + Send + Sync + 'static,
+        L::Service: Service<JSONRPCMessage, Response = Option<JSONRPCMessage>, Error = ApiError>
+            + Clone
+            + Send
+            + 'static,
+        <L::Service as Service<JSONRPCMessage>>::Future: Send + 'static,
+    {
+        Self(Arc::new(move |service| {
+            BoxCloneService::new(layer.layer(service))
+        }))
+    }
+}
+
+impl std::fmt::Debug for ServiceLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ServiceLayer")
+    }
+}
+
+tokio::task_local! {
+    // Headers of the transport message being dispatched, they change from one post to the
+    // next while the session's service is built once
+    static MESSAGE_HEADERS: HeaderMap;
+}
+
+impl Server {
+    /// Service handling the session's messages, wrapped in the configured layer. It is built
+    /// on first use and shared by every message of the session, so stateful layers such as
+    /// rate limits see all of them
+    pub fn service(self: &Arc<Self>, session_id: &str) -> Result<BoxMcpService> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        let service = client_conn
+            .service
+            .get_or_insert_with(|| self.layered(McpService::new(self.clone(), session_id)));
+
+        Ok(service.clone())
+    }
+
+    fn layered(&self, service: McpService) -> BoxMcpService {
+        match &self.service_layer {
+            Some(ServiceLayer(layer)) => layer(service),
+            None => BoxCloneService::new(service),
+        }
+    }
+
+    /// Routes one message from the session through the configured layer. Every transport
+    /// hands its parsed messages here and delivers the reply, which is `Some` for requests
+    /// and `None` for notifications, client responses and requests already answered with
+    /// [`Server::respond`]. Pings are answered without reaching the layer
+    ///
+    /// The request is no longer in flight once this returns, so its id can be reused
+    pub async fn dispatch_message(
+        self: &Arc<Self>,
+        session_id: &str,
+        message: JSONRPCMessage,
+    ) -> Result<Option<JSONRPCMessage>> {
+        let reply = match self.answer_ping(session_id, &message)? {
+            Some(pong) => Some(pong),
+            None => self.service(session_id)?.oneshot(message).await?,
+        };
+
+        match reply {
+            Some(reply) if !self.finish_response(&session_id.to_string(), &reply)? => Ok(None),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Same as [`Server::dispatch_message`], for transports with headers to pass on to the
+    /// request context. The request stays in flight until the reply goes through
+    /// [`Server::send_response`]
+    pub(crate) async fn dispatch_message_with_headers(
+        self: &Arc<Self>,
+        session_id: &str,
+        message: JSONRPCMessage,
+        headers: HeaderMap,
+    ) -> Result<Option<JSONRPCMessage>> {
+        if let Some(pong) = self.answer_ping(session_id, &message)? {
+            return Ok(Some(pong));
+        }
+
+        let service = self.service(session_id)?;
+        MESSAGE_HEADERS
+            .scope(headers, service.oneshot(message))
+            .await
+    }
+}
+
+impl Service<JSONRPCMessage> for McpService {
+    type Response = Option<JSONRPCMessage>;
+    type Error = ApiError;
+    type Future = BoxFuture<'static, Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: JSONRPCMessage) -> Self::Future {
+        let server = self.server.clone();
+        let session_id = self.session_id.clone();
+        let headers = MESSAGE_HEADERS
+            .try_with(HeaderMap::clone)
+            .unwrap_or_else(|_| self.headers.clone());
+
+        Box::pin(async move { dispatch(&server, &session_id, message, headers).await })
+    }
+}
+
+impl Server {
+    /// Pings are answered before any layer, guard or handler runs, so they stay cheap and work
+    /// in any session state, even while the server is overloaded or shutting down. They still
+    /// count as activity, go in the session history and are tracked so the transport delivers
+    /// the reply
+    fn answer_ping(
+        &self,
+        session_id: &str,
+        message: &JSONRPCMessage,
+    ) -> Result<Option<JSONRPCMessage>> {
+        let JSONRPCMessage::Request(JSONRPCRequest {
+            id,
+            params: RequestParams::Ping(_),
+            ..
+        }) = message
+        else {
+            return Ok(None);
+        };
+        if *id == RequestId::Null {
+            return Ok(None);
+        }
+
+        let session_id = session_id.to_string();
+        self.record_inbound(&session_id, message)?;
+        self.touch_session(&session_id)?;
+        if !self.track_request(&session_id, id)? {
+            return Ok(Some(duplicate_request_response(id)));
+        }
+
+        Ok(Some(create_result_response(
+            id,
+            ResultEnum::Empty(EmptyResult::default()),
+        )))
+    }
+}
+
+async fn dispatch(
+    server: &Server,
+    session_id: &SessionId,
+    message: JSONRPCMessage,
+    headers: HeaderMap,
+) -> Result<Option<JSONRPCMessage>> {
+    server.record_inbound(session_id, &message)?;
+    server.touch_session(session_id)?;
+
+    match message {
+        JSONRPCMessage::Request(ref req) => {
+            // Same span as the response so both ends of the exchange share the id
+            let span = tracing::debug_span!(
+                "inbound_request",
+                session_id,
+                method = req.params.method(),
+                id = %req.id
+            );
+            handle_request_with_headers(server, req, session_id, headers)
+                .instrument(span)
+                .await
+                .map(Some)
+        }
+        JSONRPCMessage::Notification(ref not) => {
+            handle_notification(server, not, session_id)?;
+            Ok(None)
+        }
+        JSONRPCMessage::Response(ref res) => {
+            handle_response(server, res, session_id)?;
+            Ok(None)
+        }
+    }
+}
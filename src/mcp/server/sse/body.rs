@@ -0,0 +1,72 @@
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::Value;
+
+use crate::mcp::server::utils::create_parse_error_response;
+
+/// JSON body of a posted message. Unlike axum's `Json`, bodies that are empty, not valid
+/// UTF-8 or not valid JSON are answered with a JSON-RPC `PARSE_ERROR` instead of a plain
+/// text rejection
+pub(crate) struct MessageBody(pub(crate) Value);
+
+pub(crate) enum MessageBodyRejection {
+    UnsupportedMediaType,
+    Bytes(Response),
+    Parse(String),
+}
+
+impl IntoResponse for MessageBodyRejection {
+    fn into_response(self) -> Response {
+        match self {
+            MessageBodyRejection::UnsupportedMediaType => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response()
+            }
+            MessageBodyRejection::Bytes(response) => response,
+            MessageBodyRejection::Parse(message) => (
+                StatusCode::BAD_REQUEST,
+                Json(create_parse_error_response(&message)),
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequest<S> for MessageBody {
+    type Rejection = MessageBodyRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.split(';').next())
+            .map(|x| x.trim().to_ascii_lowercase())
+            .is_some_and(|x| {
+                x == "application/json" || (x.starts_with("application/") && x.ends_with("+json"))
+            });
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|err| MessageBodyRejection::Bytes(err.into_response()))?;
+        // Checked before the content type, clients posting nothing often leave it out too
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Err(MessageBodyRejection::Parse(
+                "Message body is empty, expected a JSON-RPC message".to_string(),
+            ));
+        }
+        if !is_json {
+            return Err(MessageBodyRejection::UnsupportedMediaType);
+        }
+        let text = std::str::from_utf8(&bytes).map_err(|err| {
+            MessageBodyRejection::Parse(format!("Message body is not valid UTF-8: {err}"))
+        })?;
+
+        serde_json::from_str(text).map(MessageBody).map_err(|err| {
+            MessageBodyRejection::Parse(format!("Message body is not valid JSON: {err}"))
+        })
+    }
+}
@@ -1,39 +1,42 @@
+mod body;
 mod routes;
 
 use async_stream::try_stream;
 use axum::{
-    extract::{Query, Request, State},
-    http::{HeaderMap, StatusCode},
+    extract::{OriginalUri, Query, Request, State},
+    http::{HeaderMap, StatusCode, Uri},
     response::{
         sse::{Event, Sse},
-        Response,
+        IntoResponse, Response,
     },
     routing::{get, post},
-    Extension, Json, Router,
+    Router,
 };
 use futures::stream::Stream;
 use serde::Deserialize;
-use std::{fmt, sync::Arc, time::Duration};
+use serde_json::Value;
+use std::{fmt, future::Future, future::IntoFuture, sync::Arc, time::Duration};
+use tokio::sync::oneshot;
 use tower_http::{
     trace::{DefaultOnRequest, TraceLayer},
     LatencyUnit,
 };
 use tracing::{Level, Span};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use uuid::Uuid;
 
 use crate::mcp::{
     schema::{self},
-    server::{error::ApiError, notification::handle_notification, request::handle_request},
+    server::{error::ApiError, utils::create_error_response},
 };
 
-use super::{error::Result, Message, Server, SessionId};
+use super::{context::context_headers, error::Result, Client, Server, SessionId};
+use body::MessageBody;
 
 // Sse Server should live as long as mcp_server
 // But mcp_server can live longer
 
 struct SseState {
-    mcp_server: Server,
+    mcp_server: Arc<Server>,
     endpoint: String,
 }
 
@@ -43,6 +46,13 @@ struct SessionQuery {
     session_id: String,
 }
 
+/// Session a reconnecting client resumes on `GET /sse`
+#[derive(Debug, Deserialize)]
+struct ResumeQuery {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+}
+
 // Got from tower_http
 struct Latency {
     unit: LatencyUnit,
@@ -61,11 +71,11 @@ impl fmt::Display for Latency {
     }
 }
 
-#[derive(Clone)]
-struct RequestContext {}
-
-pub async fn serve(mcp_server: Server, endpoint: &str) -> Result<()> {
-    tracing_subscriber::registry()
+pub async fn serve<F>(mcp_server: Arc<Server>, signal: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    _ = tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
                 // axum logs rejections from built-in extractors with the `axum::rejection`
@@ -78,21 +88,92 @@ pub async fn serve(mcp_server: Server, endpoint: &str) -> Result<()> {
             }),
         )
         .with(tracing_subscriber::fmt::layer())
-        .init();
+        // The embedding application may have installed its own subscriber already
+        .try_init();
+
+    let listener = bind(&format!("0.0.0.0:{}", mcp_server.port)).await?;
+
+    tracing::debug!("listening on {}", listener.local_addr()?);
+
+    serve_listener(mcp_server, listener, signal).await
+}
+
+/// Listens on `addr`, naming the address when it can't, e.g. because the port is in use
+pub(crate) async fn bind(addr: &str) -> Result<tokio::net::TcpListener> {
+    tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|source| ApiError::BindError {
+            addr: addr.to_string(),
+            source,
+        })
+}
+
+/// Serves on `listener` until `signal` completes and open connections drain. SSE streams
+/// never end on their own, so sessions still open after the shutdown timeout are terminated
+pub(crate) async fn serve_listener<F>(
+    mcp_server: Arc<Server>,
+    listener: tokio::net::TcpListener,
+    signal: F,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let shutdown_timeout = mcp_server.shutdown_timeout;
+    let (signalled, on_signal) = oneshot::channel();
+
+    let serve = axum::serve(listener, router(mcp_server.clone()))
+        .with_graceful_shutdown(async move {
+            signal.await;
+            _ = signalled.send(());
+        })
+        .into_future();
+    tokio::pin!(serve);
+
+    tokio::select! {
+        served = &mut serve => return served.map_err(ApiError::IoError),
+        _ = on_signal => tracing::info!("shutting down, draining connections"),
+    }
 
+    let Some(timeout) = shutdown_timeout else {
+        return serve.await.map_err(ApiError::IoError);
+    };
+
+    match tokio::time::timeout(timeout, &mut serve).await {
+        Ok(served) => served.map_err(ApiError::IoError),
+        Err(_) => {
+            let terminated = mcp_server.terminate_all_sessions()?;
+            tracing::warn!(terminated, "shutdown timed out, closing remaining sessions");
+            // Handlers that are still stuck are abandoned along with their connections
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn router(mcp_server: Arc<Server>) -> Router {
+    let prefix = mcp_server.path_prefix.to_owned();
+    let endpoint = match prefix.as_str() {
+        "" => mcp_server.endpoint.to_owned(),
+        prefix => format!("{prefix}/{}", mcp_server.endpoint.trim_start_matches('/')),
+    };
+    let capabilities_route = mcp_server.capabilities_route;
     let shared_state = Arc::new(SseState {
         mcp_server,
-        endpoint: endpoint.to_string(),
+        endpoint,
     });
 
-    let port = shared_state.mcp_server.port;
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
-        .await
-        .or_else(|err| Err(ApiError::IoError(err)))?;
+    let mut router = Router::new()
+        .route(&format!("{prefix}/sse"), get(sse_handler))
+        .route(&format!("{prefix}/messages"), post(message_handler))
+        .route(&format!("{prefix}/health"), get(routes::health_handler));
+
+    if capabilities_route {
+        router = router.route(
+            &format!("{prefix}/capabilities"),
+            get(routes::capabilities_handler),
+        );
+    }
 
-    let app = Router::new()
-        .route("/sse", get(sse_handler))
-        .route("/messages", post(message_handler))
+    router
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &Request<_>| {
@@ -110,7 +191,7 @@ pub async fn serve(mcp_server: Server, endpoint: &str) -> Result<()> {
                 .on_response(|response: &Response<_>, _latency: Duration, _span: &Span| {
                     _span.record(
                         "status_code",
-                        &tracing::field::display(response.status().as_u16()),
+                        tracing::field::display(response.status().as_u16()),
                     );
 
                     let latency = Latency {
@@ -127,30 +208,41 @@ pub async fn serve(mcp_server: Server, endpoint: &str) -> Result<()> {
                 ),
         )
         // .route_layer(middleware::from_fn(print_request_response))
-        .with_state(shared_state);
-
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
-
-    axum::serve(listener, app)
-        .await
-        .or_else(|err| Err(ApiError::IoError(err)))
+        .with_state(shared_state)
 }
 
 async fn sse_handler(
     State(state): State<Arc<SseState>>,
-) -> Result<Sse<impl Stream<Item = Result<Event>>>> {
+    OriginalUri(original_uri): OriginalUri,
+    uri: Uri,
+    Query(resume): Query<ResumeQuery>,
+) -> Result<Response> {
     tracing::debug!("sse handler");
 
-    let session_id: SessionId = Uuid::new_v4().to_string();
-
-    let mut client = {
-        // Using block here so that lock can be dropped
-        state.mcp_server.new_connection(&session_id)?
+    let client = match resume.session_id {
+        Some(session_id) => match state.mcp_server.resume_client(&session_id)? {
+            Some(client) => {
+                tracing::debug!(session_id, "resumed session");
+                client
+            }
+            None => return Ok(StatusCode::NOT_FOUND.into_response()),
+        },
+        None => {
+            let client = state.mcp_server.new_session()?;
+            tracing::debug!("created client");
+            client
+        }
     };
+    let session_id = client.session_id.clone();
 
-    tracing::debug!("created client");
-
-    let session_uri = format!("{}?{}={}", state.endpoint, "sessionId", &session_id);
+    // Routers nested into a parent app only see the path past the nesting point
+    let endpoint = match original_uri.path().strip_suffix(uri.path()) {
+        Some(nested_path) if !nested_path.is_empty() => {
+            format!("{nested_path}/{}", state.endpoint.trim_start_matches('/'))
+        }
+        _ => state.endpoint.clone(),
+    };
+    let session_uri = format!("{}?{}={}", endpoint, "sessionId", &session_id);
 
     let mut endpoint_sent = false;
 
@@ -158,31 +250,62 @@ async fn sse_handler(
     struct Guard {
         session_id: SessionId,
         state: Arc<SseState>,
+        client: Option<Client>,
     }
 
     impl Drop for Guard {
         fn drop(&mut self) {
-            _ = self.state.mcp_server.close_connection(&self.session_id);
+            // With resumption on, the session outlives its stream for a while
+            if let Some(client) = self.client.take() {
+                match self.state.mcp_server.detach_client(client) {
+                    Ok(true) => return,
+                    Ok(false) => (),
+                    Err(err) => {
+                        tracing::error!(
+                            session_id = self.session_id,
+                            "failed to detach session: {err}"
+                        )
+                    }
+                }
+            }
+
+            match self.state.mcp_server.close_connection(&self.session_id) {
+                Ok(true) => (),
+                // Terminated sessions are removed before their stream ends
+                Ok(false) => {
+                    tracing::debug!(session_id = self.session_id, "session was already closed")
+                }
+                Err(err) => {
+                    tracing::error!(
+                        session_id = self.session_id,
+                        "failed to close session: {err}"
+                    )
+                }
+            }
         }
     }
 
-    let guard = Guard {
-        session_id: session_id.to_owned(),
+    // The guard owns the session id and client so the stream can close it on drop
+    let mut guard = Guard {
+        session_id,
         state: state.clone(),
+        client: Some(client),
     };
 
+    let keep_alive_interval = state.mcp_server.keep_alive_interval;
     let stream = try_stream! {
         tracing::debug!("Starting sse stream");
-        let _hold_guard = guard;
         loop {
             if !endpoint_sent {
                 endpoint_sent = true;
                 yield Event::default().event("endpoint").data(session_uri.clone())
             } else {
-                let mut_client = &mut client;
-                match mut_client.recv.recv().await {
+                let Some(client) = guard.client.as_mut() else {
+                    break;
+                };
+                match client.recv.recv().await {
                     Some(v) => {
-                        if let Some(message) = serde_json::to_string(&v.sse_message).ok() {
+                        if let Ok(message) = state.mcp_server.serialize_message(&v.sse_message) {
                             tracing::debug!("sending message");
                             yield Event::default().event("message").data(message)
                         } else {
@@ -199,62 +322,79 @@ async fn sse_handler(
         }
     };
 
-    Ok(Sse::new(stream).keep_alive(
-        axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(1))
-            .text("keep-alive-text"),
-    ))
+    Ok(event_stream_response(stream, keep_alive_interval))
+}
+
+// The keep-alive timer restarts on every event, so comments are only sent on idle streams
+fn event_stream_response<S>(stream: S, keep_alive_interval: Duration) -> Response
+where
+    S: Stream<Item = Result<Event>> + Send + 'static,
+{
+    Sse::new(stream)
+        .keep_alive(
+            axum::response::sse::KeepAlive::new()
+                .interval(keep_alive_interval)
+                .text("keep-alive-text"),
+        )
+        .into_response()
 }
 
 async fn message_handler(
     State(state): State<Arc<SseState>>,
     session_query: Query<SessionQuery>,
-    Json(message): Json<schema::JSONRPCMessage>,
-    // message: String
+    headers: HeaderMap,
+    MessageBody(body): MessageBody,
 ) -> Result<StatusCode> {
+    let session_id = session_query.0.session_id;
+
+    // Parse in two steps so a malformed request can still be answered using its id
+    let message = match schema::JSONRPCMessage::deserialize(&body) {
+        Ok(message) => message,
+        Err(err) => return reject_message(&state.mcp_server, &session_id, &body, err).await,
+    };
     tracing::debug!("{message:#?}");
 
-    let session_id = session_query.0.session_id;
+    let Some(res) = state
+        .mcp_server
+        .dispatch_message_with_headers(&session_id, message, context_headers(&headers))
+        .await?
+    else {
+        return Ok(StatusCode::OK);
+    };
 
-    let res = match message {
-        schema::JSONRPCMessage::Request(ref req) => {
-            handle_request(&state.mcp_server, req, &session_id)
-        }
-        schema::JSONRPCMessage::Notification(ref not) => {
-            handle_notification(&state.mcp_server, not, &session_id)?;
-            return Ok(StatusCode::OK);
-        }
-        _ => todo!(),
-    }?;
-
-    let client_conn = {
-        // Block here to drop lock slightly earlier
-        // let map = state
-        //     .mcp_server
-        //     .clients
-        //     .read()
-        //     .or_else(|_| Err(ApiError::PoisonedLock))?;
-
-        if let Some(client_conn) = state.mcp_server.clients.get(&session_id) {
-            client_conn.clone()
-        } else {
-            return Ok(StatusCode::OK);
+    // The session may have closed while the request was handled
+    match state.mcp_server.send_response(&session_id, res).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(ApiError::MissingClient) => {
+            tracing::debug!(session_id, "session closed before response was sent");
+            Ok(StatusCode::OK)
         }
+        Err(err) => Err(err),
+    }
+}
+
+/// Answers a request that is not valid JSON-RPC with an `INVALID_REQUEST` error when it has an
+/// id to reply to, otherwise rejects the post. Malformed responses are never answered
+async fn reject_message(
+    server: &Server,
+    session_id: &SessionId,
+    body: &Value,
+    err: serde_json::Error,
+) -> Result<StatusCode> {
+    tracing::debug!(session_id, "invalid message: {err}");
+
+    let Some(id) = body
+        .get("id")
+        .filter(|_| body.get("method").is_some())
+        .and_then(|id| schema::RequestId::deserialize(id).ok())
+    else {
+        return Ok(StatusCode::BAD_REQUEST);
     };
 
-    let tx = client_conn
-        .lock()
-        .or_else(|_| Err(ApiError::PoisonedLock))?
-        .send
-        .clone();
-
-    // TODO Ignore error for now
-    tx.send(Message {
-        session_id: session_id.to_owned(),
-        sse_message: res,
-    })
-    .await
-    .ok();
-
-    Ok(StatusCode::OK)
+    let response = create_error_response(&id, schema::INVALID_REQUEST, &err.to_string());
+    match server.send_message(session_id, response).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(ApiError::MissingClient) => Ok(StatusCode::NOT_FOUND),
+        Err(err) => Err(err),
+    }
 }
@@ -0,0 +1,66 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::mcp::schema;
+use crate::mcp::server::error::Result;
+use crate::mcp::server::{supported_methods, supported_notifications};
+
+use super::SseState;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HealthResponse {
+    status: &'static str,
+    name: String,
+    version: String,
+    uptime_secs: u64,
+    connections: usize,
+}
+
+/// Liveness probe, does not require an MCP session
+pub(crate) async fn health_handler(State(state): State<Arc<SseState>>) -> Json<HealthResponse> {
+    let server = &state.mcp_server;
+
+    Json(HealthResponse {
+        status: "ok",
+        name: server.info.name.to_owned(),
+        version: server.info.version.to_owned(),
+        uptime_secs: server.started_at.elapsed().as_secs(),
+        connections: server.clients.len(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CapabilitiesResponse {
+    server_info: schema::Implementation,
+    capabilities: schema::ServerCapabilities,
+    tools: Vec<String>,
+    prompts: Vec<String>,
+    resources: Vec<String>,
+    // Served spec methods, less the ones disabled on this server
+    methods: Vec<&'static str>,
+    notifications: &'static [&'static str],
+}
+
+/// Introspection of what the server exposes, without going through the MCP handshake
+pub(crate) async fn capabilities_handler(
+    State(state): State<Arc<SseState>>,
+) -> Result<Json<CapabilitiesResponse>> {
+    let server = &state.mcp_server;
+
+    Ok(Json(CapabilitiesResponse {
+        server_info: server.info.clone(),
+        capabilities: server.advertised_capabilities()?,
+        tools: server.tool_names()?,
+        prompts: server.prompt_names()?,
+        resources: server.resource_uris()?,
+        methods: supported_methods()
+            .iter()
+            .copied()
+            .filter(|method| server.is_method_enabled(method))
+            .collect(),
+        notifications: supported_notifications(),
+    }))
+}
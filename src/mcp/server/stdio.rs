@@ -0,0 +1,300 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::Semaphore;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::utils::{create_error_response, create_parse_error_response};
+use super::{Client, Server, SessionId};
+
+/// Largest value read from the input, the same as axum's default body limit for POSTed messages
+pub(crate) const DEFAULT_MAX_FRAME_SIZE: usize = 2 * 1024 * 1024;
+
+/// Splits a byte stream into JSON values. Values are read whole, so pretty-printed messages
+/// spanning several lines are fine and newlines between values are just whitespace.
+/// Newline-delimited input, as the MCP stdio transport specifies, is a subset
+pub(crate) struct FrameReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    scanner: FrameScanner,
+    max_frame_size: usize,
+    // Drops input up to the next newline, the rest of a line that failed to parse
+    skipping: bool,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self::with_max_frame_size(reader, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Reader failing values larger than `max_frame_size` bytes instead of buffering them
+    pub(crate) fn with_max_frame_size(reader: R, max_frame_size: usize) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            scanner: FrameScanner::default(),
+            max_frame_size,
+            skipping: false,
+        }
+    }
+
+    /// Next complete value, `None` once the input ends. After a syntax error or an oversized
+    /// value the rest of the offending line is skipped so the following messages can still
+    /// be read
+    pub(crate) async fn next(&mut self) -> Option<serde_json::Result<Value>> {
+        loop {
+            if self.skipping {
+                match self.buf.iter().position(|x| *x == b'\n') {
+                    Some(end) => {
+                        self.buf.drain(..=end);
+                        self.skipping = false;
+                    }
+                    None => self.buf.clear(),
+                }
+            }
+
+            if !self.skipping {
+                let end = self.scanner.frame_end(&self.buf);
+                let len = end.unwrap_or(self.buf.len());
+                if len > self.max_frame_size {
+                    self.buf.drain(..len);
+                    self.scanner = FrameScanner::default();
+                    self.skipping = true;
+                    return Some(Err(serde::de::Error::custom(format!(
+                        "Message larger than {} bytes, got at least {len}",
+                        self.max_frame_size
+                    ))));
+                }
+
+                if let Some(end) = end {
+                    let frame: Vec<u8> = self.buf.drain(..end).collect();
+                    self.scanner = FrameScanner::default();
+                    let value = serde_json::from_slice(&frame);
+                    self.skipping = value.is_err();
+                    return Some(value);
+                }
+                if !self.scanner.in_frame() {
+                    // Only whitespace so far
+                    self.buf.clear();
+                    self.scanner = FrameScanner::default();
+                }
+            }
+
+            match self.reader.read_buf(&mut self.buf).await {
+                Ok(0) => {
+                    if !self.buf.is_empty() && !self.skipping {
+                        tracing::debug!(len = self.buf.len(), "input ended mid message");
+                    }
+                    return None;
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    tracing::error!("failed to read input: {err}");
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Finds where a JSON value ends without parsing it, resuming where the previous call stopped
+/// so every byte is looked at once however the value is split across reads
+#[derive(Default)]
+struct FrameScanner {
+    // Bytes already scanned
+    offset: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // A top level number or literal, which ends at the next whitespace or value
+    in_scalar: bool,
+}
+
+impl FrameScanner {
+    /// Whether a value has started, as opposed to only whitespace being scanned
+    fn in_frame(&self) -> bool {
+        self.depth > 0 || self.in_string || self.in_scalar
+    }
+
+    /// Length of the first value in `buf`, with leading whitespace, once it is complete.
+    /// Malformed values end where their brackets balance, leaving the error to the parser
+    fn frame_end(&mut self, buf: &[u8]) -> Option<usize> {
+        while self.offset < buf.len() {
+            let i = self.offset;
+            self.offset += 1;
+
+            if self.in_string {
+                match buf[i] {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => {
+                        self.in_string = false;
+                        if self.depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+
+            match buf[i] {
+                x if self.in_scalar && (x.is_ascii_whitespace() || b"{[\"".contains(&x)) => {
+                    return Some(i);
+                }
+                x if x.is_ascii_whitespace() => (),
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                _ if self.depth == 0 => self.in_scalar = true,
+                _ => (),
+            }
+        }
+
+        None
+    }
+}
+
+pub(crate) async fn serve(server: Arc<Server>) -> Result<()> {
+    // Stdout carries the protocol, so logs go to stderr
+    _ = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
+        )
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        // The embedding application may have installed its own subscriber already
+        .try_init();
+
+    serve_io(server, tokio::io::stdin(), tokio::io::stdout()).await
+}
+
+/// Serves a single session over `reader` and `writer` until the input ends.
+///
+/// Replies go through the session queue and a separate task writes them, so a client that
+/// stops reading fills the queue and pauses reading instead of stalling request handling.
+/// Requests are handled concurrently, up to the queue depth, so a handler waiting on the
+/// client, e.g. for sampling, can still get its answer
+pub(crate) async fn serve_io<R, W>(server: Arc<Server>, reader: R, writer: W) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let client = server.new_session()?;
+    let session_id = client.session_id.clone();
+    let write = tokio::spawn(write_messages(server.clone(), client, writer));
+
+    let max_in_flight = server.queue_depth.max(1);
+    let in_flight = Arc::new(Semaphore::new(max_in_flight));
+    let mut frames = FrameReader::new(reader);
+    while let Some(frame) = frames.next().await {
+        let value = match frame {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::debug!(session_id, "invalid json: {err}");
+                let response = create_parse_error_response(&format!("Invalid JSON: {err}"));
+                if let Err(err) = server.send_message(&session_id, response).await {
+                    tracing::warn!(session_id, "failed to send parse error: {err}");
+                }
+                continue;
+            }
+        };
+
+        // Notifications and responses are handled in order, e.g. `initialized` must land
+        // before the requests that follow it
+        if value.get("method").is_none() || value.get("id").is_none() {
+            handle_value(&server, &session_id, value).await;
+            continue;
+        }
+
+        // The semaphore is never closed
+        let Ok(permit) = in_flight.clone().acquire_owned().await else {
+            break;
+        };
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            handle_value(&server, &session_id, value).await;
+            drop(permit);
+        });
+    }
+
+    // Let running requests reply before the queue is closed
+    let _drained = in_flight.acquire_many(max_in_flight as u32).await;
+    server.close_connection(&session_id)?;
+
+    match write.await {
+        Ok(written) => written,
+        Err(err) => Err(ApiError::HandlerError(err.to_string())),
+    }
+}
+
+/// Dispatches one inbound message, queueing any reply on the session. Like a failed post on
+/// SSE, a message that errors doesn't end the session
+async fn handle_value(server: &Arc<Server>, session_id: &SessionId, value: Value) {
+    if let Err(err) = dispatch_value(server, session_id, value).await {
+        tracing::warn!(session_id, "failed to handle message: {err}");
+    }
+}
+
+async fn dispatch_value(server: &Arc<Server>, session_id: &SessionId, value: Value) -> Result<()> {
+    let message = match schema::JSONRPCMessage::deserialize(&value) {
+        Ok(message) => message,
+        Err(err) => {
+            tracing::debug!(session_id, "invalid message: {err}");
+            // Only requests are answered, never a malformed response
+            let Some(id) = value
+                .get("id")
+                .filter(|_| value.get("method").is_some())
+                .and_then(|id| schema::RequestId::deserialize(id).ok())
+            else {
+                return Ok(());
+            };
+
+            let response = create_error_response(&id, schema::INVALID_REQUEST, &err.to_string());
+            return server.send_message(session_id, response).await;
+        }
+    };
+
+    match server.dispatch_message(session_id, message).await? {
+        Some(response) => server.send_message(session_id, response).await,
+        None => Ok(()),
+    }
+}
+
+/// Writes queued messages one per line until the session closes. Bursts are written to the
+/// buffer together and flushed once the queue is empty
+async fn write_messages<W: AsyncWrite + Unpin>(
+    server: Arc<Server>,
+    mut client: Client,
+    writer: W,
+) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+
+    while let Some(message) = client.recv.recv().await {
+        let mut next = Some(message);
+        while let Some(message) = next {
+            match server.serialize_message(&message.sse_message) {
+                Ok(data) => {
+                    writer.write_all(data.as_bytes()).await?;
+                    writer.write_all(b"\n").await?;
+                }
+                Err(_) => tracing::debug!("Error Deserialize: {:#?}", message.sse_message),
+            }
+            next = client.recv.try_recv().ok();
+        }
+
+        writer.flush().await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use crate::mcp::schema;
+
+use super::error::{ApiError, Result};
+use super::{Server, SessionId};
+
+impl Server {
+    /// Sends `notifications/resources/updated` for `uri` to every session subscribed to it.
+    /// Sessions waiting for their stream to resume get it once they reconnect.
+    ///
+    /// Returns the number of sessions it was sent to
+    pub async fn notify_resource_updated(&self, uri: &str) -> Result<usize> {
+        self.invalidate_resource(uri)?;
+
+        let mut session_ids = Vec::new();
+        for entry in self.clients.iter() {
+            let client_conn = entry.value().lock().map_err(|_| ApiError::PoisonedLock)?;
+            if client_conn.subscriptions.contains(uri) {
+                session_ids.push(entry.key().clone());
+            }
+        }
+
+        let mut sent = 0;
+        for session_id in session_ids {
+            let notification = schema::JSONRPCNotification {
+                json_rpc: schema::JSONRPC_VERSION.into(),
+                params: schema::NotificationParams::ResourceUpdated(
+                    schema::ResourceUpdatedNotificationParams {
+                        uri: uri.to_string(),
+                    },
+                ),
+            };
+
+            match self
+                .send_message(
+                    &session_id,
+                    schema::JSONRPCMessage::Notification(notification),
+                )
+                .await
+            {
+                Ok(()) => sent += 1,
+                Err(ApiError::MissingClient) => (),
+                Err(err) => tracing::warn!(session_id, "failed to notify resource update: {err}"),
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Uris the session is subscribed to
+    pub fn subscriptions(&self, session_id: &SessionId) -> Result<Vec<String>> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        Ok(client_conn.subscriptions.iter().cloned().collect())
+    }
+
+    /// Adds or removes `uri` from the session subscriptions, which live as long as the session
+    /// so a resumed stream keeps them
+    fn set_subscribed(&self, session_id: &SessionId, uri: &str, subscribed: bool) -> Result<()> {
+        let lock = self
+            .clients
+            .get(session_id)
+            .ok_or(ApiError::MissingClient)?;
+
+        let mut client_conn = lock.lock().map_err(|_| ApiError::PoisonedLock)?;
+        if subscribed {
+            client_conn.subscriptions.insert(uri.to_string());
+        } else {
+            client_conn.subscriptions.remove(uri);
+        }
+
+        Ok(())
+    }
+}
+
+/// Answers `resources/subscribe` and `resources/unsubscribe`, once enabled with
+/// [`ServerBuilder::resource_subscriptions`](super::ServerBuilder::resource_subscriptions)
+pub(crate) fn handle_subscribe(
+    server: &Server,
+    uri: &str,
+    session_id: &SessionId,
+    subscribed: bool,
+) -> Result<schema::ServerResult> {
+    server.set_subscribed(session_id, uri, subscribed)?;
+
+    Ok(schema::ServerResult::Empty(schema::EmptyResult::default()))
+}
@@ -0,0 +1,114 @@
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use super::error::{ApiError, Result};
+use super::{InitializeStatus, Server, SessionId};
+
+/// Limits on how long sessions may sit without doing anything
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SweepConfig {
+    pub(crate) init_timeout: Option<Duration>,
+    pub(crate) idle_timeout: Option<Duration>,
+}
+
+impl SweepConfig {
+    /// Time between sweeps, so a session outlives its limit by at most half of it
+    fn interval(&self) -> Option<Duration> {
+        let shortest = match (self.init_timeout, self.idle_timeout) {
+            (Some(init), Some(idle)) => init.min(idle),
+            (timeout, None) | (None, timeout) => timeout?,
+        };
+
+        Some((shortest / 2).max(Duration::from_millis(10)))
+    }
+}
+
+impl Server {
+    /// Periodically hands sessions past their init or idle timeout to the close listener.
+    /// Stops once the server is dropped
+    pub(crate) fn spawn_sweeper(self: &Arc<Self>) {
+        let Some(period) = self.sweep.interval() else {
+            return;
+        };
+
+        let server = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+                if let Err(err) = sweep(&server).await {
+                    tracing::warn!("failed to sweep sessions: {err}");
+                }
+                if server.strong_count() == 0 {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Marks the session active for the idle timeout
+    pub(crate) fn touch_session(&self, session_id: &SessionId) -> Result<()> {
+        if let Some(lock) = self.clients.get(session_id) {
+            lock.lock()
+                .map_err(|_| ApiError::PoisonedLock)?
+                .last_inbound = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Sessions that stayed uninitialized or idle for too long
+    fn expired_sessions(&self) -> Result<Vec<SessionId>> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+
+        for entry in self.clients.iter() {
+            let client_conn = entry.value().lock().map_err(|_| ApiError::PoisonedLock)?;
+            // Waiting on a handler or on the client is not idle
+            if !client_conn.in_flight.is_empty() || !client_conn.pending_requests.is_empty() {
+                continue;
+            }
+
+            let never_initialized = matches!(
+                client_conn.initialize_status,
+                InitializeStatus::NotInitialized
+            ) && self
+                .sweep
+                .init_timeout
+                .is_some_and(|timeout| now - client_conn.connected_at >= timeout);
+            let idle = self
+                .sweep
+                .idle_timeout
+                .is_some_and(|timeout| now - client_conn.last_inbound >= timeout);
+
+            if never_initialized || idle {
+                expired.push(entry.key().clone());
+            }
+        }
+
+        Ok(expired)
+    }
+}
+
+async fn sweep(server: &Weak<Server>) -> Result<()> {
+    // Don't keep the server alive between sweeps
+    let (expired, send) = {
+        let Some(server) = server.upgrade() else {
+            return Ok(());
+        };
+        (server.expired_sessions()?, server.send_close_client.clone())
+    };
+
+    for session_id in expired {
+        tracing::debug!(session_id, "reaping inactive session");
+        // The close channel is bounded, so a large sweep waits on the listener
+        if send.send(session_id).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
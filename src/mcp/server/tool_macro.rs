@@ -0,0 +1,170 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::error::{ApiError, Result};
+use crate::mcp::schema;
+
+/// Types usable as arguments of [`crate::tool!`] functions, describing their JSON schema
+pub trait ToolArgument: DeserializeOwned {
+    /// Whether callers have to pass the argument
+    const REQUIRED: bool = true;
+
+    fn json_schema() -> Value;
+}
+
+macro_rules! impl_tool_argument {
+    ($json_type:literal => $($ty:ty),+) => {
+        $(
+            impl ToolArgument for $ty {
+                fn json_schema() -> Value {
+                    json!({ "type": $json_type })
+                }
+            }
+        )+
+    };
+}
+
+impl_tool_argument!("string" => String);
+impl_tool_argument!("boolean" => bool);
+impl_tool_argument!("integer" => i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_tool_argument!("number" => f32, f64);
+
+impl ToolArgument for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: ToolArgument> ToolArgument for Option<T> {
+    const REQUIRED: bool = false;
+
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: ToolArgument> ToolArgument for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: ToolArgument> ToolArgument for HashMap<String, T> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+/// Builds the definition of a [`crate::tool!`] tool from its doc comment lines and
+/// `(name, schema, required)` arguments
+#[doc(hidden)]
+pub fn tool_definition(
+    name: &str,
+    docs: &[&str],
+    arguments: &[(&str, Value, bool)],
+) -> schema::Tool {
+    let description = docs.iter().map(|x| x.trim()).collect::<Vec<_>>().join("\n");
+    let description = description.trim();
+
+    schema::Tool {
+        name: name.to_string(),
+        description: (!description.is_empty()).then(|| description.to_string()),
+        input_schema: schema::ToolInputSchemaType::Object(schema::ToolInputSchema {
+            properties: Some(
+                arguments
+                    .iter()
+                    .map(|(name, schema, _)| (name.to_string(), schema.clone()))
+                    .collect(),
+            ),
+            required: arguments
+                .iter()
+                .filter(|(_, _, required)| *required)
+                .map(|(name, _, _)| name.to_string())
+                .collect(),
+        }),
+        output_schema: None,
+    }
+}
+
+/// Takes argument `name` out of a [`crate::tool!`] call, missing ones deserialize from null
+#[doc(hidden)]
+pub fn tool_argument<T: ToolArgument>(
+    arguments: &mut HashMap<String, Value>,
+    name: &str,
+) -> Result<T> {
+    let value = arguments.remove(name).unwrap_or(Value::Null);
+
+    serde_path_to_error::deserialize(value).map_err(|err| {
+        let path = match err.path().to_string().as_str() {
+            "." => name.to_string(),
+            path => format!("{name}.{path}"),
+        };
+        ApiError::InvalidArguments {
+            path,
+            message: err.inner().to_string(),
+        }
+    })
+}
+
+/// Declares a tool from an async function. The doc comment becomes the description and the
+/// input schema is derived from the argument types, `Option` ones being optional. Expands to a
+/// module named after the function with `tool()`, `call(..)` and `register(&Server)`, so it
+/// has to be used at module level for the function to see the surrounding imports
+///
+/// ```
+/// use rust_mcp::prelude::*;
+///
+/// tool! {
+///     /// Adds two numbers
+///     async fn add(left: i64, right: i64) -> rust_mcp::Result<schema::CallToolResult> {
+///         Ok(schema::CallToolResult::text(&(left + right).to_string()))
+///     }
+/// }
+///
+/// fn main() {
+///     let server = ServerBuilder::new("calculator", "0.1.0").build();
+///     add::register(&server).unwrap();
+///     assert_eq!(add::tool().description.as_deref(), Some("Adds two numbers"));
+/// }
+/// ```
+#[macro_export]
+macro_rules! tool {
+    (
+        $(#[doc = $doc:literal])*
+        $vis:vis async fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty $body:block
+    ) => {
+        $vis mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub fn tool() -> $crate::schema::Tool {
+                $crate::mcp::server::tool_definition(
+                    stringify!($name),
+                    &[$($doc),*],
+                    &[$((
+                        stringify!($arg),
+                        <$ty as $crate::mcp::server::ToolArgument>::json_schema(),
+                        <$ty as $crate::mcp::server::ToolArgument>::REQUIRED,
+                    )),*],
+                )
+            }
+
+            pub async fn call($($arg: $ty),*) -> $ret $body
+
+            pub fn register(server: &$crate::Server) -> $crate::Result<()> {
+                server.register_tool(tool(), |arguments| async move {
+                    #[allow(unused_mut, unused_variables)]
+                    let mut arguments = arguments.unwrap_or_default();
+                    $(
+                        let $arg = $crate::mcp::server::tool_argument::<$ty>(
+                            &mut arguments,
+                            stringify!($arg),
+                        )?;
+                    )*
+                    call($($arg),*).await
+                })
+            }
+        }
+    };
+}
@@ -1,15 +1,183 @@
+use futures::FutureExt;
+use serde_json::Value;
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
 use crate::mcp::schema::{self, JSONRPCError, JSONRPCMessage};
 
+use super::error::{ApiError, Result};
+
 pub fn create_error_response(id: &schema::RequestId, code: i64, message: &str) -> JSONRPCMessage {
+    error_response(id, code, message, None)
+}
+
+/// Error response for a request that failed with `err`, see [`ApiError::code`]. Invalid params
+/// keep their bare message and argument errors point at the offending field in `data`
+pub fn error_response_for(id: &schema::RequestId, err: &ApiError) -> JSONRPCMessage {
+    match err {
+        ApiError::InvalidParams(message) => create_error_response(id, err.code(), message),
+        ApiError::InvalidArguments { path, message } => create_error_response_with_data(
+            id,
+            err.code(),
+            message,
+            schema::ErrorData::validation(path, message),
+        ),
+        err => create_error_response(id, err.code(), &err.to_string()),
+    }
+}
+
+/// `PARSE_ERROR` for a message that could not be read. The id can't be known, so it is null
+/// as JSON-RPC specifies
+pub fn create_parse_error_response(message: &str) -> JSONRPCMessage {
+    create_error_response(&schema::RequestId::Null, schema::PARSE_ERROR, message)
+}
+
+/// Same as [`create_error_response`] with additional information about the error in `data`,
+/// either raw JSON or a [`schema::ErrorData`]
+pub fn create_error_response_with_data(
+    id: &schema::RequestId,
+    code: i64,
+    message: &str,
+    data: impl Into<Value>,
+) -> JSONRPCMessage {
+    error_response(id, code, message, Some(data.into()))
+}
+
+fn error_response(
+    id: &schema::RequestId,
+    code: i64,
+    message: &str,
+    data: Option<Value>,
+) -> JSONRPCMessage {
     let err = JSONRPCError {
         json_rpc: schema::JSONRPC_VERSION.into(),
         id: id.to_owned(),
         error: schema::ErrorParams {
             code,
             message: message.into(),
-            data: None,
+            data,
         },
     };
 
     JSONRPCMessage::Response(schema::JSONRPCResponse::Error(err))
 }
+
+/// Replacement for a message of `size` bytes over the `max` limit. Responses keep their id so
+/// the request still completes, anything else becomes an error log
+pub(crate) fn oversized_message(
+    message: &JSONRPCMessage,
+    size: usize,
+    max: usize,
+) -> JSONRPCMessage {
+    let reason = format!("Message of {size} bytes exceeds the {max} byte limit");
+
+    match message {
+        JSONRPCMessage::Response(schema::JSONRPCResponse::Result(schema::JSONRPCResult {
+            id,
+            ..
+        }))
+        | JSONRPCMessage::Response(schema::JSONRPCResponse::Error(JSONRPCError { id, .. })) => {
+            create_error_response(id, schema::INTERNAL_ERROR, &reason)
+        }
+        _ => JSONRPCMessage::Notification(schema::JSONRPCNotification {
+            json_rpc: schema::JSONRPC_VERSION.into(),
+            params: schema::NotificationParams::LoggingMessage(
+                schema::LoggingMessageNotificationParams {
+                    meta: None,
+                    level: schema::LoggingLevel::Error,
+                    logger: None,
+                    data: serde_json::Value::String(reason),
+                },
+            ),
+        }),
+    }
+}
+
+/// Tool result reporting a failed execution. Tool failures are regular results flagged with
+/// `isError` so the model can see them, unlike protocol errors such as an unknown tool
+pub fn tool_error_result(msg: &str) -> schema::CallToolResult {
+    schema::CallToolResult::error(vec![schema::CallToolContent::Text(
+        schema::TextContent::new(msg),
+    )])
+}
+
+pub fn create_result_response(
+    id: &schema::RequestId,
+    result: schema::ResultEnum,
+) -> JSONRPCMessage {
+    let res = schema::JSONRPCResult {
+        id: id.to_owned(),
+        json_rpc: schema::JSONRPC_VERSION.into(),
+        result: schema::Result {
+            base: schema::ResultBase::default(),
+            defined_fields: result,
+        },
+    };
+
+    JSONRPCMessage::Response(schema::JSONRPCResponse::Result(res))
+}
+
+/// Timeout for a request given the server limit and the client's hint, the client can only
+/// shorten it
+pub fn effective_timeout(limit: Option<Duration>, hint: Option<Duration>) -> Option<Duration> {
+    match (limit, hint) {
+        (Some(limit), Some(hint)) => Some(limit.min(hint)),
+        (limit, hint) => limit.or(hint),
+    }
+}
+
+/// Awaits `fut`, cancelling it if it runs past `timeout`
+pub async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(duration) => tokio::time::timeout(duration, fut)
+            .await
+            .map_err(|_| ApiError::Timeout(duration))?,
+        None => fut.await,
+    }
+}
+
+/// Runs a synchronous handler, turning a panic into [`ApiError::HandlerPanicked`]
+pub fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| panicked(payload))
+}
+
+/// Runs a synchronous handler like [`catch_panic`]. With a `timeout` it runs on the blocking
+/// pool so the request can be answered once it passes, the handler itself can't be stopped
+pub async fn with_timeout_blocking<T: Send + 'static>(
+    timeout: Option<Duration>,
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    if timeout.is_none() {
+        return catch_panic(f);
+    }
+
+    let task = tokio::task::spawn_blocking(move || catch_panic(f));
+    with_timeout(timeout, async {
+        task.await.map_err(|_| ApiError::HandlerPanicked)?
+    })
+    .await
+}
+
+/// Same as [`catch_panic`] for async handlers, covering both the call and the future
+pub async fn catch_panic_async<T>(fut: impl Future<Output = Result<T>>) -> Result<T> {
+    AssertUnwindSafe(fut)
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|payload| panicked(payload))
+}
+
+fn panicked<T>(payload: Box<dyn Any + Send>) -> Result<T> {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    tracing::error!("handler panicked: {message}");
+
+    Err(ApiError::HandlerPanicked)
+}
@@ -0,0 +1,118 @@
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+use crate::mcp::schema::*;
+use crate::mcp::server::{
+    notification::handle_notification, request::handle_request, Client, Server,
+};
+
+pub fn request(id: i64, method: &str, params: Value) -> JSONRPCRequest {
+    serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    }))
+    .unwrap()
+}
+
+pub fn tool(name: &str) -> Tool {
+    serde_json::from_value(json!({
+        "name": name,
+        "inputSchema": { "type": "object", "required": [] }
+    }))
+    .unwrap()
+}
+
+pub fn text_result(text: &str) -> CallToolResult {
+    serde_json::from_value(json!({
+        "content": [{ "type": "text", "text": text }]
+    }))
+    .unwrap()
+}
+
+/// Params of an initialize request from a client declaring `capabilities`
+pub fn initialize_params(capabilities: Value) -> Value {
+    json!({
+        "protocolVersion": "2024-11-05",
+        "capabilities": capabilities,
+        "clientInfo": { "name": "test-client", "version": "0.1" }
+    })
+}
+
+/// Initialize request from a client declaring `capabilities`
+pub fn initialize_request(id: i64, capabilities: Value) -> JSONRPCRequest {
+    request(id, "initialize", initialize_params(capabilities))
+}
+
+/// Connects a session and completes the initialize handshake
+pub async fn initialized_session(server: &Server, session_id: &str) -> Client {
+    initialized_session_with(server, session_id, json!({})).await
+}
+
+/// Same as [`initialized_session`], declaring the client `capabilities`
+pub async fn initialized_session_with(
+    server: &Server,
+    session_id: &str,
+    capabilities: Value,
+) -> Client {
+    let session_id = session_id.to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    let initialize = initialize_request(0, capabilities);
+    let response = handle_request(server, &initialize, &session_id)
+        .await
+        .unwrap();
+    // Delivered like a transport would so the request stops being tracked, then discarded
+    server.send_response(&session_id, response).await.unwrap();
+    client.recv.try_recv().unwrap();
+
+    let initialized: JSONRPCNotification = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    }))
+    .unwrap();
+    handle_notification(server, &initialized, &session_id).unwrap();
+
+    client
+}
+
+/// Unwraps the JSON value of a response message
+pub fn response_json(message: JSONRPCMessage) -> Value {
+    match message {
+        JSONRPCMessage::Response(response) => serde_json::to_value(response).unwrap(),
+        other => panic!("Expected a response but got {other:?}"),
+    }
+}
+
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `f` with a subscriber recording every event on this thread, returning the output
+/// alongside the logs
+pub fn capture_logs<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    let subscriber = {
+        let logs = logs.clone();
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || LogBuffer(logs.clone()))
+            .finish()
+    };
+
+    let output = tracing::subscriber::with_default(subscriber, f);
+    let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+
+    (output, logs)
+}
@@ -0,0 +1,148 @@
+use serde_json::{json, Value};
+
+use super::common::*;
+use crate::mcp::schema::*;
+use crate::mcp::server::{error::Result, request::handle_request, Server};
+
+fn complete_request(reference: Value) -> JSONRPCRequest {
+    request(
+        1,
+        "completion/complete",
+        json!({ "ref": reference, "argument": { "name": "lang", "value": "ru" } }),
+    )
+}
+
+fn complete_with(values: &[&str]) -> impl Fn(CompleteRequestArgument) -> Result<CompleteResult> {
+    let values: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+    move |argument| {
+        Ok(CompleteResult {
+            values: values
+                .iter()
+                .filter(|x| x.starts_with(&argument.value))
+                .cloned()
+                .collect(),
+            total: None,
+            has_more: None,
+        })
+    }
+}
+
+async fn completion_values(server: &Server, reference: Value) -> Value {
+    let session_id = "session".to_string();
+    let response = handle_request(server, &complete_request(reference), &session_id)
+        .await
+        .unwrap();
+    // Delivered like a transport would, so the request id can be used again
+    server
+        .send_response(&session_id, response.clone())
+        .await
+        .unwrap();
+    let response = response_json(response);
+
+    response["result"]["values"].clone()
+}
+
+#[tokio::test]
+async fn complete_known_resource_template() {
+    let server = Server::new("test", "0.1", 0);
+    let _client = initialized_session(&server, "session").await;
+
+    server
+        .register_resource_template(
+            serde_json::from_value(json!({ "uriTemplate": "file:///{lang}", "name": "src" }))
+                .unwrap(),
+        )
+        .unwrap();
+    let reference = CompleteRequestRef::Resource {
+        uri: "file:///{lang}".to_string(),
+    };
+    server
+        .register_completion(reference, complete_with(&["rust", "ruby", "go"]))
+        .unwrap();
+
+    let values = completion_values(
+        &server,
+        json!({ "type": "ref/resource", "uri": "file:///{lang}" }),
+    )
+    .await;
+    assert_eq!(values, json!(["rust", "ruby"]));
+}
+
+#[tokio::test]
+async fn complete_known_prompt() {
+    let server = Server::new("test", "0.1", 0);
+    let _client = initialized_session(&server, "session").await;
+
+    server
+        .register_prompt(
+            serde_json::from_value(json!({ "name": "review" })).unwrap(),
+            |_| Ok(vec![]),
+        )
+        .unwrap();
+    let reference = CompleteRequestRef::Prompt {
+        name: "review".to_string(),
+    };
+    server
+        .register_completion(reference, complete_with(&["rust", "go"]))
+        .unwrap();
+
+    let values =
+        completion_values(&server, json!({ "type": "ref/prompt", "name": "review" })).await;
+    assert_eq!(values, json!(["rust"]));
+}
+
+#[tokio::test]
+async fn complete_unknown_ref_is_empty() {
+    let server = Server::new("test", "0.1", 0);
+    let _client = initialized_session(&server, "session").await;
+
+    // The provider exists but the resource it completes for was never registered
+    let reference = CompleteRequestRef::Resource {
+        uri: "file:///{lang}".to_string(),
+    };
+    server
+        .register_completion(reference, complete_with(&["rust"]))
+        .unwrap();
+
+    let values = completion_values(
+        &server,
+        json!({ "type": "ref/resource", "uri": "file:///{lang}" }),
+    )
+    .await;
+    assert_eq!(values, json!([]));
+
+    let values =
+        completion_values(&server, json!({ "type": "ref/prompt", "name": "missing" })).await;
+    assert_eq!(values, json!([]));
+}
+
+#[tokio::test]
+async fn completion_providers_get_the_request_context() {
+    let server = Server::new("test", "0.1", 0);
+    let _client = initialized_session(&server, "session").await;
+
+    server
+        .register_prompt(
+            serde_json::from_value(json!({ "name": "review" })).unwrap(),
+            |_| Ok(vec![]),
+        )
+        .unwrap();
+    server
+        .register_completion_with_context(
+            CompleteRequestRef::Prompt {
+                name: "review".to_string(),
+            },
+            |context, _| {
+                Ok(CompleteResult {
+                    values: vec![format!("{} {}", context.session_id(), context.request_id())],
+                    total: None,
+                    has_more: None,
+                })
+            },
+        )
+        .unwrap();
+
+    let values =
+        completion_values(&server, json!({ "type": "ref/prompt", "name": "review" })).await;
+    assert_eq!(values, json!(["session 1"]));
+}
@@ -0,0 +1,247 @@
+use serde_json::json;
+use tracing::Level;
+
+use super::common::*;
+use crate::mcp::schema::*;
+use crate::mcp::server::{request::handle_request, Server};
+
+fn logging_params(message: JSONRPCMessage) -> LoggingMessageNotificationParams {
+    match message {
+        JSONRPCMessage::Notification(JSONRPCNotification {
+            params: NotificationParams::LoggingMessage(params),
+            ..
+        }) => params,
+        other => panic!("Expected a logging notification but got {other:?}"),
+    }
+}
+
+#[test]
+fn logging_level_serialize() {
+    assert_eq!(json!(LoggingLevel::Debug), json!("debug"));
+    assert_eq!(json!(LoggingLevel::Emergency), json!("emergency"));
+}
+
+#[tokio::test]
+async fn send_log_sets_logger() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    let delivered = server
+        .send_log(
+            &session_id,
+            LoggingLevel::Info,
+            Some("db"),
+            json!("connected"),
+        )
+        .await
+        .unwrap();
+    assert!(delivered);
+
+    let params = logging_params(client.recv.recv().await.unwrap().sse_message);
+    assert_eq!(params.level, LoggingLevel::Info);
+    assert_eq!(params.logger.as_deref(), Some("db"));
+    assert_eq!(params.data, json!("connected"));
+}
+
+#[tokio::test]
+async fn send_log_filters_muted_logger() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    server.mute_logger(&session_id, "noisy").unwrap();
+
+    let delivered = server
+        .send_log(
+            &session_id,
+            LoggingLevel::Debug,
+            Some("noisy"),
+            json!("tick"),
+        )
+        .await
+        .unwrap();
+    assert!(!delivered);
+
+    let delivered = server
+        .send_log(&session_id, LoggingLevel::Info, Some("app"), json!("ready"))
+        .await
+        .unwrap();
+    assert!(delivered);
+
+    let params = logging_params(client.recv.recv().await.unwrap().sse_message);
+    assert_eq!(params.logger.as_deref(), Some("app"));
+    assert!(client.recv.try_recv().is_err());
+
+    server.unmute_logger(&session_id, "noisy").unwrap();
+    let delivered = server
+        .send_log(
+            &session_id,
+            LoggingLevel::Debug,
+            Some("noisy"),
+            json!("tick"),
+        )
+        .await
+        .unwrap();
+    assert!(delivered);
+}
+
+#[tokio::test]
+async fn logging_notification_wire_shape() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    server
+        .send_log(
+            &session_id,
+            LoggingLevel::Warning,
+            Some("db"),
+            json!("slow"),
+        )
+        .await
+        .unwrap();
+
+    let message = client.recv.recv().await.unwrap().sse_message;
+    assert_eq!(
+        serde_json::to_value(&message).unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": {
+                "_meta": { "sessionId": "session" },
+                "level": "warning",
+                "logger": "db",
+                "data": "slow"
+            }
+        })
+    );
+}
+
+#[test]
+fn logging_level_tracing_level() {
+    let cases = [
+        (LoggingLevel::Debug, Level::DEBUG),
+        (LoggingLevel::Info, Level::INFO),
+        (LoggingLevel::Notice, Level::INFO),
+        (LoggingLevel::Warning, Level::WARN),
+        (LoggingLevel::Error, Level::ERROR),
+        (LoggingLevel::Critical, Level::ERROR),
+        (LoggingLevel::Alert, Level::ERROR),
+        (LoggingLevel::Emergency, Level::ERROR),
+    ];
+
+    for (level, expected) in cases {
+        assert_eq!(Level::from(&level), expected, "{level:?}");
+    }
+}
+
+#[tokio::test]
+async fn send_log_traced_delivers() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    let delivered = server
+        .send_log_traced(&session_id, LoggingLevel::Alert, None, json!("disk full"))
+        .await
+        .unwrap();
+    assert!(delivered);
+
+    let params = logging_params(client.recv.recv().await.unwrap().sse_message);
+    assert_eq!(params.level, LoggingLevel::Alert);
+    assert_eq!(params.data, json!("disk full"));
+}
+
+#[test]
+fn logging_level_orders_by_severity() {
+    assert!(LoggingLevel::Debug < LoggingLevel::Warning);
+    assert!(LoggingLevel::Warning < LoggingLevel::Emergency);
+    assert!(LoggingLevel::Notice > LoggingLevel::Info);
+    assert_eq!(
+        [
+            LoggingLevel::Alert,
+            LoggingLevel::Debug,
+            LoggingLevel::Error
+        ]
+        .into_iter()
+        .max(),
+        Some(LoggingLevel::Alert)
+    );
+}
+
+#[tokio::test]
+async fn set_level_filters_lower_severity() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = initialized_session(&server, &session_id).await;
+
+    let set_level = request(1, "logging/setLevel", json!({ "level": "warning" }));
+    let response = response_json(
+        handle_request(&server, &set_level, &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"], json!({}));
+
+    let mut delivered = vec![];
+    for level in [
+        LoggingLevel::Info,
+        LoggingLevel::Warning,
+        LoggingLevel::Debug,
+        LoggingLevel::Critical,
+    ] {
+        if server
+            .send_log(&session_id, level, None, json!("event"))
+            .await
+            .unwrap()
+        {
+            delivered.push(level);
+        }
+    }
+    assert_eq!(delivered, [LoggingLevel::Warning, LoggingLevel::Critical]);
+
+    let received: Vec<LoggingLevel> = std::iter::from_fn(|| client.recv.try_recv().ok())
+        .map(|x| logging_params(x.sse_message).level)
+        .collect();
+    assert_eq!(received, delivered);
+}
+
+#[test]
+fn logs_are_tagged_with_their_session() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let server = Server::new("test", "0.1", 0);
+    let sessions = ["first".to_string(), "second".to_string()];
+
+    let (delivered, logs) = capture_logs(|| {
+        runtime.block_on(async {
+            let mut delivered = Vec::new();
+            for session_id in &sessions {
+                let mut client = server.new_connection(session_id).unwrap();
+                server
+                    .send_log(session_id, LoggingLevel::Info, None, json!(session_id))
+                    .await
+                    .unwrap();
+                delivered.push(logging_params(
+                    client.recv.recv().await.unwrap().sse_message,
+                ));
+            }
+            delivered
+        })
+    });
+
+    for (session_id, params) in sessions.iter().zip(delivered) {
+        assert_eq!(params.data, json!(session_id));
+        assert_eq!(params.meta.unwrap()["sessionId"], json!(session_id));
+
+        let span = format!("send_log{{session_id=\"{session_id}\"}}");
+        assert!(
+            logs.lines()
+                .any(|x| x.contains(&span) && x.contains("sending log")),
+            "{logs}"
+        );
+    }
+}
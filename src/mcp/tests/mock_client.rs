@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use crate::mcp::schema::*;
+use crate::mcp::server::{response::handle_response, Client, Server};
+
+/// Client attached to a session that records every message the server sends it and answers
+/// server requests on demand
+pub struct MockClient {
+    server: Arc<Server>,
+    client: Client,
+    received: Vec<JSONRPCMessage>,
+}
+
+impl MockClient {
+    pub fn connect(server: Arc<Server>, session_id: &str) -> Self {
+        let client = server.new_connection(session_id).unwrap();
+
+        Self {
+            server,
+            client,
+            received: Vec::new(),
+        }
+    }
+
+    /// Every message received so far, in order
+    pub fn received(&mut self) -> &[JSONRPCMessage] {
+        while let Ok(message) = self.client.recv.try_recv() {
+            self.received.push(message.sse_message);
+        }
+
+        &self.received
+    }
+
+    pub fn received_notifications(&mut self) -> Vec<JSONRPCNotification> {
+        self.received()
+            .iter()
+            .filter_map(|x| match x {
+                JSONRPCMessage::Notification(notification) => Some(notification.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Waits for the next request from the server, recording everything received before it
+    pub async fn next_request(&mut self) -> JSONRPCRequest {
+        loop {
+            let message = self.client.recv.recv().await.unwrap().sse_message;
+            self.received.push(message.clone());
+
+            if let JSONRPCMessage::Request(request) = message {
+                return request;
+            }
+        }
+    }
+
+    /// Answers the server request `id` with `result`
+    pub fn respond(&self, id: RequestId, result: ResultEnum) {
+        let response = JSONRPCResponse::Result(JSONRPCResult {
+            json_rpc: JSONRPC_VERSION.into(),
+            id,
+            result: Result {
+                base: ResultBase::default(),
+                defined_fields: result,
+            },
+        });
+
+        handle_response(&self.server, &response, &self.client.session_id).unwrap();
+    }
+}
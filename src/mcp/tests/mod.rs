@@ -1,3 +1,14 @@
+mod common;
+mod completion_test;
+mod logging_test;
+mod mock_client;
+mod outbound_test;
+mod prompt_test;
+mod resource_test;
 mod schema_test;
-
-
+mod server_test;
+mod service_test;
+mod sse_test;
+mod stdio_test;
+mod tool_test;
+mod wire_test;
@@ -0,0 +1,271 @@
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+use super::common::capture_logs;
+use super::mock_client::MockClient;
+use crate::mcp::schema::*;
+use crate::mcp::server::{
+    error::ApiError, notification::handle_notification, response::handle_response,
+    OutboundProgress, Server,
+};
+
+fn sampling_params() -> CreateMessageRequestParams {
+    serde_json::from_value(json!({
+        "messages": [{ "role": "user", "content": { "type": "text", "text": "hi" } }],
+        "maxTokens": 10
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn outbound_request_routes_progress() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let progress = {
+        let reported = reported.clone();
+        OutboundProgress::new(ProgressToken::String("sample".to_string()), move |x| {
+            reported.lock().unwrap().push(x.progress)
+        })
+    };
+
+    let task = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            server
+                .create_message(&session_id, sampling_params(), Some(progress))
+                .await
+        })
+    };
+
+    let request = match client.recv.recv().await.unwrap().sse_message {
+        JSONRPCMessage::Request(request) => request,
+        other => panic!("Expected a request but got {other:?}"),
+    };
+    let wire = serde_json::to_value(&request).unwrap();
+    assert_eq!(wire["method"], "sampling/createMessage");
+    assert_eq!(wire["params"]["_meta"]["progressToken"], "sample");
+
+    let notification: JSONRPCNotification = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/progress",
+        "params": { "progressToken": "sample", "progress": 50, "total": 100 }
+    }))
+    .unwrap();
+    handle_notification(&server, &notification, &session_id).unwrap();
+    assert_eq!(*reported.lock().unwrap(), vec![50]);
+
+    let response: JSONRPCResponse = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": request.id,
+        "result": {
+            "role": "assistant",
+            "content": { "type": "text", "text": "hello" },
+            "model": "test-model"
+        }
+    }))
+    .unwrap();
+    handle_response(&server, &response, &session_id).unwrap();
+
+    let result = task.await.unwrap().unwrap();
+    assert_eq!(result.model, "test-model");
+
+    // Progress for a finished request is no longer routed
+    handle_notification(&server, &notification, &session_id).unwrap();
+    assert_eq!(reported.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn disconnect_fails_pending_requests() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    let task = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            server
+                .create_message(&session_id, sampling_params(), None)
+                .await
+        })
+    };
+
+    let request = match client.recv.recv().await.unwrap().sse_message {
+        JSONRPCMessage::Request(request) => request,
+        other => panic!("Expected a request but got {other:?}"),
+    };
+    assert_eq!(
+        server.pending_requests(&session_id).unwrap(),
+        vec![request.id]
+    );
+
+    assert!(server.terminate_session(&session_id).unwrap());
+    assert!(matches!(task.await.unwrap(), Err(ApiError::SessionClosed)));
+}
+
+#[tokio::test]
+async fn malformed_results_are_invalid_responses() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    let task = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            server
+                .create_message(&session_id, sampling_params(), None)
+                .await
+        })
+    };
+
+    let request = match client.recv.recv().await.unwrap().sse_message {
+        JSONRPCMessage::Request(request) => request,
+        other => panic!("Expected a request but got {other:?}"),
+    };
+    // Missing the model and with a content of the wrong type
+    let response: JSONRPCResponse = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": request.id,
+        "result": { "role": "assistant", "content": 1 }
+    }))
+    .unwrap();
+    handle_response(&server, &response, &session_id).unwrap();
+
+    assert!(matches!(
+        task.await.unwrap(),
+        Err(ApiError::InvalidResponse(_))
+    ));
+}
+
+#[tokio::test]
+async fn mock_client_answers_sampling() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let mut mock = MockClient::connect(server.clone(), "session");
+
+    let task = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            server
+                .create_message(&"session".to_string(), sampling_params(), None)
+                .await
+        })
+    };
+
+    let request = mock.next_request().await;
+    let result: CreateMessageResult = serde_json::from_value(json!({
+        "role": "assistant",
+        "content": { "type": "text", "text": "hello" },
+        "model": "test-model"
+    }))
+    .unwrap();
+    mock.respond(request.id, ResultEnum::CreateMessage(result));
+
+    assert_eq!(task.await.unwrap().unwrap().model, "test-model");
+    assert_eq!(mock.received().len(), 1);
+}
+
+#[tokio::test]
+async fn outbound_response_logs_latency() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let mut mock = MockClient::connect(server.clone(), "session");
+
+    let task = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            server
+                .list_roots(
+                    &"session".to_string(),
+                    serde_json::from_value(json!({})).unwrap(),
+                    None,
+                )
+                .await
+        })
+    };
+
+    let request = mock.next_request().await;
+    let id = request.id.clone();
+    let result: ListRootResult = serde_json::from_value(json!({ "roots": [] })).unwrap();
+    let ((), logs) = capture_logs(|| mock.respond(request.id, ResultEnum::ListRoot(result)));
+
+    task.await.unwrap().unwrap();
+    let line = logs
+        .lines()
+        .find(|x| x.contains("client answered request"))
+        .unwrap();
+    assert!(line.contains(&format!(
+        "outbound_request{{session_id=\"session\" id={id}}}"
+    )));
+    assert!(line.contains("latency_us="));
+}
+
+#[tokio::test]
+async fn generic_request_pings_the_client() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let mut mock = MockClient::connect(server.clone(), "session");
+
+    let task = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            server
+                .request(
+                    &"session".to_string(),
+                    ServerRequestParams::Ping(serde_json::from_value(json!({})).unwrap()),
+                )
+                .await
+        })
+    };
+
+    let request = mock.next_request().await;
+    assert_eq!(serde_json::to_value(&request).unwrap()["method"], "ping");
+    mock.respond(
+        request.id,
+        ResultEnum::Empty(serde_json::from_value(json!({})).unwrap()),
+    );
+
+    assert!(matches!(
+        task.await.unwrap().unwrap(),
+        ClientResult::Empty(_)
+    ));
+    assert!(server
+        .pending_requests(&"session".to_string())
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn abandoned_outbound_requests_are_forgotten() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let mut mock = MockClient::connect(server.clone(), "session");
+
+    let task = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            let progress =
+                OutboundProgress::new(ProgressToken::String("sample".to_string()), |_| ());
+            server
+                .create_message(&"session".to_string(), sampling_params(), Some(progress))
+                .await
+        })
+    };
+
+    mock.next_request().await;
+    assert_eq!(
+        server
+            .pending_requests(&"session".to_string())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    task.abort();
+    assert!(task.await.unwrap_err().is_cancelled());
+    assert!(server
+        .pending_requests(&"session".to_string())
+        .unwrap()
+        .is_empty());
+}
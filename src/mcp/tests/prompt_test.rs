@@ -0,0 +1,200 @@
+use serde_json::json;
+use std::sync::Arc;
+
+use super::common::*;
+use crate::mcp::schema::{Prompt, PromptMessage, ReadResourceResult, Role, INVALID_PARAMS};
+use crate::mcp::server::{request::handle_request, PromptTemplate, Server};
+
+fn greeting() -> Prompt {
+    serde_json::from_value(json!({
+        "name": "greet",
+        "arguments": [
+            { "name": "name", "required": true },
+            { "name": "title" }
+        ]
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn prompt_renders_messages_from_arguments() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_prompt(
+            serde_json::from_value(json!({
+                "name": "review",
+                "arguments": [{ "name": "file", "required": true }]
+            }))
+            .unwrap(),
+            |arguments| {
+                let file = &arguments["file"];
+                let contents = ReadResourceResult::text(file, Some("text/plain"), "fn main() {}");
+                Ok(vec![
+                    PromptMessage::text(Role::User, &format!("Review {file}")),
+                    PromptMessage::resource(Role::User, contents.contents[0].clone()),
+                ])
+            },
+        )
+        .unwrap();
+
+    let get = request(
+        1,
+        "prompts/get",
+        json!({ "name": "review", "arguments": { "file": "file:///main.rs" } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+
+    assert_eq!(
+        response["result"]["messages"],
+        json!([
+            {
+                "role": "user",
+                "content": { "type": "text", "text": "Review file:///main.rs" }
+            },
+            {
+                "role": "user",
+                "content": {
+                    "type": "resource",
+                    "resource": {
+                        "uri": "file:///main.rs",
+                        "mimeType": "text/plain",
+                        "text": "fn main() {}"
+                    }
+                }
+            }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn static_prompt_substitutes_arguments() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_static_prompt(greeting(), "Hello {title}{name}, {mood}?")
+        .unwrap();
+    server
+        .register_static_prompt(
+            Prompt {
+                name: "strict".to_string(),
+                ..greeting()
+            },
+            PromptTemplate::new("Hello {name}, {mood}?").strict(true),
+        )
+        .unwrap();
+
+    let get = request(
+        1,
+        "prompts/get",
+        json!({ "name": "greet", "arguments": { "name": "Ada" } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["messages"],
+        json!([{ "role": "user", "content": { "type": "text", "text": "Hello Ada, {mood}?" } }])
+    );
+
+    let get = request(
+        2,
+        "prompts/get",
+        json!({ "name": "strict", "arguments": { "name": "Ada", "mood": "ok" } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["messages"][0]["content"]["text"],
+        "Hello Ada, ok?"
+    );
+
+    let get = request(
+        3,
+        "prompts/get",
+        json!({ "name": "strict", "arguments": { "name": "Ada" } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+}
+
+#[tokio::test]
+async fn static_prompt_missing_required_argument() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_static_prompt(greeting(), "Hello {name}")
+        .unwrap();
+
+    let get = request(
+        1,
+        "prompts/get",
+        json!({ "name": "greet", "arguments": { "title": "Dr. " } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    assert_eq!(
+        response["error"]["message"],
+        "Missing required argument: name"
+    );
+}
+
+// Real time, paused time doesn't advance while a blocking task runs
+#[tokio::test]
+async fn client_deadline_bounds_prompts() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let release = Arc::new(std::sync::Barrier::new(2));
+    {
+        let release = release.clone();
+        server
+            .register_prompt(greeting(), move |_| {
+                release.wait();
+                Ok(vec![])
+            })
+            .unwrap();
+    }
+
+    let get = request(
+        1,
+        "prompts/get",
+        json!({ "name": "greet", "arguments": { "name": "x" }, "_meta": { "timeoutMs": 20 } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+    assert_eq!(response["error"]["message"], "Timed out after 20ms");
+
+    // Lets the abandoned handler finish
+    release.wait();
+}
+
+#[tokio::test]
+async fn prompt_handlers_get_the_request_context() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_prompt_with_context(greeting(), |context, _| {
+            Ok(vec![PromptMessage::text(
+                Role::User,
+                &format!("asked by {}", context.session_id()),
+            )])
+        })
+        .unwrap();
+
+    let get = request(
+        1,
+        "prompts/get",
+        json!({ "name": "greet", "arguments": { "name": "x" } }),
+    );
+    let response = response_json(handle_request(&server, &get, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["messages"][0]["content"]["text"],
+        "asked by session"
+    );
+}
@@ -0,0 +1,502 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use futures::StreamExt;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::common::*;
+use crate::mcp::schema::{
+    ContentsResource, JSONRPCMessage, ReadResourceResult, Resource, ResourceEncoding,
+    METHOD_NOT_FOUND,
+};
+use crate::mcp::server::{
+    request::handle_request, NotificationDropPolicy, ResourceChunk, Server, ServerBuilder,
+    RESOURCE_CHUNKS_CAPABILITY, RESOURCE_CHUNK_METHOD,
+};
+
+const CHUNK_SIZE: usize = 16 * 1024;
+const CHUNKS: usize = 256;
+const QUEUE_DEPTH: usize = 8;
+
+#[tokio::test]
+async fn large_resource_streams_in_chunks() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .queue_depth(QUEUE_DEPTH)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let mut client = initialized_session_with(
+        &server,
+        &session_id,
+        json!({ "experimental": { RESOURCE_CHUNKS_CAPABILITY: {} } }),
+    )
+    .await;
+
+    let produced = Arc::new(AtomicUsize::new(0));
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///big.bin", "name": "big" })).unwrap();
+    {
+        let produced = produced.clone();
+        server
+            .register_resource_stream(resource, move |_| {
+                let produced = produced.clone();
+                futures::stream::iter(0..CHUNKS).map(move |i| {
+                    produced.fetch_add(1, Ordering::SeqCst);
+                    Ok(ResourceChunk::Blob(vec![i as u8; CHUNK_SIZE]))
+                })
+            })
+            .unwrap();
+    }
+
+    let read = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let read = request(1, "resources/read", json!({ "uri": "file:///big.bin" }));
+            handle_request(&server, &read, &session_id).await.unwrap()
+        })
+    };
+
+    let mut received = 0;
+    while received < CHUNKS {
+        let notification = match client.recv.recv().await.unwrap().sse_message {
+            JSONRPCMessage::Notification(notification) => {
+                serde_json::to_value(notification).unwrap()
+            }
+            other => panic!("Expected a chunk but got {other:?}"),
+        };
+        assert_eq!(notification["method"], RESOURCE_CHUNK_METHOD);
+        assert_eq!(notification["params"]["index"], received);
+
+        let blob = notification["params"]["blob"].as_str().unwrap();
+        assert_eq!(
+            BASE64_STANDARD.decode(blob).unwrap(),
+            vec![received as u8; CHUNK_SIZE]
+        );
+        received += 1;
+
+        // Only the client's queue and the chunk being sent are held, never the whole resource
+        let buffered = (produced.load(Ordering::SeqCst) - received) * CHUNK_SIZE;
+        assert!(buffered <= (QUEUE_DEPTH + 1) * CHUNK_SIZE);
+        tokio::task::yield_now().await;
+    }
+
+    let response = response_json(read.await.unwrap());
+    assert_eq!(response["result"]["contents"], json!([]));
+    assert_eq!(response["result"]["_meta"]["chunks"], CHUNKS);
+}
+
+#[tokio::test]
+async fn streamed_resources_are_inline_without_the_capability() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = initialized_session(&server, &session_id).await;
+
+    let resource: Resource = serde_json::from_value(
+        json!({ "uri": "file:///log.txt", "name": "log", "mimeType": "text/plain" }),
+    )
+    .unwrap();
+    server
+        .register_resource_stream(resource, |_| {
+            futures::stream::iter(["a", "b", "c"]).map(|x| Ok(ResourceChunk::Text(x.to_string())))
+        })
+        .unwrap();
+    let capabilities = server.advertised_capabilities().unwrap();
+    assert!(capabilities.experimental.unwrap()[RESOURCE_CHUNKS_CAPABILITY].is_object());
+
+    let read = request(1, "resources/read", json!({ "uri": "file:///log.txt" }));
+    let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["contents"],
+        json!([{ "uri": "file:///log.txt", "mimeType": "text/plain", "text": "abc" }])
+    );
+    assert!(response["result"].get("_meta").is_none());
+    assert!(client.recv.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn cached_resource_is_read_again_after_update() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").resource_cache(8).build());
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///a.txt", "name": "a" })).unwrap();
+    {
+        let reads = reads.clone();
+        server
+            .register_resource(resource, move |uri| {
+                let count = reads.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    Ok(ReadResourceResult::text(
+                        &uri,
+                        None,
+                        &format!("read {count}"),
+                    ))
+                }
+            })
+            .unwrap();
+    }
+
+    let read = |id| request(id, "resources/read", json!({ "uri": "file:///a.txt" }));
+    for id in 1..=2 {
+        let read = read(id);
+        let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+        assert_eq!(response["result"]["contents"][0]["text"], "read 1");
+    }
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+    server
+        .notify_resource_updated("file:///a.txt")
+        .await
+        .unwrap();
+    let response = response_json(
+        handle_request(&server, &read(3), &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"]["contents"][0]["text"], "read 2");
+    assert_eq!(reads.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn cached_resource_is_read_again_after_registry_swap() {
+    let server = ServerBuilder::new("test", "0.1").resource_cache(8).build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///a.txt", "name": "a" })).unwrap();
+    server
+        .register_resource(resource.clone(), |uri| async move {
+            Ok(ReadResourceResult::text(&uri, None, "old"))
+        })
+        .unwrap();
+
+    let read = |id| request(id, "resources/read", json!({ "uri": "file:///a.txt" }));
+    let response = response_json(
+        handle_request(&server, &read(1), &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"]["contents"][0]["text"], "old");
+
+    let staging = Server::new("test", "0.1", 0);
+    staging
+        .register_resource(resource, |uri| async move {
+            Ok(ReadResourceResult::text(&uri, None, "new"))
+        })
+        .unwrap();
+    server
+        .replace_registry(staging.into_registry().unwrap())
+        .await
+        .unwrap();
+
+    let response = response_json(
+        handle_request(&server, &read(2), &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"]["contents"][0]["text"], "new");
+}
+
+#[tokio::test]
+async fn blob_contents_keep_only_the_raw_bytes() {
+    const SIZE: usize = 1024 * 1024;
+
+    let bytes: Vec<u8> = (0..SIZE).map(|i| i as u8).collect();
+    let ptr = bytes.as_ptr();
+    let result = ReadResourceResult::blob("file:///big.bin", None, bytes);
+    match &result.contents[0] {
+        // Same allocation the provider handed over, not a base64 copy of it
+        ContentsResource::Blob(blob) => {
+            assert_eq!(blob.blob.as_bytes().len(), SIZE);
+            assert_eq!(blob.blob.as_bytes().as_ptr(), ptr);
+        }
+        other => panic!("Expected a blob but got {other:?}"),
+    }
+
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///big.bin", "name": "big" })).unwrap();
+    server
+        .register_resource(resource, |uri| async move {
+            Ok(ReadResourceResult::blob(
+                &uri,
+                None,
+                (0..SIZE).map(|i| i as u8).collect::<Vec<_>>(),
+            ))
+        })
+        .unwrap();
+
+    let read = request(1, "resources/read", json!({ "uri": "file:///big.bin" }));
+    let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+    let blob = response["result"]["contents"][0]["blob"].as_str().unwrap();
+    assert_eq!(blob.len(), SIZE.div_ceil(3) * 4);
+    assert_eq!(
+        BASE64_STANDARD.decode(blob).unwrap(),
+        (0..SIZE).map(|i| i as u8).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn utf8_text_resource() {
+    let contents =
+        ContentsResource::from_bytes("file:///a.md", Some("text/markdown"), "# héllo".as_bytes());
+    assert_eq!(
+        serde_json::to_value(contents).unwrap(),
+        json!({ "uri": "file:///a.md", "mimeType": "text/markdown", "text": "# héllo" })
+    );
+
+    let contents = ContentsResource::from_bytes("file:///a.json", None, b"{}");
+    assert!(matches!(contents, ContentsResource::Text(_)));
+}
+
+#[test]
+fn binary_resource() {
+    let bytes = [0x89, b'P', b'N', b'G', 0xff, 0x00];
+    let contents = ContentsResource::from_bytes("file:///a.png", Some("image/png"), &bytes);
+    assert_eq!(
+        serde_json::to_value(contents).unwrap(),
+        json!({
+            "uri": "file:///a.png",
+            "mimeType": "image/png",
+            "blob": BASE64_STANDARD.encode(bytes)
+        })
+    );
+
+    // Valid UTF-8 is still a blob when the mime type is not text, unless overridden
+    let contents =
+        ContentsResource::from_bytes("file:///a.bin", Some("application/octet-stream"), b"abc");
+    assert!(matches!(contents, ContentsResource::Blob(_)));
+    let contents = ContentsResource::from_bytes_as(
+        "file:///a.bin",
+        Some("application/octet-stream"),
+        b"abc",
+        ResourceEncoding::Text,
+    );
+    assert!(matches!(contents, ContentsResource::Text(_)));
+}
+
+#[test]
+fn text_mime_over_binary_falls_back_to_blob() {
+    let bytes = [b'h', b'i', 0xc3, 0x28];
+    let contents =
+        ContentsResource::from_bytes("file:///a.txt", Some("text/plain; charset=utf-8"), &bytes);
+    assert!(matches!(contents, ContentsResource::Blob(_)));
+
+    let contents = ContentsResource::from_bytes_as(
+        "file:///a.txt",
+        Some("text/plain"),
+        &bytes,
+        ResourceEncoding::Text,
+    );
+    assert!(matches!(contents, ContentsResource::Blob(_)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_deadline_bounds_resource_reads() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_secs(5))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///slow", "name": "slow" })).unwrap();
+    server
+        .register_resource(resource, |uri| async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(ReadResourceResult::text(&uri, None, "done"))
+        })
+        .unwrap();
+
+    let read = request(
+        1,
+        "resources/read",
+        json!({ "uri": "file:///slow", "_meta": { "timeoutMs": 20 } }),
+    );
+    let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+    assert_eq!(response["error"]["message"], "Timed out after 20ms");
+}
+
+#[tokio::test]
+async fn resource_readers_get_the_request_context() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///whoami", "name": "whoami" })).unwrap();
+    server
+        .register_resource_with_context(resource, |context, uri| async move {
+            Ok(ReadResourceResult::text(&uri, None, context.session_id()))
+        })
+        .unwrap();
+
+    let read = request(1, "resources/read", json!({ "uri": "file:///whoami" }));
+    let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+    assert_eq!(response["result"]["contents"][0]["text"], "session");
+}
+
+#[tokio::test]
+async fn resource_updated_during_a_read_is_not_cached() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").resource_cache(8).build());
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let release = Arc::new(tokio::sync::Notify::new());
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///a.txt", "name": "a" })).unwrap();
+    {
+        let reads = reads.clone();
+        let release = release.clone();
+        server
+            .register_resource(resource, move |uri| {
+                let count = reads.fetch_add(1, Ordering::SeqCst) + 1;
+                let release = release.clone();
+                async move {
+                    if count == 1 {
+                        release.notified().await;
+                    }
+                    Ok(ReadResourceResult::text(
+                        &uri,
+                        None,
+                        &format!("read {count}"),
+                    ))
+                }
+            })
+            .unwrap();
+    }
+
+    let first = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let read = request(1, "resources/read", json!({ "uri": "file:///a.txt" }));
+            response_json(handle_request(&server, &read, &session_id).await.unwrap())
+        })
+    };
+    while reads.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    // Lands while the first read is still running, its contents are already stale
+    server
+        .notify_resource_updated("file:///a.txt")
+        .await
+        .unwrap();
+    release.notify_one();
+    assert_eq!(
+        first.await.unwrap()["result"]["contents"][0]["text"],
+        "read 1"
+    );
+
+    let read = request(2, "resources/read", json!({ "uri": "file:///a.txt" }));
+    let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+    assert_eq!(response["result"]["contents"][0]["text"], "read 2");
+}
+
+#[tokio::test]
+async fn streamed_chunks_are_never_dropped() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .queue_depth(2)
+            .notification_drop_policy(NotificationDropPolicy::DropNewest)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let mut client = initialized_session_with(
+        &server,
+        &session_id,
+        json!({ "experimental": { RESOURCE_CHUNKS_CAPABILITY: {} } }),
+    )
+    .await;
+
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///log.txt", "name": "log" })).unwrap();
+    server
+        .register_resource_stream(resource, |_| {
+            futures::stream::iter(0..10).map(|i| Ok(ResourceChunk::Text(i.to_string())))
+        })
+        .unwrap();
+
+    let read = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let read = request(1, "resources/read", json!({ "uri": "file:///log.txt" }));
+            handle_request(&server, &read, &session_id).await.unwrap()
+        })
+    };
+
+    for index in 0..10 {
+        let message = client.recv.recv().await.unwrap().sse_message;
+        let chunk = serde_json::to_value(message).unwrap();
+        assert_eq!(chunk["params"]["text"], index.to_string());
+    }
+    let response = response_json(read.await.unwrap());
+    assert_eq!(response["result"]["_meta"]["chunks"], 10);
+}
+
+#[tokio::test(start_paused = true)]
+async fn resource_streams_time_out() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_secs(1))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let resource: Resource =
+        serde_json::from_value(json!({ "uri": "file:///tail", "name": "tail" })).unwrap();
+    server
+        .register_resource_stream(resource, |_| {
+            futures::stream::iter([Ok(ResourceChunk::Text("first".to_string()))])
+                .chain(futures::stream::pending())
+        })
+        .unwrap();
+
+    let read = request(1, "resources/read", json!({ "uri": "file:///tail" }));
+    let response = response_json(handle_request(&server, &read, &session_id).await.unwrap());
+    assert_eq!(response["error"]["message"], "Timed out after 1s");
+}
+
+#[tokio::test]
+async fn subscriptions_are_opt_in() {
+    let subscribe = request(1, "resources/subscribe", json!({ "uri": "file:///a.txt" }));
+
+    let server = Server::new("test", "0.1", 0);
+    let _client = initialized_session(&server, "session").await;
+    let capabilities = server.advertised_capabilities().unwrap();
+    assert!(capabilities.resources.is_none());
+
+    let response = response_json(
+        handle_request(&server, &subscribe, &"session".to_string())
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+    let server = ServerBuilder::new("test", "0.1")
+        .resource_subscriptions(true)
+        .build();
+    let _client = initialized_session(&server, "session").await;
+    let capabilities = server.advertised_capabilities().unwrap();
+    assert_eq!(capabilities.resources.unwrap()["subscribe"], true);
+
+    let response = response_json(
+        handle_request(&server, &subscribe, &"session".to_string())
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"], json!({}));
+    assert_eq!(
+        server.subscriptions(&"session".to_string()).unwrap(),
+        vec!["file:///a.txt".to_string()]
+    );
+}
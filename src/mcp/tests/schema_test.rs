@@ -4,6 +4,10 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::mcp::schema::*;
+use crate::mcp::server::error::ApiError;
+use crate::mcp::server::utils::{
+    create_error_response, create_error_response_with_data, error_response_for,
+};
 
 #[derive(Serialize, Deserialize)]
 struct TestProgress {
@@ -18,11 +22,11 @@ fn progress_token_deserialize() {
             "progressToken": "hi"
         }"#;
 
-    let test_progress: TestProgress = serde_json::from_str(&data).unwrap();
+    let test_progress: TestProgress = serde_json::from_str(data).unwrap();
 
     match test_progress.progress_token {
-        ProgressToken::Number(num) => panic!("Progress Token should be a string but got {num}"),
         ProgressToken::String(val) => assert_eq!(val, "hi"),
+        other => panic!("Progress Token should be a string but got {other:?}"),
     }
 }
 
@@ -37,6 +41,7 @@ fn initialize_message_deserialize() {
                 experimental: None,
                 roots: Some(RootCapabilities { list_changed: None }),
                 sampling: Some(HashMap::new()),
+                extra: HashMap::new(),
             },
             client_info: Implementation {
                 version: "0.0.1".to_string(),
@@ -90,3 +95,479 @@ fn initialized_notification_deserialize() {
 
     assert_eq!(message, correct_msg);
 }
+
+#[test]
+fn contents_resource_into_embedded_resource() {
+    let text: ContentsResource = serde_json::from_value(json!({
+        "uri": "file:///notes.txt",
+        "mimeType": "text/plain",
+        "text": "hello"
+    }))
+    .unwrap();
+    let blob: ContentsResource = serde_json::from_value(json!({
+        "uri": "file:///image.png",
+        "blob": "aGVsbG8="
+    }))
+    .unwrap();
+
+    match EmbeddedResourceEnum::from(text.clone()) {
+        EmbeddedResourceEnum::Text(x) => assert_eq!(ContentsResource::Text(x), text),
+        EmbeddedResourceEnum::Blob(_) => panic!("Text contents should stay text"),
+    }
+
+    match EmbeddedResourceEnum::from(blob.clone()) {
+        EmbeddedResourceEnum::Blob(x) => assert_eq!(ContentsResource::Blob(x), blob),
+        EmbeddedResourceEnum::Text(_) => panic!("Blob contents should stay blob"),
+    }
+}
+
+#[test]
+fn embedded_resource_into_contents_resource() {
+    let text: EmbeddedResourceEnum = serde_json::from_value(json!({
+        "uri": "file:///notes.txt",
+        "text": "hello"
+    }))
+    .unwrap();
+    let blob: EmbeddedResourceEnum = serde_json::from_value(json!({
+        "uri": "file:///image.png",
+        "mimeType": "image/png",
+        "blob": "aGVsbG8="
+    }))
+    .unwrap();
+
+    match ContentsResource::from(text.clone()) {
+        ContentsResource::Text(x) => assert_eq!(EmbeddedResourceEnum::Text(x), text),
+        ContentsResource::Blob(_) => panic!("Text contents should stay text"),
+    }
+
+    match ContentsResource::from(blob.clone()) {
+        ContentsResource::Blob(x) => assert_eq!(EmbeddedResourceEnum::Blob(x), blob),
+        ContentsResource::Text(_) => panic!("Blob contents should stay blob"),
+    }
+}
+
+#[test]
+fn request_id_display() {
+    assert_eq!(RequestId::Number(5).to_string(), "5");
+    assert_eq!(RequestId::String("abc".to_string()).to_string(), "abc");
+}
+
+#[test]
+fn request_id_keys_are_distinct() {
+    let number = RequestId::Number(5);
+    let string = RequestId::String("5".to_string());
+
+    assert_ne!(number.key(), string.key());
+    assert_eq!(number.key(), RequestId::Number(5).key());
+
+    let mut pending = HashMap::new();
+    pending.insert(number.clone(), "number");
+    pending.insert(string.clone(), "string");
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[&number], "number");
+    assert_eq!(pending[&string], "string");
+}
+
+#[test]
+fn protocol_version_unknown_round_trips() {
+    let version: ProtocolVersion = serde_json::from_value(json!("2099-01-01")).unwrap();
+    assert_eq!(version, ProtocolVersion::Unknown("2099-01-01".to_string()));
+    assert_eq!(json!(version), json!("2099-01-01"));
+
+    let version: ProtocolVersion = serde_json::from_value(json!("2025-03-26")).unwrap();
+    assert_eq!(version, ProtocolVersion::Mcp2025_03_26);
+}
+
+#[test]
+fn protocol_version_negotiation() {
+    for version in ProtocolVersion::supported_versions() {
+        assert_eq!(&ProtocolVersion::negotiate(version), version);
+    }
+
+    // Unsupported versions get the highest version the server speaks
+    let unknown = ProtocolVersion::Unknown("2099-01-01".to_string());
+    assert_eq!(
+        ProtocolVersion::negotiate(&unknown),
+        *ProtocolVersion::supported_versions().last().unwrap()
+    );
+    assert_eq!(
+        &ProtocolVersion::negotiate(&unknown),
+        LATEST_PROTOCOL_VERSION
+    );
+}
+
+#[test]
+fn custom_request_deserialize() {
+    let request: JSONRPCRequest = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "x-foo/bar",
+        "params": { "a": 1 }
+    }))
+    .unwrap();
+
+    assert_eq!(
+        request.params,
+        RequestParams::Custom(CustomRequestParams {
+            method: "x-foo/bar".to_string(),
+            params: json!({ "a": 1 }),
+        })
+    );
+}
+
+#[test]
+fn request_methods_match_their_params() {
+    for method in REQUEST_METHODS {
+        let request: JSONRPCRequest =
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 1, "method": method }))
+                .unwrap_or_else(|err| panic!("{method}: {err}"));
+        // Methods with required params land in `Custom` when they are missing
+        if !matches!(request.params, RequestParams::Custom(_)) {
+            assert_eq!(request.params.method(), *method);
+            assert_eq!(
+                CLIENT_REQUEST_METHODS.contains(method),
+                !request.params.is_server_request()
+            );
+        }
+    }
+
+    // Not a method, only a base of the list requests
+    let request: JSONRPCRequest =
+        serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 1, "method": "paginated" }))
+            .unwrap();
+    assert!(matches!(request.params, RequestParams::Custom(_)));
+}
+
+#[test]
+fn server_capabilities_merge() {
+    let mut capabilities: ServerCapabilities =
+        serde_json::from_value(json!({ "tools": { "listChanged": true } })).unwrap();
+    let resources: ServerCapabilities = serde_json::from_value(json!({
+        "resources": { "subscribe": true, "listChanged": false }
+    }))
+    .unwrap();
+
+    capabilities.merge(&resources);
+    assert_eq!(
+        serde_json::to_value(&capabilities).unwrap(),
+        json!({
+            "tools": { "listChanged": true },
+            "resources": { "subscribe": true, "listChanged": false }
+        })
+    );
+
+    // Flags stay set once either side sets them
+    let tools: ServerCapabilities = serde_json::from_value(json!({
+        "tools": { "listChanged": false },
+        "resources": { "listChanged": true }
+    }))
+    .unwrap();
+    capabilities.merge(&tools);
+    capabilities.merge(&ServerCapabilities::default());
+    assert_eq!(capabilities.tools.unwrap()["listChanged"], true);
+    assert_eq!(capabilities.resources.unwrap()["listChanged"], true);
+}
+
+#[test]
+fn call_tool_result_is_error_shape() {
+    assert_eq!(
+        serde_json::to_value(CallToolResult::text("done")).unwrap(),
+        json!({ "content": [{ "type": "text", "text": "done" }], "isError": false })
+    );
+    assert_eq!(
+        serde_json::to_value(CallToolResult::error(vec![])).unwrap(),
+        json!({ "content": [], "isError": true })
+    );
+
+    // Results from clients may omit it, which the spec reads as success
+    let result: CallToolResult = serde_json::from_value(json!({ "content": [] })).unwrap();
+    assert_eq!(result.is_error, None);
+}
+
+#[test]
+fn role_string_round_trip() {
+    for role in [Role::User, Role::Assistant] {
+        let text = role.to_string();
+        assert_eq!(serde_json::to_value(&role).unwrap(), json!(text));
+        assert_eq!(text.parse::<Role>().unwrap(), role);
+    }
+    assert_eq!("Assistant".parse::<Role>().unwrap(), Role::Assistant);
+
+    assert_eq!(
+        "system".parse::<Role>(),
+        Err(UnknownRole("system".to_string()))
+    );
+}
+
+#[test]
+fn error_data_shapes() {
+    let data: serde_json::Value = ErrorData::validation("options.size", "expected u32").into();
+    assert_eq!(
+        data,
+        json!({ "path": "options.size", "reason": "expected u32" })
+    );
+
+    let data: serde_json::Value =
+        ErrorData::unsupported_version(ProtocolVersion::supported_versions().to_vec()).into();
+    assert_eq!(data, json!({ "supported": ["2024-11-05", "2025-03-26"] }));
+
+    let parsed: ErrorData = serde_json::from_value(data).unwrap();
+    assert!(matches!(parsed, ErrorData::UnsupportedVersion { .. }));
+
+    let response = create_error_response_with_data(
+        &RequestId::Number(1),
+        INVALID_PARAMS,
+        "bad size",
+        ErrorData::validation("size", "expected u32"),
+    );
+    assert_eq!(
+        serde_json::to_value(response).unwrap()["error"]["data"],
+        json!({ "path": "size", "reason": "expected u32" })
+    );
+}
+
+#[test]
+fn call_tool_result_builder_mixes_content() {
+    let result = CallToolResult::builder()
+        .text("resized")
+        .image(b"png", "image/png")
+        .build();
+
+    assert_eq!(
+        serde_json::to_value(result).unwrap(),
+        json!({
+            "content": [
+                { "type": "text", "text": "resized" },
+                { "type": "image", "data": "cG5n", "mimeType": "image/png" }
+            ],
+            "isError": false
+        })
+    );
+
+    let result = CallToolResult::builder()
+        .embedded_resource(ContentsResource::Text(TextResourceContents {
+            resource_contents_base: ResourceContents::new("file:///a.txt", None),
+            text: "a".to_string(),
+        }))
+        .error()
+        .build();
+    assert_eq!(
+        serde_json::to_value(result).unwrap(),
+        json!({
+            "content": [
+                { "type": "resource", "resource": { "uri": "file:///a.txt", "text": "a" } }
+            ],
+            "isError": true
+        })
+    );
+}
+
+#[test]
+fn client_notification_params_round_trip() {
+    let progress = ClientNotificationParams::Progress(ProgressNotificationParams {
+        progress_token: ProgressToken::Number(1),
+        progress: 5,
+        total: Some(10),
+    });
+    let cancelled = ClientNotificationParams::Cancelled(
+        serde_json::from_value(json!({ "requestId": 3, "reason": "stop" })).unwrap(),
+    );
+
+    for params in [progress, cancelled] {
+        let general: NotificationParams = params.clone().into();
+        assert_eq!(
+            Option::<ClientNotificationParams>::from(general),
+            Some(params)
+        );
+    }
+}
+
+#[test]
+fn client_result_round_trip() {
+    let roots = ClientResult::ListRoot(
+        serde_json::from_value(json!({ "roots": [{ "uri": "file:///src" }] })).unwrap(),
+    );
+    let empty = ClientResult::Empty(EmptyResult::default());
+
+    for result in [roots, empty] {
+        let general: ResultEnum = result.clone().into();
+        assert_eq!(Option::<ClientResult>::from(general), Some(result));
+    }
+}
+
+#[test]
+fn content_annotations() {
+    let result = CallToolResult::builder()
+        .content(CallToolContent::Text(
+            TextContent::new("for you")
+                .audience(&[Role::User])
+                .priority(0.8),
+        ))
+        .content(CallToolContent::Image(
+            ImageContent::new(b"png", "image/png").priority(2.0),
+        ))
+        .build();
+
+    assert_eq!(
+        serde_json::to_value(result).unwrap(),
+        json!({
+            "content": [
+                {
+                    "type": "text",
+                    "text": "for you",
+                    "annotations": { "audience": ["user"], "priority": 0.8_f32 }
+                },
+                {
+                    "type": "image",
+                    "data": "cG5n",
+                    "mimeType": "image/png",
+                    "annotations": { "priority": 1.0 }
+                }
+            ],
+            "isError": false
+        })
+    );
+}
+
+#[test]
+fn audio_content_shape() {
+    let result = CallToolResult::builder().audio(b"wav", "audio/wav").build();
+    let audio = json!({ "type": "audio", "data": "d2F2", "mimeType": "audio/wav" });
+    assert_eq!(
+        serde_json::to_value(&result).unwrap(),
+        json!({ "content": [audio], "isError": false })
+    );
+
+    let message = PromptMessage::audio(Role::User, b"wav", "audio/wav");
+    assert_eq!(serde_json::to_value(&message).unwrap()["content"], audio);
+
+    let sampling: SamplingMessageContent = serde_json::from_value(audio.clone()).unwrap();
+    assert_eq!(
+        sampling,
+        SamplingMessageContent::Audio(AudioContent::new(b"wav", "audio/wav"))
+    );
+}
+
+#[test]
+fn null_ids_only_on_responses() {
+    let error = create_error_response(&RequestId::Null, INVALID_REQUEST, "bad");
+    assert_eq!(
+        serde_json::to_value(&error).unwrap(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": INVALID_REQUEST, "message": "bad" }
+        })
+    );
+    assert_eq!(
+        serde_json::from_value::<JSONRPCMessage>(serde_json::to_value(&error).unwrap()).unwrap(),
+        error
+    );
+
+    for (id, parsed) in [
+        (json!(1), RequestId::Number(1)),
+        (json!("1"), RequestId::String("1".to_string())),
+        (json!(u64::MAX), RequestId::Unsigned(u64::MAX)),
+    ] {
+        assert_eq!(
+            serde_json::from_value::<RequestId>(id.clone()).unwrap(),
+            parsed
+        );
+        assert_eq!(serde_json::to_value(parsed).unwrap(), id);
+    }
+}
+
+#[test]
+fn api_errors_map_to_response_codes() {
+    let io = || std::io::Error::other("disk");
+    let cases = [
+        (ApiError::PoisonedLock, INTERNAL_ERROR),
+        (ApiError::IoError(io()), INTERNAL_ERROR),
+        (
+            ApiError::BindError {
+                addr: "127.0.0.1:1".to_string(),
+                source: io(),
+            },
+            INTERNAL_ERROR,
+        ),
+        (ApiError::MissingClient, INVALID_REQUEST),
+        (ApiError::HandlerError("oops".to_string()), INTERNAL_ERROR),
+        (ApiError::InvalidParams("bad".to_string()), INVALID_PARAMS),
+        (ApiError::InvalidTool("bad".to_string()), INTERNAL_ERROR),
+        (
+            ApiError::InvalidArguments {
+                path: "/a".to_string(),
+                message: "bad".to_string(),
+            },
+            INVALID_PARAMS,
+        ),
+        (
+            ApiError::ClientError {
+                code: METHOD_NOT_FOUND,
+                message: "nope".to_string(),
+            },
+            INTERNAL_ERROR,
+        ),
+        (ApiError::InvalidResponse("bad".to_string()), INTERNAL_ERROR),
+        (ApiError::HandlerPanicked, INTERNAL_ERROR),
+        (
+            ApiError::Timeout(std::time::Duration::from_secs(1)),
+            INTERNAL_ERROR,
+        ),
+        (ApiError::SessionClosed, INTERNAL_ERROR),
+        (ApiError::Cancelled, INTERNAL_ERROR),
+        (
+            ApiError::UnknownRequest(RequestId::Number(1)),
+            INVALID_PARAMS,
+        ),
+    ];
+
+    for (err, code) in cases {
+        let response =
+            serde_json::to_value(error_response_for(&RequestId::Number(7), &err)).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], code, "{err}");
+    }
+
+    // Params errors keep the handler's message, argument errors say where
+    let response = serde_json::to_value(error_response_for(
+        &RequestId::Number(7),
+        &ApiError::InvalidArguments {
+            path: "/a".to_string(),
+            message: "bad".to_string(),
+        },
+    ))
+    .unwrap();
+    assert_eq!(response["error"]["message"], "bad");
+    assert_eq!(response["error"]["data"]["path"], "/a");
+    let response = serde_json::to_value(error_response_for(
+        &RequestId::Number(7),
+        &ApiError::InvalidParams("bad".to_string()),
+    ))
+    .unwrap();
+    assert_eq!(response["error"]["message"], "bad");
+}
+
+#[test]
+fn request_base_of_paginated_requests() {
+    let request: JSONRPCRequest = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/list",
+        "params": { "cursor": "next", "_meta": { "progressToken": "list" } }
+    }))
+    .unwrap();
+    assert_eq!(
+        request.params.request_base().unwrap().progress_token(),
+        Some(&ProgressToken::String("list".to_string()))
+    );
+
+    let request: JSONRPCRequest = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "resources/subscribe",
+        "params": { "uri": "file:///a" }
+    }))
+    .unwrap();
+    assert!(request.params.request_base().is_none());
+}
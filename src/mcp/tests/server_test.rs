@@ -0,0 +1,1025 @@
+use std::sync::Arc;
+
+use crate::mcp::schema::{
+    Implementation, InitializeRequestParams, InitializeResult, JSONRPCMessage, JSONRPCNotification,
+    JSONRPCRequest, JSONRPCResponse, JSONRPCResult, LoggingLevel, NotificationParams,
+    ProtocolVersion, RequestId, Result, ResultEnum, ServerCapabilities, ServerResult,
+    INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND,
+};
+use crate::mcp::server::{
+    error::ApiError,
+    notification::handle_notification,
+    request::{handle_initialize, handle_request},
+    NotificationDropPolicy, Server, ServerBuilder, ServerHandle, ToolOverflowPolicy,
+};
+
+use super::common::*;
+use super::mock_client::MockClient;
+
+#[test]
+fn close_connection_reports_presence() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+
+    assert!(server.close_connection(&session_id).unwrap());
+    assert!(!server.close_connection(&session_id).unwrap());
+}
+
+#[test]
+fn close_missing_connection() {
+    let server = Server::new("test", "0.1", 0);
+
+    assert!(!server.close_connection(&"missing".to_string()).unwrap());
+}
+
+fn message() -> JSONRPCMessage {
+    JSONRPCMessage::Request(request(1, "tools/list", serde_json::json!({})))
+}
+
+#[tokio::test]
+async fn send_to_closed_channel_drops_session() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let client = server.new_connection(&session_id).unwrap();
+    drop(client);
+
+    server.send_message(&session_id, message()).await.unwrap();
+    assert!(!server.clients.contains_key(&session_id));
+}
+
+#[tokio::test]
+async fn send_after_reconnect_keeps_new_session() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    server.send_message(&session_id, message()).await.unwrap();
+    assert!(client.recv.recv().await.is_some());
+
+    drop(server.new_connection(&session_id).unwrap());
+    let mut client = server.new_connection(&session_id).unwrap();
+    server.send_message(&session_id, message()).await.unwrap();
+    assert!(client.recv.recv().await.is_some());
+    assert!(server.clients.contains_key(&session_id));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn close_while_sending() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+    let drain = tokio::spawn(async move { while client.recv.recv().await.is_some() {} });
+
+    let senders: Vec<_> = (0..32)
+        .map(|_| {
+            let server = server.clone();
+            let session_id = session_id.clone();
+            tokio::spawn(async move { server.send_message(&session_id, message()).await })
+        })
+        .collect();
+    server.close_connection(&session_id).unwrap();
+
+    for sender in senders {
+        match sender.await.unwrap() {
+            Ok(()) | Err(ApiError::MissingClient) => (),
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+    drain.await.unwrap();
+    assert!(server.clients.is_empty());
+}
+
+#[tokio::test]
+async fn initialize_reports_server_info() {
+    let info = Implementation {
+        name: "calculator".to_string(),
+        version: "1.2.3".to_string(),
+    };
+    let server = ServerBuilder::from_info(info.clone()).build();
+    assert_eq!(server.info(), &info);
+
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+    let initialize = initialize_request(0, serde_json::json!({}));
+    let response = handle_request(&server, &initialize, &session_id)
+        .await
+        .unwrap();
+
+    match response {
+        JSONRPCMessage::Response(JSONRPCResponse::Result(JSONRPCResult {
+            result:
+                Result {
+                    defined_fields: ResultEnum::Initialize(result),
+                    ..
+                },
+            ..
+        })) => assert_eq!(result.server_info, info),
+        other => panic!("Expected an initialize result but got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn initialize_negotiates_protocol_version() {
+    let server = Server::new("test", "0.1", 0);
+
+    for (session_id, requested, expected) in [
+        ("old", "2024-11-05", ProtocolVersion::Mcp2024_11_05),
+        ("new", "2025-03-26", ProtocolVersion::Mcp2025_03_26),
+        ("future", "2099-01-01", ProtocolVersion::Mcp2025_03_26),
+    ] {
+        let session_id = session_id.to_string();
+        let _client = server.new_connection(&session_id).unwrap();
+        let mut params = initialize_params(serde_json::json!({}));
+        params["protocolVersion"] = requested.into();
+        let initialize = request(0, "initialize", params);
+        let response = response_json(
+            handle_request(&server, &initialize, &session_id)
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(
+            response["result"]["protocolVersion"],
+            serde_json::json!(expected)
+        );
+        assert_eq!(server.protocol_version(&session_id).unwrap(), expected);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_initialize_has_one_winner() {
+    let server: ServerHandle = Arc::new(Server::new("test", "0.1", 0));
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+    let barrier = Arc::new(tokio::sync::Barrier::new(2));
+
+    let attempts: Vec<_> = (0..2)
+        .map(|id| {
+            let server = server.clone();
+            let session_id = session_id.clone();
+            let barrier = barrier.clone();
+            tokio::spawn(async move {
+                let initialize = initialize_request(id, serde_json::json!({}));
+                barrier.wait().await;
+                response_json(
+                    handle_request(&server, &initialize, &session_id)
+                        .await
+                        .unwrap(),
+                )
+            })
+        })
+        .collect();
+
+    let mut responses = Vec::new();
+    for attempt in attempts {
+        responses.push(attempt.await.unwrap());
+    }
+
+    let (won, lost): (Vec<_>, Vec<_>) = responses
+        .into_iter()
+        .partition(|response| response.get("result").is_some());
+    assert_eq!(won.len(), 1);
+    assert_eq!(lost.len(), 1);
+    assert_eq!(
+        lost[0]["error"]["message"],
+        "Connection already initializing"
+    );
+}
+
+#[test]
+fn handle_initialize_returns_server_result() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+
+    let params: InitializeRequestParams =
+        serde_json::from_value(initialize_params(serde_json::json!({}))).unwrap();
+
+    let result = handle_initialize(&server, &params, &session_id).unwrap();
+    assert_eq!(
+        result,
+        ServerResult::Initialize(Box::new(InitializeResult {
+            protocol_version: ProtocolVersion::Mcp2024_11_05,
+            capabilities: ServerCapabilities::default(),
+            server_info: server.info().clone(),
+            instructions: None,
+        }))
+    );
+
+    assert!(matches!(
+        handle_initialize(&server, &params, &"missing".to_string()),
+        Err(ApiError::MissingClient)
+    ));
+}
+
+#[tokio::test]
+async fn custom_method() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_method("x-foo/bar", |params| async move {
+            Ok(serde_json::json!({ "echo": params }))
+        })
+        .unwrap();
+
+    let call = request(1, "x-foo/bar", serde_json::json!({ "a": 1 }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"],
+        serde_json::json!({ "echo": { "a": 1 } })
+    );
+
+    let call = request(2, "x-foo/missing", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn set_capabilities_applies_to_new_sessions() {
+    let server: ServerHandle = Arc::new(Server::new("test", "0.1", 0));
+    let initialize = || initialize_request(0, serde_json::json!({}));
+
+    let before = "before".to_string();
+    let _client = server.new_connection(&before).unwrap();
+    let response = response_json(
+        handle_request(&server, &initialize(), &before)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"]["capabilities"], serde_json::json!({}));
+
+    server
+        .set_capabilities(ServerCapabilities {
+            logging: Some(Default::default()),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let after = "after".to_string();
+    let _client = server.new_connection(&after).unwrap();
+    let response = response_json(
+        handle_request(&server, &initialize(), &after)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(
+        response["result"]["capabilities"],
+        serde_json::json!({ "logging": {} })
+    );
+}
+
+#[tokio::test]
+async fn broadcast_reaches_every_session() {
+    let server: ServerHandle = Arc::new(Server::new("test", "0.1", 0));
+    let mut first = MockClient::connect(server.clone(), "first");
+    let mut second = MockClient::connect(server.clone(), "second");
+
+    let params: NotificationParams = serde_json::from_value(serde_json::json!({
+        "method": "notifications/tools/list_changed",
+        "params": {}
+    }))
+    .unwrap();
+    assert_eq!(
+        server.broadcast_notification(params.clone()).await.unwrap(),
+        2
+    );
+
+    for mock in [&mut first, &mut second] {
+        let notifications = mock.received_notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].params, params);
+    }
+}
+
+/// Fills a client queue of depth 2 with four logs and returns the data of those delivered
+async fn deliver_logs(policy: NotificationDropPolicy) -> Vec<serde_json::Value> {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .queue_depth(2)
+            .notification_drop_policy(policy)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let mut mock = MockClient::connect(server.clone(), &session_id);
+
+    for i in 0..4 {
+        server
+            .send_log(&session_id, LoggingLevel::Info, None, serde_json::json!(i))
+            .await
+            .unwrap();
+    }
+
+    mock.received_notifications()
+        .into_iter()
+        .map(|x| serde_json::to_value(x).unwrap()["params"]["data"].clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn full_queue_drops_notifications_by_policy() {
+    assert_eq!(
+        deliver_logs(NotificationDropPolicy::DropNewest).await,
+        vec![0, 1]
+    );
+    assert_eq!(
+        deliver_logs(NotificationDropPolicy::DropOldest).await,
+        vec![2, 3]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn full_queue_blocks_by_default() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").queue_depth(1).build());
+    let session_id = "session".to_string();
+    let mut client = server.new_connection(&session_id).unwrap();
+
+    server.send_message(&session_id, message()).await.unwrap();
+    let blocked = tokio::time::timeout(
+        std::time::Duration::from_millis(50),
+        server.send_message(&session_id, message()),
+    )
+    .await;
+    assert!(blocked.is_err());
+
+    let send = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move { server.send_message(&session_id, message()).await })
+    };
+    assert!(client.recv.recv().await.is_some());
+    send.await.unwrap().unwrap();
+    assert!(client.recv.recv().await.is_some());
+}
+
+#[tokio::test]
+async fn initialize_validates_client_capabilities() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+
+    let rejected = [
+        (
+            serde_json::json!({ "sampling": {}, "experimental": { "streaming": true } }),
+            "experimental capability streaming must be an object",
+        ),
+        (
+            serde_json::json!({ "sampling": {}, "listChanged": true }),
+            "listChanged is declared without roots",
+        ),
+        (
+            serde_json::json!({
+                "sampling": {},
+                "experimental": { "roots": { "listChanged": true } }
+            }),
+            "experimental roots.listChanged contradicts roots.listChanged",
+        ),
+        (
+            serde_json::json!({
+                "sampling": {},
+                "roots": { "listChanged": true },
+                "experimental": { "roots": { "listChanged": false } }
+            }),
+            "experimental roots.listChanged contradicts roots.listChanged",
+        ),
+    ];
+    for (id, (capabilities, reason)) in rejected.into_iter().enumerate() {
+        let invalid = initialize_request(id as i64, capabilities);
+        let response = response_json(
+            handle_request(&server, &invalid, &session_id)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+        assert_eq!(
+            response["error"]["message"],
+            format!("Invalid client capabilities: {reason}")
+        );
+    }
+    // Rejected capabilities are not kept
+    assert!(server
+        .sessions_with_capability(|x| x.sampling.is_some())
+        .unwrap()
+        .is_empty());
+
+    // The session is left uninitialized so the client can retry. Experimental keys may share
+    // a name with standard capabilities as long as they agree
+    let valid = initialize_request(
+        4,
+        serde_json::json!({
+            "roots": { "listChanged": true },
+            "sampling": {},
+            "experimental": {
+                "sampling": { "streaming": true },
+                "roots": { "listChanged": true }
+            }
+        }),
+    );
+    let response = response_json(handle_request(&server, &valid, &session_id).await.unwrap());
+    assert!(response["result"]["serverInfo"].is_object());
+    assert_eq!(
+        server
+            .sessions_with_capability(|x| x.sampling.is_some())
+            .unwrap(),
+        vec![session_id]
+    );
+}
+
+#[tokio::test]
+async fn sessions_filtered_by_capability() {
+    let server = Server::new("test", "0.1", 0);
+
+    let mut clients = Vec::new();
+    for (session_id, capabilities) in [
+        ("sampling", serde_json::json!({ "sampling": {} })),
+        ("plain", serde_json::json!({})),
+    ] {
+        clients.push(initialized_session_with(&server, session_id, capabilities).await);
+    }
+
+    assert_eq!(
+        server
+            .sessions_with_capability(|x| x.sampling.is_some())
+            .unwrap(),
+        vec!["sampling".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn unhandled_notifications_reach_fallback() {
+    let notifications = |methods: &[&str]| -> Vec<JSONRPCNotification> {
+        methods
+            .iter()
+            .map(|method| {
+                serde_json::from_value(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": { "requestId": 1 }
+                }))
+                .unwrap()
+            })
+            .collect()
+    };
+    let sent = notifications(&["notifications/roots/list_changed", "notifications/custom"]);
+
+    // Without a fallback they are ignored
+    let server = Server::new("test", "0.1", 0);
+    let _client = initialized_session(&server, "session").await;
+    for notification in &sent {
+        handle_notification(&server, notification, &"session".to_string()).unwrap();
+    }
+
+    let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let server = {
+        let received = received.clone();
+        ServerBuilder::new("test", "0.1")
+            .on_notification(move |session_id, params| {
+                received
+                    .lock()
+                    .unwrap()
+                    .push((session_id.to_string(), params.clone()));
+            })
+            .build()
+    };
+    let _client = initialized_session(&server, "session").await;
+    for notification in &sent {
+        handle_notification(&server, notification, &"session".to_string()).unwrap();
+    }
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    assert!(received
+        .iter()
+        .all(|(session_id, _)| session_id == "session"));
+    assert_eq!(
+        received
+            .iter()
+            .map(|(_, params)| params.clone())
+            .collect::<Vec<_>>(),
+        sent.into_iter().map(|x| x.params).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn replace_registry_swaps_tools_mid_session() {
+    let server: ServerHandle = Arc::new(Server::new("test", "0.1", 0));
+    server
+        .register_tool(tool("old"), |_| async { Ok(text_result("old")) })
+        .unwrap();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    let mut mock = MockClient::connect(server.clone(), "watcher");
+
+    let staging = Server::new("test", "0.1", 0);
+    staging
+        .register_tool(tool("new"), |_| async { Ok(text_result("new")) })
+        .unwrap();
+    let old = server
+        .replace_registry(staging.into_registry().unwrap())
+        .await
+        .unwrap();
+
+    // Only the tool list changed
+    let notifications = mock.received_notifications();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(
+        serde_json::to_value(&notifications[0]).unwrap()["method"],
+        "notifications/tools/list_changed"
+    );
+
+    let list = request(1, "tools/list", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["result"]["tools"][0]["name"], "new");
+    assert_eq!(response["result"]["tools"].as_array().unwrap().len(), 1);
+
+    // Restoring the snapshot brings the old tool back
+    server.replace_registry(old).await.unwrap();
+    let list = request(2, "tools/list", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["result"]["tools"][0]["name"], "old");
+}
+
+fn cancelled(id: i64) -> JSONRPCNotification {
+    serde_json::from_value(serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/cancelled",
+        "params": { "requestId": id, "reason": "user aborted" }
+    }))
+    .unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelled_requests_stop_waiting_for_a_permit() {
+    let server: ServerHandle = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .tool_concurrency_limit(1, ToolOverflowPolicy::Wait)
+            .build(),
+    );
+    server
+        .register_tool(tool("hold"), |_| std::future::pending())
+        .unwrap();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let first = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", serde_json::json!({ "name": "hold" }));
+            handle_request(&server, &call, &session_id).await.unwrap()
+        })
+    };
+    let queued = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let call = request(2, "tools/call", serde_json::json!({ "name": "hold" }));
+            handle_request(&server, &call, &session_id).await.unwrap()
+        })
+    };
+    let in_flight = |server: &Server| server.in_flight_requests(&session_id).unwrap();
+    while !in_flight(&server).contains(&RequestId::Number(2)) {
+        tokio::task::yield_now().await;
+    }
+
+    handle_notification(&server, &cancelled(2), &session_id).unwrap();
+    let response = response_json(queued.await.unwrap());
+    assert_eq!(response["error"]["message"], "Request cancelled");
+
+    first.abort();
+}
+
+#[tokio::test]
+async fn request_ids_in_flight_cannot_be_reused() {
+    let server: ServerHandle = Arc::new(Server::new("test", "0.1", 0));
+    server
+        .register_tool_with_context(tool("wait"), None, |context, _| async move {
+            context.cancellation().cancelled().await;
+            Ok(text_result("cancelled"))
+        })
+        .unwrap();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let first = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", serde_json::json!({ "name": "wait" }));
+            response_json(handle_request(&server, &call, &session_id).await.unwrap())
+        })
+    };
+    let in_flight = |server: &Server| server.in_flight_requests(&session_id).unwrap();
+    while !in_flight(&server).contains(&RequestId::Number(1)) {
+        tokio::task::yield_now().await;
+    }
+
+    let reused = request(1, "tools/list", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &reused, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INVALID_REQUEST);
+    assert!(response["id"].is_null());
+
+    // The first request can still be cancelled
+    handle_notification(&server, &cancelled(1), &session_id).unwrap();
+    assert_eq!(
+        first.await.unwrap()["result"]["content"][0]["text"],
+        "cancelled"
+    );
+}
+
+#[tokio::test]
+async fn cancelled_request_response_is_dropped() {
+    let server: ServerHandle = Arc::new(Server::new("test", "0.1", 0));
+    let release = Arc::new(tokio::sync::Notify::new());
+    {
+        let release = release.clone();
+        server
+            .register_tool(tool("slow"), move |_| {
+                let release = release.clone();
+                async move {
+                    release.notified().await;
+                    Ok(text_result("done"))
+                }
+            })
+            .unwrap();
+    }
+    let session_id = "session".to_string();
+    let mut client = initialized_session(&server, &session_id).await;
+
+    let call = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", serde_json::json!({ "name": "slow" }));
+            let response = handle_request(&server, &call, &session_id).await.unwrap();
+            server.send_response(&session_id, response).await.unwrap();
+        })
+    };
+    let in_flight = |server: &Server| server.in_flight_requests(&session_id).unwrap();
+    while !in_flight(&server).contains(&RequestId::Number(1)) {
+        tokio::task::yield_now().await;
+    }
+
+    handle_notification(&server, &cancelled(1), &session_id).unwrap();
+    assert!(!in_flight(&server).contains(&RequestId::Number(1)));
+
+    release.notify_one();
+    call.await.unwrap();
+    assert!(client.recv.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn cancel_of_unknown_request_is_ignored() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let mut client = initialized_session(&server, &session_id).await;
+
+    handle_notification(&server, &cancelled(42), &session_id).unwrap();
+    assert!(client.recv.try_recv().is_err());
+
+    // The session keeps working
+    let list = request(1, "tools/list", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["id"], 1);
+}
+
+#[tokio::test]
+async fn initialize_can_be_optional() {
+    let list = request(1, "tools/list", serde_json::json!({}));
+
+    let server = Server::new("test", "0.1", 0);
+    let _client = server.new_connection("strict").unwrap();
+    let response = response_json(
+        handle_request(&server, &list, &"strict".to_string())
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["error"]["message"], "Connection not initialized");
+
+    let server = ServerBuilder::new("test", "0.1")
+        .require_initialize(false)
+        .build();
+    let session_id = "trusted".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["result"]["tools"], serde_json::json!([]));
+
+    // A client that does initialize still can
+    let initialize = initialize_request(2, serde_json::json!({}));
+    let response = response_json(
+        handle_request(&server, &initialize, &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["result"]["serverInfo"]["name"], "test");
+}
+
+#[tokio::test]
+async fn initialized_without_initialize_is_ignored() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+
+    let initialized: JSONRPCNotification = serde_json::from_value(serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    }))
+    .unwrap();
+    handle_notification(&server, &initialized, &session_id).unwrap();
+
+    let list = request(1, "tools/list", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["error"]["message"], "Connection not initialized");
+}
+
+#[test]
+fn generated_instructions_list_tools() {
+    let initialize_instructions = |server: &Server| {
+        let session_id = "session".to_string();
+        let _client = server.new_connection(&session_id).unwrap();
+        let params: InitializeRequestParams =
+            serde_json::from_value(initialize_params(serde_json::json!({}))).unwrap();
+
+        match handle_initialize(server, &params, &session_id).unwrap() {
+            ServerResult::Initialize(result) => result.instructions,
+            other => panic!("Expected an initialize result but got {other:?}"),
+        }
+    };
+    let mut echo = tool("echo");
+    echo.description = Some("Echoes its input".to_string());
+
+    let server = ServerBuilder::new("test", "0.1")
+        .generated_instructions(true)
+        .build();
+    assert_eq!(initialize_instructions(&server), None);
+    server
+        .register_tool(echo.clone(), |_| async { Ok(text_result("echo")) })
+        .unwrap();
+    server
+        .register_tool(tool("noop"), |_| async { Ok(text_result("")) })
+        .unwrap();
+    assert_eq!(
+        initialize_instructions(&server).unwrap(),
+        "Available tools:\n- echo: Echoes its input\n- noop"
+    );
+
+    // Explicit instructions are kept as they are
+    let server = ServerBuilder::new("test", "0.1")
+        .instructions("Call echo")
+        .generated_instructions(true)
+        .build();
+    server
+        .register_tool(echo, |_| async { Ok(text_result("echo")) })
+        .unwrap();
+    assert_eq!(initialize_instructions(&server).unwrap(), "Call echo");
+}
+
+#[tokio::test(start_paused = true)]
+async fn uninitialized_sessions_are_reaped() {
+    let disconnects = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let server = {
+        let disconnects = disconnects.clone();
+        ServerBuilder::new("test", "0.1")
+            .init_timeout(std::time::Duration::from_secs(5))
+            .on_disconnect(move |_| {
+                disconnects.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+            .build()
+    };
+    let server = Arc::new(server);
+    server.spawn_listener().unwrap();
+
+    let _idle = server.new_connection("idle").unwrap();
+    let _ready = initialized_session(&server, "ready").await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(6)).await;
+    assert!(!server.clients.contains_key("idle"));
+    assert!(server.clients.contains_key("ready"));
+    assert_eq!(disconnects.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[test]
+fn registered_names_are_sorted() {
+    let server = Server::new("test", "0.1", 0);
+    for name in ["search", "echo", "build"] {
+        server
+            .register_tool(tool(name), |_| async { Ok(text_result("ok")) })
+            .unwrap();
+    }
+    for name in ["summarize", "review"] {
+        let prompt = serde_json::from_value(serde_json::json!({ "name": name })).unwrap();
+        server.register_prompt(prompt, |_| Ok(vec![])).unwrap();
+    }
+    for uri in ["file:///b.txt", "file:///a.txt"] {
+        let resource =
+            serde_json::from_value(serde_json::json!({ "uri": uri, "name": uri })).unwrap();
+        server
+            .register_resource(resource, |uri| async move {
+                Ok(crate::mcp::schema::ReadResourceResult::text(&uri, None, ""))
+            })
+            .unwrap();
+    }
+
+    assert_eq!(server.tool_names().unwrap(), ["build", "echo", "search"]);
+    assert_eq!(server.prompt_names().unwrap(), ["review", "summarize"]);
+    assert_eq!(
+        server.resource_uris().unwrap(),
+        ["file:///a.txt", "file:///b.txt"]
+    );
+}
+
+#[tokio::test]
+async fn handlers_read_client_experimental_capabilities() {
+    let capabilities = serde_json::from_value(serde_json::json!({
+        "experimental": { "streaming": { "chunks": true }, "batching": {} }
+    }))
+    .unwrap();
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .capabilities(capabilities)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let _client = initialized_session_with(
+        &server,
+        &session_id,
+        serde_json::json!({ "experimental": { "streaming": { "chunks": true }, "tracing": {} } }),
+    )
+    .await;
+
+    let weak = Arc::downgrade(&server);
+    server
+        .register_tool_with_context(tool("features"), None, move |context, _| {
+            let server = weak.upgrade().unwrap();
+            async move {
+                let client = server.client_experimental(context.session_id())?;
+                let shared = server.shared_experimental(context.session_id())?;
+                Ok(text_result(&format!(
+                    "{} {:?}",
+                    client["streaming"]["chunks"], shared
+                )))
+            }
+        })
+        .unwrap();
+
+    let call = request(1, "tools/call", serde_json::json!({ "name": "features" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["content"][0]["text"],
+        "true [\"streaming\"]"
+    );
+}
+
+#[tokio::test]
+async fn disabled_methods_are_not_found() {
+    let server = ServerBuilder::new("test", "0.1")
+        .enabled_methods(&["tools/list"])
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    server
+        .register_tool(tool("echo"), |_| async { Ok(text_result("echo")) })
+        .unwrap();
+
+    let list = request(1, "tools/list", serde_json::json!({}));
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["result"]["tools"][0]["name"], "echo");
+
+    let call = request(2, "tools/call", serde_json::json!({ "name": "echo" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn null_request_id_is_invalid() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let list: JSONRPCRequest = serde_json::from_value(serde_json::json!({
+        "jsonrpc": "2.0", "id": null, "method": "tools/list"
+    }))
+    .unwrap();
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    assert_eq!(response["id"], serde_json::Value::Null);
+    assert_eq!(response["error"]["code"], INVALID_REQUEST);
+}
+
+#[tokio::test]
+async fn server_only_requests_are_rejected() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    for (id, method, params) in [
+        (1, "roots/list", serde_json::json!({})),
+        (
+            2,
+            "sampling/createMessage",
+            serde_json::json!({ "messages": [], "maxTokens": 10 }),
+        ),
+    ] {
+        let response = response_json(
+            handle_request(&server, &request(id, method, params), &session_id)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(response["error"]["code"], INVALID_REQUEST, "{method}");
+        assert_eq!(
+            response["error"]["message"],
+            "This request is not valid from a client"
+        );
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn pings_keep_sessions_alive() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .idle_timeout(std::time::Duration::from_secs(1))
+            .build(),
+    );
+    server.spawn_listener().unwrap();
+    let _client = initialized_session(&server, "session").await;
+
+    for id in 0..5 {
+        tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+        let ping = JSONRPCMessage::Request(request(id, "ping", serde_json::json!({})));
+        server.dispatch_message("session", ping).await.unwrap();
+    }
+    assert!(server.clients.contains_key("session"));
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    assert!(!server.clients.contains_key("session"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn sessions_with_running_requests_are_not_idle() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .idle_timeout(std::time::Duration::from_secs(1))
+            .build(),
+    );
+    server.spawn_listener().unwrap();
+    server
+        .register_tool(tool("slow"), |_| async {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            Ok(text_result("done"))
+        })
+        .unwrap();
+    let _client = initialized_session(&server, "session").await;
+
+    let call = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", serde_json::json!({ "name": "slow" }));
+            server
+                .dispatch_message("session", JSONRPCMessage::Request(call))
+                .await
+        })
+    };
+
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    assert!(server.clients.contains_key("session"));
+
+    call.await.unwrap().unwrap().unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    assert!(!server.clients.contains_key("session"));
+}
+
+#[tokio::test]
+async fn abandoned_requests_stop_being_tracked() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    server
+        .register_tool(tool("stuck"), |_| std::future::pending())
+        .unwrap();
+    let _client = initialized_session(&server, "session").await;
+
+    let call = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", serde_json::json!({ "name": "stuck" }));
+            server
+                .dispatch_message("session", JSONRPCMessage::Request(call))
+                .await
+        })
+    };
+    while server
+        .in_flight_requests(&"session".to_string())
+        .unwrap()
+        .is_empty()
+    {
+        tokio::task::yield_now().await;
+    }
+
+    call.abort();
+    assert!(call.await.unwrap_err().is_cancelled());
+    assert!(server
+        .in_flight_requests(&"session".to_string())
+        .unwrap()
+        .is_empty());
+}
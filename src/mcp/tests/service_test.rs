@@ -0,0 +1,287 @@
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower::layer::layer_fn;
+use tower::util::MapRequestLayer;
+use tower::{service_fn, Layer, ServiceExt};
+
+use super::common::*;
+use crate::mcp::schema::{JSONRPCMessage, JSONRPCNotification, JSONRPCRequest};
+use crate::mcp::server::{error::ApiError, McpService, Server, ServerBuilder};
+
+fn method(message: &JSONRPCMessage) -> String {
+    serde_json::to_value(message).unwrap()["method"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Layer recording the method of every message passing through
+fn logging_layer(
+    seen: Arc<Mutex<Vec<String>>>,
+) -> MapRequestLayer<impl Fn(JSONRPCMessage) -> JSONRPCMessage + Clone> {
+    MapRequestLayer::new(move |message: JSONRPCMessage| {
+        seen.lock().unwrap().push(method(&message));
+        message
+    })
+}
+
+fn initialize() -> JSONRPCMessage {
+    JSONRPCMessage::Request(initialize_request(0, json!({})))
+}
+
+fn initialized() -> JSONRPCMessage {
+    let notification: JSONRPCNotification = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    }))
+    .unwrap();
+
+    JSONRPCMessage::Notification(notification)
+}
+
+fn list_tools() -> JSONRPCRequest {
+    request(1, "tools/list", json!({}))
+}
+
+#[tokio::test]
+async fn layered_service_dispatches() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let _client = server.new_connection("session").unwrap();
+    let seen = Arc::new(Mutex::new(vec![]));
+    let service = logging_layer(seen.clone()).layer(McpService::new(server.clone(), "session"));
+
+    let response = service.clone().oneshot(initialize()).await.unwrap();
+    assert!(response.is_some());
+
+    // Notifications have no response
+    let response = service.clone().oneshot(initialized()).await.unwrap();
+    assert!(response.is_none());
+
+    let response = service
+        .oneshot(JSONRPCMessage::Request(list_tools()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(response_json(response)["result"], json!({ "tools": [] }));
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        ["initialize", "notifications/initialized", "tools/list"]
+    );
+}
+
+#[tokio::test]
+async fn service_answers_pings() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let _client = server.new_connection("session").unwrap();
+
+    let response = McpService::new(server, "session")
+        .oneshot(JSONRPCMessage::Request(request(3, "ping", json!({}))))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        response_json(response),
+        json!({ "jsonrpc": "2.0", "id": 3, "result": {} })
+    );
+}
+
+#[tokio::test]
+async fn builder_layer_wraps_session_service() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .layer(logging_layer(seen.clone()))
+            .build(),
+    );
+    let _client = server.new_connection("session").unwrap();
+
+    let response = server
+        .service("session")
+        .unwrap()
+        .oneshot(initialize())
+        .await
+        .unwrap();
+    assert!(response.is_some());
+    assert_eq!(*seen.lock().unwrap(), ["initialize"]);
+}
+
+#[tokio::test]
+async fn dispatch_message_routes_through_layer() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .layer(logging_layer(seen.clone()))
+            .build(),
+    );
+    let _client = server.new_connection("session").unwrap();
+
+    let response = server
+        .dispatch_message("session", initialize())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        response_json(response)["result"]["serverInfo"]["name"],
+        "test"
+    );
+    assert!(server
+        .in_flight_requests(&"session".to_string())
+        .unwrap()
+        .is_empty());
+
+    let response = server
+        .dispatch_message("session", initialized())
+        .await
+        .unwrap();
+    assert!(response.is_none());
+    assert_eq!(
+        *seen.lock().unwrap(),
+        ["initialize", "notifications/initialized"]
+    );
+}
+
+#[tokio::test]
+async fn layers_are_built_once_per_session() {
+    let built = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let built = built.clone();
+        Arc::new(
+            ServerBuilder::new("test", "0.1")
+                .layer(layer_fn(move |service: McpService| {
+                    built.fetch_add(1, Ordering::SeqCst);
+                    service
+                }))
+                .build(),
+        )
+    };
+    let _client = server.new_connection("session").unwrap();
+
+    server
+        .dispatch_message("session", initialize())
+        .await
+        .unwrap();
+    server
+        .dispatch_message("session", initialized())
+        .await
+        .unwrap();
+    server
+        .dispatch_message("session", JSONRPCMessage::Request(list_tools()))
+        .await
+        .unwrap();
+    assert_eq!(built.load(Ordering::SeqCst), 1);
+
+    // A new session gets its own
+    let _other = server.new_connection("other").unwrap();
+    server
+        .dispatch_message("other", initialize())
+        .await
+        .unwrap();
+    assert_eq!(built.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn request_ids_can_be_reused_after_dispatch() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let _client = initialized_session(&server, "session").await;
+
+    for _ in 0..2 {
+        let response = server
+            .dispatch_message("session", JSONRPCMessage::Request(list_tools()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response_json(response)["id"], 1);
+        assert!(server
+            .in_flight_requests(&"session".to_string())
+            .unwrap()
+            .is_empty());
+    }
+}
+
+#[test]
+fn request_id_is_on_the_dispatch_span() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    server
+        .register_tool(tool("echo"), |_| async {
+            tracing::info!("echoing");
+            Ok(text_result("echo"))
+        })
+        .unwrap();
+
+    let (response, logs) = capture_logs(|| {
+        runtime.block_on(async {
+            let _client = initialized_session(&server, "session").await;
+            let call = request(5, "tools/call", json!({ "name": "echo" }));
+            server
+                .dispatch_message("session", JSONRPCMessage::Request(call))
+                .await
+                .unwrap()
+                .unwrap()
+        })
+    });
+    assert_eq!(response_json(response)["id"], 5);
+    assert!(server
+        .in_flight_requests(&"session".to_string())
+        .unwrap()
+        .is_empty());
+
+    let span = "inbound_request{session_id=\"session\" method=\"tools/call\" id=5}";
+    let handled = logs.lines().find(|x| x.contains("echoing")).unwrap();
+    assert!(handled.contains(span), "{handled}");
+    let sent = logs
+        .lines()
+        .rfind(|x| x.contains("sending response"))
+        .unwrap();
+    assert!(
+        sent.contains("inbound_request{session_id=\"session\" id=5}"),
+        "{sent}"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn pings_are_answered_while_draining() {
+    // Stands in for a server refusing work while it shuts down
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .layer(layer_fn(|_| {
+                service_fn(|_: JSONRPCMessage| async { Err(ApiError::SessionClosed) })
+            }))
+            .build(),
+    );
+    let _client = server.new_connection("session").unwrap();
+
+    let response = tokio::time::timeout(
+        Duration::from_millis(100),
+        server.dispatch_message(
+            "session",
+            JSONRPCMessage::Request(request(7, "ping", json!({}))),
+        ),
+    )
+    .await
+    .unwrap()
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        response_json(response),
+        json!({ "jsonrpc": "2.0", "id": 7, "result": {} })
+    );
+    assert!(server
+        .in_flight_requests(&"session".to_string())
+        .unwrap()
+        .is_empty());
+
+    // Anything else still goes through the layer
+    assert!(matches!(
+        server
+            .dispatch_message("session", JSONRPCMessage::Request(list_tools()))
+            .await,
+        Err(ApiError::SessionClosed)
+    ));
+}
@@ -0,0 +1,853 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+use tower::ServiceExt;
+
+use super::common::{initialize_request, text_result};
+use crate::mcp::schema::{
+    CallToolResult, LoggingLevel, RequestId, ResultEnum, ServerResult, Tool, INTERNAL_ERROR,
+    INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR,
+};
+use crate::mcp::server::{
+    error::ApiError, sse, utils::create_result_response, HistoryDirection, Server, ServerBuilder,
+};
+
+fn echo_tool() -> Tool {
+    serde_json::from_value(json!({
+        "name": "echo",
+        "description": "Echoes its input",
+        "inputSchema": { "type": "object", "required": [] }
+    }))
+    .unwrap()
+}
+
+async fn get_json(app: axum::Router, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn health_route() {
+    let server = Server::new("test", "0.1", 0);
+    server.new_connection("session").unwrap();
+    let app = sse::router(Arc::new(server));
+
+    let response = app
+        .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["name"], "test");
+    assert_eq!(body["version"], "0.1");
+    assert_eq!(body["connections"], 1);
+    assert!(body["uptimeSecs"].is_u64());
+}
+
+#[tokio::test]
+async fn capabilities_route_lists_registered_tools() {
+    let server = ServerBuilder::new("test", "0.1")
+        .capabilities_route(true)
+        .build();
+    server
+        .register_tool(echo_tool(), |_| async {
+            Ok(CallToolResult {
+                content: vec![],
+                is_error: None,
+                structured_content: None,
+            })
+        })
+        .unwrap();
+
+    let (status, body) = get_json(sse::router(Arc::new(server)), "/capabilities").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["tools"], json!(["echo"]));
+    assert_eq!(body["prompts"], json!([]));
+    assert_eq!(body["capabilities"]["tools"], json!({}));
+    assert!(body["methods"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("tools/call")));
+}
+
+#[tokio::test]
+async fn capabilities_route_disabled_by_default() {
+    let server = ServerBuilder::new("test", "0.1").build();
+
+    let (status, _) = get_json(sse::router(Arc::new(server)), "/capabilities").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn dropping_sse_stream_closes_session() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let app = sse::router(server.clone());
+
+    let response = app
+        .oneshot(Request::get("/sse").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    let event = String::from_utf8(frame.to_vec()).unwrap();
+    assert!(event.starts_with("event: endpoint"));
+
+    let session_id = event.split("sessionId=").nth(1).unwrap().trim().to_string();
+    assert!(server.clients.contains_key(&session_id));
+
+    drop(body);
+    assert!(!server.clients.contains_key(&session_id));
+}
+
+#[tokio::test]
+async fn terminate_session_ends_sse_stream() {
+    let disconnects = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let disconnects = disconnects.clone();
+        ServerBuilder::new("test", "0.1")
+            .on_disconnect(move |_| {
+                disconnects.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+    };
+    let server = Arc::new(server);
+    let (mut body, session_id) = open_sse(server.clone()).await;
+
+    assert!(server.terminate_session(&session_id).unwrap());
+    assert!(!server.clients.contains_key(&session_id));
+
+    let end = tokio::time::timeout(Duration::from_secs(5), body.frame())
+        .await
+        .expect("stream should end after termination");
+    assert!(end.is_none());
+
+    drop(body);
+    assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+    assert!(!server.terminate_session(&session_id).unwrap());
+}
+
+/// Opens an SSE stream and returns it with the session id from the endpoint event
+async fn open_sse(server: Arc<Server>) -> (Body, String) {
+    let response = sse::router(server)
+        .oneshot(Request::get("/sse").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    let event = String::from_utf8(frame.to_vec()).unwrap();
+    let session_id = event.split("sessionId=").nth(1).unwrap().trim().to_string();
+
+    (body, session_id)
+}
+
+async fn next_message_event(server: Arc<Server>) -> String {
+    let (mut body, session_id) = open_sse(server.clone()).await;
+    server
+        .send_log(&session_id, LoggingLevel::Info, None, json!("hi"))
+        .await
+        .unwrap();
+
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    String::from_utf8(frame.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn sse_messages_compact_by_default() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+
+    let event = next_message_event(server).await;
+    assert!(event.starts_with("event: message\ndata: {\"method\":\"notifications/message\""));
+    assert_eq!(event.matches("data:").count(), 1);
+}
+
+#[tokio::test]
+async fn sse_messages_pretty() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").pretty_json(true).build());
+
+    let event = next_message_event(server).await;
+    // Each line of the pretty printed JSON becomes its own data line
+    assert!(
+        event.starts_with("event: message\ndata: {\ndata:   \"method\": \"notifications/message\"")
+    );
+}
+
+async fn post_message(server: Arc<Server>, session_id: &str, message: Value) -> StatusCode {
+    sse::router(server)
+        .oneshot(
+            Request::post(format!("/messages?sessionId={session_id}"))
+                .header("content-type", "application/json")
+                .body(Body::from(message.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn session_history_records_exchange() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").session_history(3).build());
+    let (_body, session_id) = open_sse(server.clone()).await;
+
+    let initialize = json!(initialize_request(0, json!({})));
+    let status = post_message(server.clone(), &session_id, initialize.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let history = server.session_history(&session_id).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].direction, HistoryDirection::Inbound);
+    assert_eq!(json!(history[0].message), initialize);
+    assert_eq!(history[1].direction, HistoryDirection::Outbound);
+    assert_eq!(json!(history[1].message)["id"], 0);
+    assert!(history[0].timestamp <= history[1].timestamp);
+
+    // Only the last three messages are kept
+    for i in 0..2 {
+        server
+            .send_log(&session_id, LoggingLevel::Info, None, json!(i))
+            .await
+            .unwrap();
+    }
+    let history = server.session_history(&session_id).unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[0].direction, HistoryDirection::Outbound);
+    assert_eq!(json!(history[2].message)["params"]["data"], 1);
+}
+
+#[tokio::test]
+async fn session_history_records_pings() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").session_history(4).build());
+    let (_body, session_id) = open_sse(server.clone()).await;
+
+    let ping = json!({ "jsonrpc": "2.0", "id": 1, "method": "ping", "params": {} });
+    let status = post_message(server.clone(), &session_id, ping.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let history = server.session_history(&session_id).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].direction, HistoryDirection::Inbound);
+    assert_eq!(json!(history[0].message), ping);
+    assert_eq!(history[1].direction, HistoryDirection::Outbound);
+    assert_eq!(json!(history[1].message)["id"], 1);
+}
+
+#[tokio::test]
+async fn session_history_off_by_default() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let (_body, session_id) = open_sse(server.clone()).await;
+
+    server
+        .send_log(&session_id, LoggingLevel::Info, None, json!("hi"))
+        .await
+        .unwrap();
+    assert!(server.session_history(&session_id).unwrap().is_empty());
+}
+
+async fn next_event_data(body: &mut Body) -> Value {
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    let event = String::from_utf8(frame.to_vec()).unwrap();
+    let data = event.strip_prefix("event: message\ndata: ").unwrap();
+
+    serde_json::from_str(data.trim()).unwrap()
+}
+
+#[tokio::test]
+async fn oversized_messages_are_replaced() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .max_message_size(256)
+            .build(),
+    );
+    let (mut body, session_id) = open_sse(server.clone()).await;
+    let big = "x".repeat(1024);
+
+    // A response keeps its id so the client request still completes
+    let response = create_result_response(
+        &RequestId::Number(7),
+        ResultEnum::CallTool(text_result(&big)),
+    );
+    server.send_message(&session_id, response).await.unwrap();
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["id"], 7);
+    assert_eq!(data["error"]["code"], INTERNAL_ERROR);
+    assert!(data["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("exceeds the 256 byte limit"));
+
+    server
+        .send_log(&session_id, LoggingLevel::Info, None, json!(big))
+        .await
+        .unwrap();
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["method"], "notifications/message");
+    assert_eq!(data["params"]["level"], "error");
+
+    // Messages under the limit are untouched
+    server
+        .send_log(&session_id, LoggingLevel::Info, None, json!("small"))
+        .await
+        .unwrap();
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["params"]["data"], "small");
+}
+
+async fn initialize_over_sse(server: Arc<Server>, session_id: &str, body: &mut Body) {
+    let initialize = json!(initialize_request(0, json!({})));
+    post_message(server.clone(), session_id, initialize).await;
+    next_event_data(body).await;
+
+    let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    post_message(server, session_id, initialized).await;
+}
+
+#[tokio::test]
+async fn malformed_requests_keep_their_id() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let (mut body, session_id) = open_sse(server.clone()).await;
+    initialize_over_sse(server.clone(), &session_id, &mut body).await;
+
+    let unknown = json!({ "jsonrpc": "2.0", "id": "abc", "method": "x-unknown/method" });
+    let status = post_message(server.clone(), &session_id, unknown).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["id"], "abc");
+    assert_eq!(data["error"]["code"], METHOD_NOT_FOUND);
+
+    let bad_params = json!({
+        "jsonrpc": "2.0",
+        "id": 5,
+        "method": "tools/call",
+        "params": { "arguments": {} }
+    });
+    post_message(server.clone(), &session_id, bad_params).await;
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["id"], 5);
+    assert_eq!(data["error"]["code"], INVALID_PARAMS);
+
+    // A malformed response is never answered
+    let invalid = json!({ "jsonrpc": "2.0", "id": 6, "result": 1 });
+    let status = post_message(server.clone(), &session_id, invalid).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Not a valid request but it still has an id to answer
+    let invalid = json!({ "jsonrpc": "2.0", "id": 7, "method": 1 });
+    post_message(server.clone(), &session_id, invalid).await;
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["id"], 7);
+    assert_eq!(data["error"]["code"], INVALID_REQUEST);
+
+    let status = post_message(server, &session_id, json!({ "jsonrpc": "2.0" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn respond_out_of_band() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let release = Arc::new(Notify::new());
+    {
+        let release = release.clone();
+        server
+            .register_tool(echo_tool(), move |_| {
+                let release = release.clone();
+                async move {
+                    release.notified().await;
+                    Ok(text_result("late"))
+                }
+            })
+            .unwrap();
+    }
+
+    let (mut body, session_id) = open_sse(server.clone()).await;
+    initialize_over_sse(server.clone(), &session_id, &mut body).await;
+
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 9,
+        "method": "tools/call",
+        "params": { "name": "echo" }
+    });
+    let pending = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move { post_message(server, &session_id, call).await })
+    };
+
+    // Fails until the call is in flight
+    let id = RequestId::Number(9);
+    while server
+        .respond(
+            &session_id,
+            &id,
+            ServerResult::CallTool(text_result("early")),
+        )
+        .await
+        .is_err()
+    {
+        tokio::task::yield_now().await;
+    }
+
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["id"], 9);
+    assert_eq!(data["result"]["content"][0]["text"], "early");
+
+    // The handler's own response is dropped once it was answered out of band
+    release.notify_one();
+    assert_eq!(pending.await.unwrap(), StatusCode::OK);
+    server
+        .send_log(&session_id, LoggingLevel::Info, None, json!("after"))
+        .await
+        .unwrap();
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["params"]["data"], "after");
+
+    assert!(matches!(
+        server
+            .respond(
+                &session_id,
+                &id,
+                ServerResult::CallTool(text_result("again"))
+            )
+            .await,
+        Err(ApiError::UnknownRequest(_))
+    ));
+}
+
+#[tokio::test]
+async fn routes_mounted_under_prefix() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .path_prefix("/mcp/")
+            .build(),
+    );
+
+    let (status, _) = get_json(sse::router(server.clone()), "/sse").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let response = sse::router(server.clone())
+        .oneshot(Request::get("/mcp/sse").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    let event = String::from_utf8(frame.to_vec()).unwrap();
+    let endpoint = event.lines().nth(1).unwrap().trim_start_matches("data: ");
+    assert!(endpoint.starts_with("/mcp/messages?sessionId="));
+
+    let initialize = json!(initialize_request(0, json!({})));
+    let status = sse::router(server)
+        .oneshot(
+            Request::post(endpoint)
+                .header("content-type", "application/json")
+                .body(Body::from(initialize.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::OK);
+    assert!(next_event_data(&mut body).await["result"].is_object());
+}
+
+#[tokio::test]
+async fn custom_session_ids() {
+    let next = Arc::new(AtomicUsize::new(0));
+    let server = ServerBuilder::new("test", "0.1")
+        .session_id_generator(move || format!("node-7-{}", next.fetch_add(1, Ordering::SeqCst)))
+        .build();
+    let server = Arc::new(server);
+
+    let (_first, first_id) = open_sse(server.clone()).await;
+    let (_second, second_id) = open_sse(server.clone()).await;
+    assert_eq!(first_id, "node-7-0");
+    assert_eq!(second_id, "node-7-1");
+    assert!(server.clients.contains_key(&second_id));
+}
+
+#[tokio::test]
+async fn colliding_session_ids_are_generated_again() {
+    let ids = Arc::new(std::sync::Mutex::new(vec!["b", "a", "a"]));
+    let server = ServerBuilder::new("test", "0.1")
+        .session_id_generator(move || ids.lock().unwrap().pop().unwrap_or("a").to_string())
+        .build();
+    let server = Arc::new(server);
+
+    let (_first, first_id) = open_sse(server.clone()).await;
+    let (_second, second_id) = open_sse(server.clone()).await;
+    assert_eq!(first_id, "a");
+    assert_eq!(second_id, "b");
+    assert_eq!(server.clients.len(), 2);
+
+    // A generator that only returns ids in use never replaces the live session
+    assert!(matches!(
+        server.new_session(),
+        Err(ApiError::SessionIdInUse(_))
+    ));
+    assert_eq!(server.clients.len(), 2);
+}
+
+#[tokio::test]
+async fn shutdown_timeout_closes_open_streams() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .shutdown_timeout(Duration::from_millis(100))
+            .build(),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown, signal) = tokio::sync::oneshot::channel::<()>();
+    let serve = tokio::spawn(sse::serve_listener(server.clone(), listener, async move {
+        _ = signal.await;
+    }));
+
+    // A client that keeps its SSE stream open and never disconnects
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /sse HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut received = Vec::new();
+    while !String::from_utf8_lossy(&received).contains("event: endpoint") {
+        let mut buf = [0; 1024];
+        let read = stream.read(&mut buf).await.unwrap();
+        received.extend_from_slice(&buf[..read]);
+    }
+    assert_eq!(server.clients.len(), 1);
+
+    shutdown.send(()).unwrap();
+    let served = tokio::time::timeout(Duration::from_secs(5), serve)
+        .await
+        .expect("server should exit after the shutdown timeout");
+    served.unwrap().unwrap();
+    assert!(server.clients.is_empty());
+}
+
+async fn next_frame(body: &mut Body) -> String {
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    String::from_utf8(frame.to_vec()).unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn keep_alives_only_on_idle_streams() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .keep_alive_interval(Duration::from_millis(200))
+            .build(),
+    );
+    let (mut body, session_id) = open_sse(server.clone()).await;
+
+    // Messages every 50ms keep the stream busy well past the keep-alive interval
+    for i in 0..10 {
+        server
+            .send_log(&session_id, LoggingLevel::Info, None, json!(i))
+            .await
+            .unwrap();
+        assert!(next_frame(&mut body).await.starts_with("event: message"));
+        tokio::time::advance(Duration::from_millis(50)).await;
+    }
+
+    let start = tokio::time::Instant::now();
+    assert_eq!(next_frame(&mut body).await, ": keep-alive-text\n\n");
+    assert!(start.elapsed() >= Duration::from_millis(150));
+}
+
+#[tokio::test]
+async fn router_nests_into_parent_app() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    let app = axum::Router::new()
+        .route("/", axum::routing::get(|| async { "home" }))
+        .nest("/mcp", server.clone().into_router().unwrap());
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/mcp/sse").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    let event = String::from_utf8(frame.to_vec()).unwrap();
+    let endpoint = event.lines().nth(1).unwrap().trim_start_matches("data: ");
+    assert!(endpoint.starts_with("/mcp/messages?sessionId="));
+    assert_eq!(server.clients.len(), 1);
+
+    let (status, _) = get_json(app, "/").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn undecodable_body_is_parse_error() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let (_body, session_id) = open_sse(server.clone()).await;
+
+    let post = |body: &'static [u8], content_type: &str| {
+        Request::post(format!("/messages?sessionId={session_id}"))
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    for (body, message) in [
+        (
+            &b"{\"jsonrpc\": \"2.0\", \"method\": \"\xff\xfe\"}"[..],
+            "not valid UTF-8",
+        ),
+        (&b"{\"jsonrpc\": \"2.0\","[..], "not valid JSON"),
+        (&b""[..], "body is empty"),
+        (&b" \r\n\t "[..], "body is empty"),
+    ] {
+        let response = sse::router(server.clone())
+            .oneshot(post(body, "application/json; charset=utf-8"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], Value::Null);
+        assert_eq!(body["error"]["code"], PARSE_ERROR);
+        assert!(body["error"]["message"].as_str().unwrap().contains(message));
+    }
+
+    let response = sse::router(server)
+        .oneshot(post(b"{}", "text/plain"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn request_context_carries_post_headers() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    server
+        .register_tool_with_context(echo_tool(), None, |context, _| async move {
+            let header = |name| {
+                context
+                    .headers()
+                    .get(name)
+                    .map(|value| value.to_str().unwrap().to_string())
+            };
+            let summary = json!({
+                "session": context.session_id(),
+                "id": context.request_id(),
+                "userAgent": header("user-agent"),
+                "requestId": header("x-request-id"),
+                "other": header("x-other"),
+                "elapsed": context.elapsed() < Duration::from_secs(5),
+            });
+            Ok(text_result(&summary.to_string()))
+        })
+        .unwrap();
+
+    let (mut body, session_id) = open_sse(server.clone()).await;
+    initialize_over_sse(server.clone(), &session_id, &mut body).await;
+
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "tools/call",
+        "params": { "name": "echo" }
+    });
+    let status = sse::router(server)
+        .oneshot(
+            Request::post(format!("/messages?sessionId={session_id}"))
+                .header("content-type", "application/json")
+                .header("user-agent", "inspector/1.0")
+                .header("x-request-id", "req-4")
+                .header("x-other", "dropped")
+                .body(Body::from(call.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::OK);
+
+    let data = next_event_data(&mut body).await;
+    let text = data["result"]["content"][0]["text"].as_str().unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(text).unwrap(),
+        json!({
+            "session": session_id,
+            "id": 4,
+            "userAgent": "inspector/1.0",
+            "requestId": "req-4",
+            "other": null,
+            "elapsed": true,
+        })
+    );
+}
+
+#[tokio::test]
+async fn resumed_session_keeps_subscriptions() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .session_resumption(Duration::from_secs(5))
+            .resource_subscriptions(true)
+            .build(),
+    );
+    let (mut body, session_id) = open_sse(server.clone()).await;
+    initialize_over_sse(server.clone(), &session_id, &mut body).await;
+
+    let subscribe = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "resources/subscribe",
+        "params": { "uri": "file:///a.txt" }
+    });
+    post_message(server.clone(), &session_id, subscribe).await;
+    assert_eq!(next_event_data(&mut body).await["id"], 1);
+
+    // The update is queued while the stream is down
+    drop(body);
+    assert_eq!(
+        server
+            .notify_resource_updated("file:///a.txt")
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        server
+            .notify_resource_updated("file:///b.txt")
+            .await
+            .unwrap(),
+        0
+    );
+
+    let response = sse::router(server.clone())
+        .oneshot(
+            Request::get(format!("/sse?sessionId={session_id}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    assert!(String::from_utf8(frame.to_vec())
+        .unwrap()
+        .contains(&format!("sessionId={session_id}")));
+
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["method"], "notifications/resources/updated");
+    assert_eq!(data["params"]["uri"], "file:///a.txt");
+    assert_eq!(
+        server.subscriptions(&session_id).unwrap(),
+        vec!["file:///a.txt".to_string()]
+    );
+
+    // Only detached sessions can be resumed
+    let status = sse::router(server)
+        .oneshot(
+            Request::get(format!("/sse?sessionId={session_id}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test(start_paused = true)]
+async fn detached_session_expires() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .session_resumption(Duration::from_secs(5))
+            .build(),
+    );
+    let (body, session_id) = open_sse(server.clone()).await;
+
+    drop(body);
+    assert!(server.subscriptions(&session_id).is_ok());
+
+    tokio::time::sleep(Duration::from_secs(6)).await;
+    assert!(matches!(
+        server.subscriptions(&session_id),
+        Err(ApiError::MissingClient)
+    ));
+}
+
+#[tokio::test]
+async fn bind_failure_names_the_address() {
+    let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = taken.local_addr().unwrap().to_string();
+
+    let err = sse::bind(&addr).await.unwrap_err();
+    assert!(matches!(&err, ApiError::BindError { addr: failed, .. } if *failed == addr));
+    assert!(err.to_string().contains(&addr));
+}
+
+/// Replays the messages mcp-inspector sends when it connects and opens the tools tab
+#[tokio::test]
+async fn mcp_inspector_handshake() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    server
+        .register_tool(echo_tool(), |_| async { Ok(text_result("echo")) })
+        .unwrap();
+    let (mut body, session_id) = open_sse(server.clone()).await;
+
+    let initialize = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2024-11-05",
+            "capabilities": { "sampling": {}, "roots": { "listChanged": true } },
+            "clientInfo": { "name": "mcp-inspector", "version": "0.0.1" }
+        }
+    });
+    let status = post_message(server.clone(), &session_id, initialize).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["jsonrpc"], "2.0");
+    assert_eq!(data["id"], 0);
+    assert_eq!(data["result"]["protocolVersion"], "2024-11-05");
+    assert_eq!(
+        data["result"]["serverInfo"],
+        json!({ "name": "test", "version": "0.1" })
+    );
+    assert!(data["result"]["capabilities"]["tools"].is_object());
+
+    let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    let status = post_message(server.clone(), &session_id, initialized).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let list = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list", "params": {} });
+    let status = post_message(server.clone(), &session_id, list).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = next_event_data(&mut body).await;
+    assert_eq!(data["id"], 1);
+    assert_eq!(
+        data["result"]["tools"],
+        json!([{
+            "name": "echo",
+            "description": "Echoes its input",
+            "inputSchema": { "type": "object", "required": [] }
+        }])
+    );
+}
@@ -0,0 +1,393 @@
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::task::JoinHandle;
+
+use super::common::{initialize_request, text_result, tool};
+use crate::mcp::schema::{
+    JSONRPCMessage, JSONRPCNotification, NotificationParams, Tool, INVALID_REQUEST, PARSE_ERROR,
+};
+use crate::mcp::server::{
+    error::Result,
+    stdio::{serve_io, FrameReader},
+    Server, ServerBuilder, PARTIAL_RESULT_LOGGER,
+};
+
+#[tokio::test]
+async fn frames_span_lines_and_reads() {
+    let (mut input, reader) = tokio::io::duplex(64);
+    let mut frames = FrameReader::new(reader);
+
+    // A pretty-printed value arriving in pieces, followed by two on a single line
+    let pretty = serde_json::to_string_pretty(&json!({ "id": 1, "text": "a\nb" })).unwrap();
+    let tail = pretty[10..].to_string();
+    input.write_all(&pretty.as_bytes()[..10]).await.unwrap();
+    let write = tokio::spawn(async move {
+        tokio::task::yield_now().await;
+        input.write_all(tail.as_bytes()).await.unwrap();
+        input
+            .write_all(b"\n{\"id\": 2}  {\"id\": 3}\n")
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(
+        frames.next().await.unwrap().unwrap(),
+        json!({ "id": 1, "text": "a\nb" })
+    );
+    assert_eq!(frames.next().await.unwrap().unwrap(), json!({ "id": 2 }));
+    assert_eq!(frames.next().await.unwrap().unwrap(), json!({ "id": 3 }));
+
+    write.await.unwrap();
+    assert!(frames.next().await.is_none());
+}
+
+#[tokio::test]
+async fn frames_recover_after_invalid_line() {
+    let input: &[u8] = b"{\"id\": 1}\n{not json}\n{\n  \"id\": 2\n}\n";
+    let mut frames = FrameReader::new(input);
+
+    assert_eq!(frames.next().await.unwrap().unwrap(), json!({ "id": 1 }));
+    assert!(frames.next().await.unwrap().is_err());
+    assert_eq!(frames.next().await.unwrap().unwrap(), json!({ "id": 2 }));
+    assert!(frames.next().await.is_none());
+}
+
+#[tokio::test]
+async fn oversized_frames_are_skipped() {
+    let input = format!(
+        "{{\"id\": 1}}\n{{\"text\": \"{}\"}}\n{{\"id\": 2}}\n",
+        "a".repeat(1024)
+    );
+
+    // Whether the oversized value arrives whole or in pieces
+    for read_size in [input.len(), 16] {
+        let (mut writer, reader) = tokio::io::duplex(read_size);
+        let mut frames = FrameReader::with_max_frame_size(reader, 64);
+        let input = input.clone();
+        let write = tokio::spawn(async move { writer.write_all(input.as_bytes()).await });
+
+        assert_eq!(frames.next().await.unwrap().unwrap(), json!({ "id": 1 }));
+        let err = frames.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().starts_with("Message larger than 64 bytes"));
+        assert_eq!(frames.next().await.unwrap().unwrap(), json!({ "id": 2 }));
+        write.await.unwrap().unwrap();
+        assert!(frames.next().await.is_none());
+    }
+}
+
+#[tokio::test]
+async fn frames_split_inside_strings_and_escapes() {
+    let (mut input, reader) = tokio::io::duplex(64);
+    let mut frames = FrameReader::new(reader);
+
+    // Braces and escaped quotes inside strings don't end the value
+    let value = json!({ "text": "} \"{[", "list": [1, { "a": "]" }] });
+    let data = format!("{value}\n");
+    let write = tokio::spawn(async move {
+        for byte in data.as_bytes() {
+            input.write_all(&[*byte]).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+    });
+
+    assert_eq!(frames.next().await.unwrap().unwrap(), value);
+    write.await.unwrap();
+    assert!(frames.next().await.is_none());
+}
+
+type Lines = tokio::io::Lines<BufReader<DuplexStream>>;
+
+/// Serves a session over in-memory pipes, `output_size` being how much the server can write
+/// before the client reads
+fn serve_pipes(
+    server: Arc<Server>,
+    output_size: usize,
+) -> (DuplexStream, Lines, JoinHandle<Result<()>>) {
+    let (input, reader) = tokio::io::duplex(64 * 1024);
+    let (writer, output) = tokio::io::duplex(output_size);
+    let serve = tokio::spawn(serve_io(server, reader, writer));
+
+    (input, BufReader::new(output).lines(), serve)
+}
+
+async fn next_reply(lines: &mut Lines) -> Value {
+    let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+        .await
+        .expect("server should reply")
+        .unwrap()
+        .unwrap();
+
+    serde_json::from_str(&line).unwrap()
+}
+
+/// Completes the handshake, sending initialize pretty-printed over several lines
+async fn initialize(input: &mut DuplexStream, lines: &mut Lines) {
+    let initialize = initialize_request(0, json!({ "roots": {} }));
+    input
+        .write_all(
+            serde_json::to_string_pretty(&initialize)
+                .unwrap()
+                .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    let reply = next_reply(lines).await;
+    assert_eq!(reply["id"], 0);
+    assert_eq!(reply["result"]["serverInfo"]["name"], "test");
+
+    let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    input
+        .write_all(format!("{initialized}\n").as_bytes())
+        .await
+        .unwrap();
+}
+
+fn list_tools(id: i64) -> String {
+    format!(
+        "{}\n",
+        json!({ "jsonrpc": "2.0", "id": id, "method": "tools/list", "params": {} })
+    )
+}
+
+#[tokio::test]
+async fn serves_multi_line_messages() {
+    let server = Arc::new(Server::new("test", "0.1", 0));
+    let (mut input, mut lines, serve) = serve_pipes(server.clone(), 1024);
+
+    initialize(&mut input, &mut lines).await;
+    input.write_all(list_tools(1).as_bytes()).await.unwrap();
+    let reply = next_reply(&mut lines).await;
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["result"]["tools"], json!([]));
+
+    drop(input);
+    serve.await.unwrap().unwrap();
+    assert!(server.clients.is_empty());
+    assert!(lines.next_line().await.unwrap().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_reader_applies_backpressure() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").queue_depth(2).build());
+    let (mut input, mut lines, serve) = serve_pipes(server.clone(), 64);
+    initialize(&mut input, &mut lines).await;
+
+    // Far more replies than fit in the pipe and the queue while nothing reads them
+    for id in 1..=20 {
+        input.write_all(list_tools(id).as_bytes()).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!serve.is_finished());
+
+    let mut ids = Vec::new();
+    for _ in 1..=20 {
+        ids.push(next_reply(&mut lines).await["id"].as_i64().unwrap());
+    }
+    ids.sort();
+    assert_eq!(ids, (1..=20).collect::<Vec<_>>());
+
+    drop(input);
+    serve.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn handlers_can_wait_on_the_client() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .session_id_generator(|| "stdio".to_string())
+            .build(),
+    );
+    let roots_tool: Tool = serde_json::from_value(json!({
+        "name": "roots",
+        "inputSchema": { "type": "object", "required": [] }
+    }))
+    .unwrap();
+    let weak = Arc::downgrade(&server);
+    server
+        .register_tool(roots_tool, move |_| {
+            let server = weak.upgrade().unwrap();
+            async move {
+                let roots = server
+                    .list_roots(
+                        &"stdio".to_string(),
+                        serde_json::from_value(json!({})).unwrap(),
+                        None,
+                    )
+                    .await?;
+                Ok(text_result(&roots.roots.len().to_string()))
+            }
+        })
+        .unwrap();
+    let (mut input, mut lines, serve) = serve_pipes(server.clone(), 1024);
+    initialize(&mut input, &mut lines).await;
+
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": "roots" }
+    });
+    input
+        .write_all(format!("{call}\n").as_bytes())
+        .await
+        .unwrap();
+
+    // The tool is still running while the client answers its request
+    let request = next_reply(&mut lines).await;
+    assert_eq!(request["method"], "roots/list");
+    let response = json!({
+        "jsonrpc": "2.0",
+        "id": request["id"],
+        "result": { "roots": [{ "uri": "file:///repo" }] }
+    });
+    input
+        .write_all(format!("{response}\n").as_bytes())
+        .await
+        .unwrap();
+
+    let reply = next_reply(&mut lines).await;
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["result"]["content"][0]["text"], "1");
+
+    drop(input);
+    serve.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn outbound_messages_are_json_rpc_2() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    let (mut input, mut lines, _serve) = serve_pipes(server.clone(), 64 * 1024);
+    initialize(&mut input, &mut lines).await;
+
+    input.write_all(list_tools(1).as_bytes()).await.unwrap();
+    input
+        .write_all(b"{\"jsonrpc\": \"2.0\", \"id\": 2, \"method\": \"nope\"}\n")
+        .await
+        .unwrap();
+    let session_id = server.clients.iter().next().unwrap().key().clone();
+    server
+        .send_message(
+            &session_id,
+            JSONRPCMessage::Notification(JSONRPCNotification {
+                json_rpc: "1.0".into(),
+                params: NotificationParams::ToolListChanged(Default::default()),
+            }),
+        )
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(next_reply(&mut lines).await["jsonrpc"], "2.0");
+    }
+}
+
+#[tokio::test]
+async fn partial_results_come_before_the_response() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    server
+        .register_tool_with_context(tool("count"), None, |context, _| async move {
+            for i in 1..=3 {
+                context.send_partial(json!(i)).await?;
+            }
+            Ok(text_result("done"))
+        })
+        .unwrap();
+    let (mut input, mut lines, _serve) = serve_pipes(server, 64 * 1024);
+    initialize(&mut input, &mut lines).await;
+
+    let call =
+        json!({ "jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": { "name": "count" } });
+    input
+        .write_all(format!("{call}\n").as_bytes())
+        .await
+        .unwrap();
+
+    for i in 1..=3 {
+        let partial = next_reply(&mut lines).await;
+        assert_eq!(partial["params"]["logger"], PARTIAL_RESULT_LOGGER);
+        assert_eq!(
+            partial["params"]["data"],
+            json!({ "requestId": 5, "partial": i })
+        );
+    }
+    let response = next_reply(&mut lines).await;
+    assert_eq!(response["id"], 5);
+    assert_eq!(response["result"]["content"][0]["text"], "done");
+}
+
+#[tokio::test]
+async fn invalid_json_gets_null_id_parse_error() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    let (mut input, mut lines, _serve) = serve_pipes(server, 64 * 1024);
+    initialize(&mut input, &mut lines).await;
+
+    input.write_all(b"{not json}\n").await.unwrap();
+    input.write_all(list_tools(1).as_bytes()).await.unwrap();
+
+    let error = next_reply(&mut lines).await;
+    assert_eq!(error["jsonrpc"], "2.0");
+    assert_eq!(error["id"], Value::Null);
+    assert_eq!(error["error"]["code"], PARSE_ERROR);
+
+    // The session carries on after the bad line
+    assert_eq!(next_reply(&mut lines).await["id"], 1);
+}
+
+#[tokio::test]
+async fn null_request_id_gets_an_error_reply() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    let (mut input, mut lines, _serve) = serve_pipes(server, 64 * 1024);
+    initialize(&mut input, &mut lines).await;
+
+    let request = json!({ "jsonrpc": "2.0", "id": null, "method": "tools/list" });
+    input
+        .write_all(format!("{request}\n").as_bytes())
+        .await
+        .unwrap();
+
+    let error = next_reply(&mut lines).await;
+    assert_eq!(error["id"], Value::Null);
+    assert_eq!(error["error"]["code"], INVALID_REQUEST);
+}
+
+#[tokio::test]
+async fn server_only_request_gets_an_error_reply() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    let (mut input, mut lines, _serve) = serve_pipes(server, 64 * 1024);
+    initialize(&mut input, &mut lines).await;
+
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "roots/list" });
+    input
+        .write_all(format!("{request}\n").as_bytes())
+        .await
+        .unwrap();
+
+    let error = next_reply(&mut lines).await;
+    assert_eq!(error["id"], 1);
+    assert_eq!(error["error"]["code"], INVALID_REQUEST);
+    assert_eq!(
+        error["error"]["message"],
+        "This request is not valid from a client"
+    );
+}
+
+#[tokio::test]
+async fn pings_are_answered_over_stdio() {
+    let server = Arc::new(ServerBuilder::new("test", "0.1").build());
+    let (mut input, mut lines, _serve) = serve_pipes(server.clone(), 64 * 1024);
+
+    // Before initialize too
+    for id in [1, 2] {
+        let ping = json!({ "jsonrpc": "2.0", "id": id, "method": "ping" });
+        input
+            .write_all(format!("{ping}\n").as_bytes())
+            .await
+            .unwrap();
+
+        let pong = next_reply(&mut lines).await;
+        assert_eq!(pong, json!({ "jsonrpc": "2.0", "id": id, "result": {} }));
+    }
+}
@@ -0,0 +1,822 @@
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::common::*;
+use crate::mcp::schema::{
+    CallToolResult, ProtocolVersion, RequestBaseParams, Tool, INTERNAL_ERROR, INVALID_PARAMS,
+    METHOD_NOT_FOUND, SERVER_BUSY,
+};
+use crate::mcp::server::{
+    error::ApiError,
+    request::handle_request,
+    utils::{effective_timeout, tool_error_result},
+    Server, ServerBuilder, ToolOverflowPolicy,
+};
+
+#[tokio::test(start_paused = true)]
+async fn tool_timeouts_are_per_tool() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_millis(20))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    // Both tools take the same time, only build is allowed to run past the server default
+    server
+        .register_tool(tool("echo"), |_| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(text_result("echo"))
+        })
+        .unwrap();
+    server
+        .register_tool_with_timeout(tool("build"), Some(Duration::from_secs(5)), |_| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(text_result("built"))
+        })
+        .unwrap();
+
+    let echo = request(1, "tools/call", json!({ "name": "echo" }));
+    let response = response_json(handle_request(&server, &echo, &session_id).await.unwrap());
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+
+    let build = request(2, "tools/call", json!({ "name": "build" }));
+    let response = response_json(handle_request(&server, &build, &session_id).await.unwrap());
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["result"]["content"][0]["text"], "built");
+}
+
+#[tokio::test(start_paused = true)]
+async fn tool_timeout_shorter_than_default() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_secs(5))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool_with_timeout(tool("slow"), Some(Duration::from_millis(20)), |_| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(text_result("done"))
+        })
+        .unwrap();
+
+    let call = request(1, "tools/call", json!({ "name": "slow" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_deadline_bounds_tool_timeout() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_secs(5))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool(tool("slow"), |_| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(text_result("done"))
+        })
+        .unwrap();
+
+    let call = request(
+        1,
+        "tools/call",
+        json!({ "name": "slow", "_meta": { "timeoutMs": 20 } }),
+    );
+    let started = tokio::time::Instant::now();
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+    assert_eq!(started.elapsed(), Duration::from_millis(20));
+}
+
+#[test]
+fn timeout_hint_is_bounded_by_server_limit() {
+    let hint: RequestBaseParams = serde_json::from_value(json!({
+        "_meta": { "timeoutMs": 60_000 }
+    }))
+    .unwrap();
+    assert_eq!(hint.timeout_hint(), Some(Duration::from_secs(60)));
+    assert_eq!(
+        effective_timeout(Some(Duration::from_secs(5)), hint.timeout_hint()),
+        Some(Duration::from_secs(5))
+    );
+    assert_eq!(
+        effective_timeout(None, hint.timeout_hint()),
+        Some(Duration::from_secs(60))
+    );
+
+    let passed: RequestBaseParams = serde_json::from_value(json!({
+        "_meta": { "deadline": 1_000 }
+    }))
+    .unwrap();
+    assert_eq!(passed.timeout_hint(), Some(Duration::ZERO));
+    assert_eq!(RequestBaseParams::default().timeout_hint(), None);
+}
+
+#[test]
+fn tool_error_result_shape() {
+    let result = tool_error_result("disk full");
+
+    assert_eq!(
+        serde_json::to_value(result).unwrap(),
+        json!({
+            "content": [{ "type": "text", "text": "disk full" }],
+            "isError": true
+        })
+    );
+}
+
+#[tokio::test]
+async fn failing_tool_returns_error_result() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool(tool("fail"), |_| async {
+            Err(ApiError::HandlerError("disk full".to_string()))
+        })
+        .unwrap();
+
+    let call = request(1, "tools/call", json!({ "name": "fail" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert!(response.get("error").is_none());
+    assert_eq!(response["result"]["isError"], true);
+    assert_eq!(
+        response["result"]["content"][0]["text"],
+        "Handler Error: disk full"
+    );
+}
+
+#[tokio::test]
+async fn unknown_tool_is_protocol_error() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let call = request(1, "tools/call", json!({ "name": "missing" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert!(response.get("result").is_none());
+    assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn idempotent_tool_call_runs_once() {
+    let server = ServerBuilder::new("test", "0.1")
+        .idempotency_cache(16, Duration::from_secs(60))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    {
+        let runs = runs.clone();
+        server
+            .register_tool(tool("charge"), move |_| {
+                let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+                async move { Ok(text_result(&format!("charged {run}"))) }
+            })
+            .unwrap();
+    }
+
+    let call = |id, key: &str| {
+        request(
+            id,
+            "tools/call",
+            json!({ "name": "charge", "_meta": { "idempotencyKey": key } }),
+        )
+    };
+
+    let first = response_json(
+        handle_request(&server, &call(1, "abc"), &session_id)
+            .await
+            .unwrap(),
+    );
+    let retry = response_json(
+        handle_request(&server, &call(2, "abc"), &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(retry["id"], 2);
+    assert_eq!(first["result"], retry["result"]);
+
+    handle_request(&server, &call(3, "other"), &session_id)
+        .await
+        .unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn idempotency_key_ignored_when_disabled() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    {
+        let runs = runs.clone();
+        server
+            .register_tool(tool("charge"), move |_| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async { Ok(text_result("charged")) }
+            })
+            .unwrap();
+    }
+
+    for id in 0..2 {
+        let call = request(
+            id,
+            "tools/call",
+            json!({ "name": "charge", "_meta": { "idempotencyKey": "abc" } }),
+        );
+        handle_request(&server, &call, &session_id).await.unwrap();
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn list_tools_order_is_stable() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    for name in ["deploy", "build", "test", "archive", "lint"] {
+        server
+            .register_tool(tool(name), |_| async { Ok(text_result("ok")) })
+            .unwrap();
+    }
+
+    let list = |id| request(id, "tools/list", json!({}));
+    let names = |response: serde_json::Value| -> Vec<String> {
+        response["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let first = names(response_json(
+        handle_request(&server, &list(1), &session_id)
+            .await
+            .unwrap(),
+    ));
+    let second = names(response_json(
+        handle_request(&server, &list(2), &session_id)
+            .await
+            .unwrap(),
+    ));
+    assert_eq!(first, ["archive", "build", "deploy", "lint", "test"]);
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn nested_arguments_over_limit_are_rejected() {
+    let server = ServerBuilder::new("test", "0.1")
+        .params_complexity_limit(16, 1000)
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    server
+        .register_tool(tool("echo"), |_| async { Ok(text_result("echo")) })
+        .unwrap();
+
+    let nested = (0..32).fold(json!("leaf"), |inner, _| json!({ "inner": inner }));
+    let call = request(
+        1,
+        "tools/call",
+        json!({ "name": "echo", "arguments": { "value": nested } }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    assert_eq!(
+        response["error"]["message"],
+        "Params exceed nesting depth of 16"
+    );
+
+    let wide = json!({ "name": "echo", "arguments": { "values": vec![0; 2000] } });
+    let response = response_json(
+        handle_request(&server, &request(2, "tools/call", wide), &session_id)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+    let flat = request(
+        3,
+        "tools/call",
+        json!({ "name": "echo", "arguments": { "value": 1 } }),
+    );
+    let response = response_json(handle_request(&server, &flat, &session_id).await.unwrap());
+    assert_eq!(response["result"]["content"][0]["text"], "echo");
+}
+
+#[derive(serde::Deserialize)]
+struct ResizeArgs {
+    width: u32,
+    options: ResizeOptions,
+}
+
+#[derive(serde::Deserialize)]
+struct ResizeOptions {
+    keep_aspect: bool,
+}
+
+#[tokio::test]
+async fn typed_tool_reports_bad_field_path() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    server
+        .register_typed_tool(tool("resize"), |args: ResizeArgs| async move {
+            Ok(text_result(&format!(
+                "{} {}",
+                args.width, args.options.keep_aspect
+            )))
+        })
+        .unwrap();
+
+    let call = request(
+        1,
+        "tools/call",
+        json!({
+            "name": "resize",
+            "arguments": { "width": 640, "options": { "keep_aspect": "yes" } }
+        }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    assert_eq!(response["error"]["data"]["path"], "options.keep_aspect");
+
+    let call = request(
+        2,
+        "tools/call",
+        json!({
+            "name": "resize",
+            "arguments": { "width": 640, "options": { "keep_aspect": true } }
+        }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["result"]["content"][0]["text"], "640 true");
+}
+
+#[test]
+fn duplicate_tool_names_are_rejected() {
+    let server = Server::new("test", "0.1", 0);
+    server
+        .register_tool(tool("echo"), |_| async { Ok(text_result("first")) })
+        .unwrap();
+
+    let duplicate = server.register_tool(tool("echo"), |_| async { Ok(text_result("second")) });
+    assert!(matches!(duplicate, Err(ApiError::InvalidTool(_))));
+
+    let empty = server.register_tool(tool(" "), |_| async { Ok(text_result("empty")) });
+    assert!(matches!(empty, Err(ApiError::InvalidTool(_))));
+}
+
+#[test]
+fn required_property_must_be_defined() {
+    let server = Server::new("test", "0.1", 0);
+    let resize = |properties: serde_json::Value| -> Tool {
+        serde_json::from_value(json!({
+            "name": "resize",
+            "inputSchema": { "type": "object", "properties": properties, "required": ["width"] }
+        }))
+        .unwrap()
+    };
+
+    let missing = server.register_tool(resize(json!({ "height": {} })), |_| async {
+        Ok(text_result("resized"))
+    });
+    assert!(matches!(missing, Err(ApiError::InvalidTool(_))));
+
+    server
+        .register_tool(
+            resize(json!({ "width": { "type": "integer" } })),
+            |_| async { Ok(text_result("resized")) },
+        )
+        .unwrap();
+}
+
+crate::tool! {
+    /// Repeats a message
+    ///
+    /// Separators are placed between copies
+    async fn repeat(
+        message: String,
+        times: u32,
+        separator: Option<String>,
+    ) -> crate::Result<crate::schema::CallToolResult> {
+        let copies = vec![message; times as usize];
+        Ok(text_result(&copies.join(separator.as_deref().unwrap_or(""))))
+    }
+}
+
+#[tokio::test]
+async fn tool_macro_declares_and_registers() {
+    assert_eq!(
+        serde_json::to_value(repeat::tool()).unwrap(),
+        json!({
+            "name": "repeat",
+            "description": "Repeats a message\n\nSeparators are placed between copies",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "message": { "type": "string" },
+                    "times": { "type": "integer" },
+                    "separator": { "type": "string" }
+                },
+                "required": ["message", "times"]
+            }
+        })
+    );
+
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    repeat::register(&server).unwrap();
+
+    let call = request(
+        1,
+        "tools/call",
+        json!({ "name": "repeat", "arguments": { "message": "ab", "times": 2, "separator": "-" } }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["result"]["content"][0]["text"], "ab-ab");
+
+    let call = request(
+        2,
+        "tools/call",
+        json!({ "name": "repeat", "arguments": { "message": "ab" } }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    assert_eq!(response["error"]["data"]["path"], "times");
+}
+
+#[tokio::test]
+async fn tool_handler_gets_request_context() {
+    let server = ServerBuilder::new("test", "0.1").build();
+    let session_id = "session".to_string();
+    let mut client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool_with_context(tool("whoami"), None, |context, _| async move {
+            context.progress().report(1, Some(1)).await?;
+            Ok(text_result(&format!(
+                "{} {} {:?} {}",
+                context.session_id(),
+                context.request_id(),
+                context.protocol_version(),
+                context.is_cancelled(),
+            )))
+        })
+        .unwrap();
+
+    let call = request(
+        7,
+        "tools/call",
+        json!({ "name": "whoami", "_meta": { "progressToken": "call-7" } }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["content"][0]["text"],
+        format!("session 7 {:?} false", ProtocolVersion::Mcp2024_11_05)
+    );
+
+    let progress = serde_json::to_value(client.recv.try_recv().unwrap().sse_message).unwrap();
+    assert_eq!(progress["method"], "notifications/progress");
+    assert_eq!(progress["params"]["progressToken"], "call-7");
+    assert_eq!(progress["params"]["progress"], 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn tool_calls_over_the_limit_wait() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .tool_concurrency_limit(1, ToolOverflowPolicy::Wait)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let release = Arc::new(tokio::sync::Notify::new());
+    {
+        let started = started.clone();
+        let release = release.clone();
+        server
+            .register_tool(tool("hold"), move |_| {
+                let started = started.clone();
+                let release = release.clone();
+                async move {
+                    started.fetch_add(1, Ordering::SeqCst);
+                    release.notified().await;
+                    Ok(text_result("done"))
+                }
+            })
+            .unwrap();
+    }
+
+    let calls: Vec<_> = (1..=2)
+        .map(|id| {
+            let server = server.clone();
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                let call = request(id, "tools/call", json!({ "name": "hold" }));
+                response_json(handle_request(&server, &call, &session_id).await.unwrap())
+            })
+        })
+        .collect();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+
+    // The second call only starts once the first returns
+    release.notify_one();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+    release.notify_one();
+
+    for call in calls {
+        assert_eq!(call.await.unwrap()["result"]["content"][0]["text"], "done");
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn tool_calls_over_the_limit_rejected() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .tool_concurrency_limit(2, ToolOverflowPolicy::Reject)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let release = Arc::new(tokio::sync::Notify::new());
+    {
+        let release = release.clone();
+        server
+            .register_tool(tool("hold"), move |_| {
+                let release = release.clone();
+                async move {
+                    release.notified().await;
+                    Ok(text_result("done"))
+                }
+            })
+            .unwrap();
+    }
+    // Lower than the server limit
+    server.set_tool_concurrency_limit("hold", 1).unwrap();
+
+    let first = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", json!({ "name": "hold" }));
+            handle_request(&server, &call, &session_id).await.unwrap()
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let call = request(2, "tools/call", json!({ "name": "hold" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], SERVER_BUSY);
+
+    release.notify_one();
+    first.await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn queued_tool_calls_time_out() {
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .request_timeout(Duration::from_secs(1))
+            .tool_concurrency_limit(1, ToolOverflowPolicy::Wait)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    server
+        .register_tool(tool("hold"), |_| std::future::pending())
+        .unwrap();
+
+    let first = {
+        let server = server.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let call = request(1, "tools/call", json!({ "name": "hold" }));
+            handle_request(&server, &call, &session_id).await.unwrap()
+        })
+    };
+    tokio::task::yield_now().await;
+
+    // Never gets a permit, the wait still counts against the timeout
+    let call = request(2, "tools/call", json!({ "name": "hold" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("Timed out"));
+
+    first.abort();
+}
+
+#[test]
+fn zero_tool_concurrency_limit_is_rejected() {
+    let server = Server::new("test", "0.1", 0);
+    server
+        .register_tool(tool("hold"), |_| async { Ok(text_result("done")) })
+        .unwrap();
+    assert!(matches!(
+        server.set_tool_concurrency_limit("hold", 0),
+        Err(ApiError::InvalidParams(_))
+    ));
+}
+
+#[tokio::test]
+async fn zero_server_tool_concurrency_limit_runs_one_call() {
+    let server = ServerBuilder::new("test", "0.1")
+        .tool_concurrency_limit(0, ToolOverflowPolicy::Reject)
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+    server
+        .register_tool(tool("echo"), |_| async { Ok(text_result("echo")) })
+        .unwrap();
+
+    let call = request(1, "tools/call", json!({ "name": "echo" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["result"]["content"][0]["text"], "echo");
+}
+
+#[tokio::test]
+async fn panicking_tool_is_an_internal_error() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool(tool("boom"), |_| async { panic!("secret detail") })
+        .unwrap();
+    server
+        .register_tool(tool("echo"), |_| async { Ok(text_result("echo")) })
+        .unwrap();
+
+    let boom = request(1, "tools/call", json!({ "name": "boom" }));
+    let response = response_json(handle_request(&server, &boom, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+    assert!(!response.to_string().contains("secret detail"));
+
+    // The session is still usable
+    assert!(server.clients.contains_key(&session_id));
+    let echo = request(2, "tools/call", json!({ "name": "echo" }));
+    let response = response_json(handle_request(&server, &echo, &session_id).await.unwrap());
+    assert_eq!(response["result"]["content"][0]["text"], "echo");
+}
+
+#[tokio::test]
+async fn output_schema_is_listed_and_checked() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    let weather = |name: &str| -> Tool {
+        serde_json::from_value(json!({
+            "name": name,
+            "inputSchema": { "type": "object", "required": [] },
+            "outputSchema": {
+                "type": "object",
+                "properties": { "celsius": { "type": "number" } },
+                "required": ["celsius"]
+            }
+        }))
+        .unwrap()
+    };
+    server
+        .register_tool(weather("weather"), |_| async {
+            Ok(CallToolResult::builder()
+                .text("21 degrees")
+                .structured(json!({ "celsius": 21 }))
+                .build())
+        })
+        .unwrap();
+    server
+        .register_tool(weather("broken"), |_| async {
+            Ok(text_result("21 degrees"))
+        })
+        .unwrap();
+
+    let list = request(1, "tools/list", json!({}));
+    let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+    let tools = response["result"]["tools"].as_array().unwrap();
+    let listed = tools.iter().find(|x| x["name"] == "weather").unwrap();
+    assert_eq!(listed["outputSchema"]["required"], json!(["celsius"]));
+
+    let call = request(2, "tools/call", json!({ "name": "weather" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(
+        response["result"]["structuredContent"],
+        json!({ "celsius": 21 })
+    );
+
+    let call = request(3, "tools/call", json!({ "name": "broken" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+}
+
+#[tokio::test]
+async fn progress_reports_are_coalesced() {
+    let server = ServerBuilder::new("test", "0.1")
+        .progress_interval(Duration::from_secs(1))
+        .build();
+    let session_id = "session".to_string();
+    let mut client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool_with_context(tool("copy"), None, |context, _| async move {
+            for progress in 1..=1000 {
+                context.progress().report(progress, Some(1000)).await?;
+            }
+            Ok(text_result("copied"))
+        })
+        .unwrap();
+
+    let call = request(
+        1,
+        "tools/call",
+        json!({ "name": "copy", "_meta": { "progressToken": "copy" } }),
+    );
+    handle_request(&server, &call, &session_id).await.unwrap();
+
+    let mut sent = Vec::new();
+    while let Ok(message) = client.recv.try_recv() {
+        sent.push(serde_json::to_value(message.sse_message).unwrap());
+    }
+    assert!(sent.len() < 10, "{} notifications sent", sent.len());
+    assert_eq!(sent.last().unwrap()["params"]["progress"], 1000);
+}
+
+#[tokio::test(start_paused = true)]
+async fn timeouts_follow_virtual_time() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_secs(30))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool_with_context(tool("slow"), None, |context, arguments| async move {
+            let secs = arguments.unwrap_or_default()["secs"].as_u64().unwrap();
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            Ok(text_result(&context.elapsed().as_secs().to_string()))
+        })
+        .unwrap();
+
+    let started = std::time::Instant::now();
+
+    let call = request(
+        1,
+        "tools/call",
+        json!({ "name": "slow", "arguments": { "secs": 10 } }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["result"]["content"][0]["text"], "10");
+
+    let call = request(
+        2,
+        "tools/call",
+        json!({ "name": "slow", "arguments": { "secs": 60 } }),
+    );
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test(start_paused = true)]
+async fn context_tools_keep_their_timeout() {
+    let server = ServerBuilder::new("test", "0.1")
+        .request_timeout(Duration::from_secs(30))
+        .build();
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    server
+        .register_tool_with_context(tool("slow"), Some(Duration::from_secs(1)), |_, _| async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(text_result("done"))
+        })
+        .unwrap();
+
+    let call = request(1, "tools/call", json!({ "name": "slow" }));
+    let response = response_json(handle_request(&server, &call, &session_id).await.unwrap());
+    assert_eq!(response["error"]["message"], "Timed out after 1s");
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use super::common::*;
+use crate::mcp::schema::*;
+use crate::mcp::server::{
+    request::handle_request, supported_methods, supported_notifications, Server, ServerBuilder,
+};
+
+/// Asserts `value` parses as `T` and serializes back to exactly the same JSON
+fn assert_round_trip<T: Serialize + DeserializeOwned>(value: Value) {
+    let parsed: T = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(serde_json::to_value(parsed).unwrap(), value);
+}
+
+fn wire(result: ResultEnum) -> Value {
+    serde_json::to_value(Result {
+        base: ResultBase::default(),
+        defined_fields: result,
+    })
+    .unwrap()
+}
+
+#[test]
+fn empty_result_is_empty_object() {
+    assert_eq!(wire(ResultEnum::Empty(EmptyResult::default())), json!({}));
+}
+
+#[test]
+fn empty_meta_is_omitted() {
+    let result = EmptyResult {
+        meta: Some(HashMap::new()),
+        extra: HashMap::new(),
+    };
+    assert_eq!(serde_json::to_value(result).unwrap(), json!({}));
+
+    let result = EmptyResult {
+        meta: Some(HashMap::from([("traceId".to_string(), json!("t1"))])),
+        extra: HashMap::new(),
+    };
+    assert_eq!(
+        serde_json::to_value(result).unwrap(),
+        json!({ "_meta": { "traceId": "t1" } })
+    );
+}
+
+#[test]
+fn empty_result_response() {
+    let response = JSONRPCResponse::Result(JSONRPCResult {
+        id: RequestId::Number(1),
+        json_rpc: JSONRPC_VERSION.to_string(),
+        result: Result {
+            base: ResultBase::default(),
+            defined_fields: ResultEnum::Empty(EmptyResult::default()),
+        },
+    });
+
+    assert_eq!(
+        serde_json::to_value(response).unwrap(),
+        json!({ "jsonrpc": "2.0", "id": 1, "result": {} })
+    );
+}
+
+#[tokio::test]
+async fn initialize_result_has_no_extra_keys() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = server.new_connection(&session_id).unwrap();
+
+    let initialize = initialize_request(0, json!({}));
+    let response = response_json(
+        handle_request(&server, &initialize, &session_id)
+            .await
+            .unwrap(),
+    );
+
+    assert_eq!(
+        response,
+        json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "result": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "serverInfo": { "name": "test", "version": "0.1" }
+            }
+        })
+    );
+}
+
+#[test]
+fn paginated_results() {
+    assert_eq!(
+        wire(ResultEnum::ListTools(ListToolsResult {
+            paginated_base: PaginatedResult { next_cursor: None },
+            tools: vec![tool("build")],
+        })),
+        json!({
+            "tools": [{ "name": "build", "inputSchema": { "type": "object", "required": [] } }]
+        })
+    );
+
+    assert_eq!(
+        wire(ResultEnum::ListTools(ListToolsResult {
+            paginated_base: PaginatedResult {
+                next_cursor: Some("2".to_string())
+            },
+            tools: vec![],
+        })),
+        json!({ "nextCursor": "2", "tools": [] })
+    );
+}
+
+#[test]
+fn call_tool_result() {
+    assert_eq!(
+        wire(ResultEnum::CallTool(text_result("done"))),
+        json!({ "content": [{ "type": "text", "text": "done" }] })
+    );
+
+    let mut result = text_result("failed");
+    result.is_error = Some(true);
+    assert_eq!(
+        wire(ResultEnum::CallTool(result)),
+        json!({ "content": [{ "type": "text", "text": "failed" }], "isError": true })
+    );
+}
+
+#[test]
+fn result_types_round_trip() {
+    assert_round_trip::<ListResourcesResult>(json!({
+        "resources": [{ "uri": "file:///a.txt", "name": "a" }]
+    }));
+    assert_round_trip::<ListResourcesTemplateResult>(json!({
+        "resourceTemplates": [{ "uriTemplate": "file:///{path}", "name": "files" }]
+    }));
+    assert_round_trip::<ReadResourceResult>(json!({
+        "contents": [{ "uri": "file:///a.txt", "mimeType": "text/plain", "text": "hi" }]
+    }));
+    assert_round_trip::<ListPromptsResult>(json!({
+        "prompts": [{ "name": "greet", "arguments": [{ "name": "who", "required": true }] }]
+    }));
+    assert_round_trip::<GetPromptResult>(json!({
+        "messages": [{ "role": "user", "content": { "type": "text", "text": "hi" } }]
+    }));
+    assert_round_trip::<CompleteResult>(json!({ "values": [] }));
+    assert_round_trip::<CreateMessageResult>(json!({
+        "role": "assistant",
+        "content": { "type": "text", "text": "hi" },
+        "model": "m"
+    }));
+    assert_round_trip::<ListRootResult>(json!({ "roots": [{ "uri": "file:///" }] }));
+}
+
+#[test]
+fn read_resource_text_helper() {
+    assert_eq!(
+        wire(ResultEnum::ReadResource(ReadResourceResult::text(
+            "file:///notes.md",
+            Some("text/markdown"),
+            "# Notes"
+        ))),
+        json!({
+            "contents": [{
+                "uri": "file:///notes.md",
+                "mimeType": "text/markdown",
+                "text": "# Notes"
+            }]
+        })
+    );
+}
+
+#[test]
+fn read_resource_blob_helper() {
+    assert_eq!(
+        wire(ResultEnum::ReadResource(ReadResourceResult::blob(
+            "file:///logo.png",
+            None,
+            b"\x89PNG"
+        ))),
+        json!({ "contents": [{ "uri": "file:///logo.png", "blob": "iVBORw==" }] })
+    );
+}
+
+/// Request examples from the 2024-11-05 specification, with the method each has to parse as
+fn spec_requests() -> Vec<(&'static str, Value)> {
+    vec![
+        (
+            "initialize",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": { "roots": { "listChanged": true }, "sampling": {} },
+                    "clientInfo": { "name": "ExampleClient", "version": "1.0.0" }
+                }
+            }),
+        ),
+        (
+            "ping",
+            json!({ "jsonrpc": "2.0", "id": "123", "method": "ping" }),
+        ),
+        (
+            "tools/list",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "tools/list",
+                "params": { "cursor": "optional-cursor-value" }
+            }),
+        ),
+        (
+            "tools/call",
+            json!({
+                "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+                "params": { "name": "get_weather", "arguments": { "location": "New York" } }
+            }),
+        ),
+        (
+            "resources/list",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "resources/list",
+                "params": { "cursor": "optional-cursor-value" }
+            }),
+        ),
+        (
+            "resources/read",
+            json!({
+                "jsonrpc": "2.0", "id": 2, "method": "resources/read",
+                "params": { "uri": "file:///project/src/main.rs" }
+            }),
+        ),
+        (
+            "resources/templates/list",
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "resources/templates/list" }),
+        ),
+        (
+            "resources/subscribe",
+            json!({
+                "jsonrpc": "2.0", "id": 4, "method": "resources/subscribe",
+                "params": { "uri": "file:///project/src/main.rs" }
+            }),
+        ),
+        (
+            "resources/unsubscribe",
+            json!({
+                "jsonrpc": "2.0", "id": 5, "method": "resources/unsubscribe",
+                "params": { "uri": "file:///project/src/main.rs" }
+            }),
+        ),
+        (
+            "prompts/list",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "prompts/list",
+                "params": { "cursor": "optional-cursor-value" }
+            }),
+        ),
+        (
+            "prompts/get",
+            json!({
+                "jsonrpc": "2.0", "id": 2, "method": "prompts/get",
+                "params": {
+                    "name": "code_review",
+                    "arguments": { "code": "def hello():\n    print('world')" }
+                }
+            }),
+        ),
+        (
+            "logging/setLevel",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "logging/setLevel",
+                "params": { "level": "info" }
+            }),
+        ),
+        (
+            "completion/complete",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "completion/complete",
+                "params": {
+                    "ref": { "type": "ref/prompt", "name": "code_review" },
+                    "argument": { "name": "language", "value": "py" }
+                }
+            }),
+        ),
+        (
+            "roots/list",
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "roots/list" }),
+        ),
+        (
+            "sampling/createMessage",
+            json!({
+                "jsonrpc": "2.0", "id": 1, "method": "sampling/createMessage",
+                "params": {
+                    "messages": [{
+                        "role": "user",
+                        "content": { "type": "text", "text": "What is the capital of France?" }
+                    }],
+                    "modelPreferences": {
+                        "hints": [{ "name": "claude-3-sonnet" }],
+                        "intelligencePriority": 0.8,
+                        "speedPriority": 0.5,
+                        "costPriority": 0.3
+                    },
+                    "systemPrompt": "You are a helpful assistant.",
+                    "maxTokens": 100
+                }
+            }),
+        ),
+    ]
+}
+
+#[test]
+fn spec_requests_parse_as_their_method() {
+    for (method, value) in spec_requests() {
+        let request: JSONRPCRequest = serde_json::from_value(value.clone())
+            .unwrap_or_else(|err| panic!("{method} does not parse: {err}"));
+        assert!(
+            !matches!(request.params, RequestParams::Custom(_)),
+            "{method} parsed as a custom method"
+        );
+        assert_eq!(request.params.method(), method);
+
+        let message: JSONRPCMessage = serde_json::from_value(value).unwrap();
+        assert!(matches!(message, JSONRPCMessage::Request(_)), "{method}");
+    }
+}
+
+#[tokio::test]
+async fn request_ids_are_echoed_exactly() {
+    let server = Server::new("test", "0.1", 0);
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    for id in [
+        json!(123),
+        json!("123"),
+        json!(i64::MIN),
+        json!(u64::MAX),
+        json!("not-a-number"),
+    ] {
+        let list: JSONRPCRequest =
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": id, "method": "tools/list" }))
+                .unwrap();
+        let response = response_json(handle_request(&server, &list, &session_id).await.unwrap());
+        assert_eq!(response["id"], id);
+    }
+
+    let fractional = json!({ "jsonrpc": "2.0", "id": 1.5, "method": "tools/list" });
+    assert!(serde_json::from_value::<JSONRPCRequest>(fractional).is_err());
+}
+
+#[tokio::test]
+async fn supported_methods_are_answered() {
+    assert!(supported_methods().contains(&"initialize"));
+    assert!(!supported_methods().contains(&"sampling/createMessage"));
+    assert!(supported_notifications().contains(&"notifications/cancelled"));
+    assert!(!supported_notifications().contains(&"notifications/roots/list_changed"));
+
+    // Subscriptions are the only opt-in methods
+    let server = Arc::new(
+        ServerBuilder::new("test", "0.1")
+            .resource_subscriptions(true)
+            .build(),
+    );
+    let session_id = "session".to_string();
+    let _client = initialized_session(&server, &session_id).await;
+
+    for (method, value) in spec_requests() {
+        if !supported_methods().contains(&method) {
+            continue;
+        }
+
+        let request: JSONRPCMessage = serde_json::from_value(value).unwrap();
+        let response = response_json(
+            server
+                .dispatch_message(&session_id, request)
+                .await
+                .unwrap()
+                .unwrap(),
+        );
+        // Errors about the spec example's tool or prompt not existing are fine
+        assert_ne!(
+            response["error"]["message"],
+            format!("Unknown method: {method}")
+        );
+    }
+}